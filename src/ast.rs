@@ -1,44 +1,956 @@
 use crate::token;
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::ops::{Index, IndexMut};
 
+mod display;
+pub mod expand;
+mod serialize;
+pub mod visit;
+
+/// Index of a node in the arena of its [`Program`]
+///
+/// Ids are handed out in the order the nodes are allocated, passes can key
+/// side tables on them or index a `Vec` with [`NodeId::index`]
+pub struct NodeId<T> {
+    index: u32,
+    node: PhantomData<fn() -> T>,
+}
+
+pub type StmtId = NodeId<Statement>;
+pub type ExprId = NodeId<Expression>;
+
+impl<T> NodeId<T> {
+    fn new(index: usize) -> Self {
+        Self {
+            index: u32::try_from(index).expect("too many nodes in the program"),
+            node: PhantomData,
+        }
+    }
+    pub fn index(self) -> usize {
+        self.index as usize
+    }
+}
+
+impl<T> Clone for NodeId<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for NodeId<T> {}
+
+impl<T> PartialEq for NodeId<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for NodeId<T> {}
+
+impl<T> PartialOrd for NodeId<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for NodeId<T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.index.cmp(&other.index)
+    }
+}
+
+impl<T> Hash for NodeId<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state)
+    }
+}
+
+impl<T> Debug for NodeId<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.index)
+    }
+}
+
+/// The top level statements of a program, and the arena owning every node
 #[derive(Default, Debug)]
 pub struct Program {
-    pub statements: Vec<Statement>,
+    pub statements: Vec<StmtId>,
+    stmts: Vec<Statement>,
+    exprs: Vec<Expression>,
+    /// Values of the `const` statements, evaluated by the parser
+    const_values: HashMap<StmtId, Literal>,
+    /// Spans of the whole source of the nodes, recorded by the parser
+    statement_spans: HashMap<StmtId, token::Span>,
+    expression_spans: HashMap<ExprId, token::Span>,
+}
+
+impl Program {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn alloc_statement(&mut self, stmt: Statement) -> StmtId {
+        self.stmts.push(stmt);
+        StmtId::new(self.stmts.len() - 1)
+    }
+    pub fn alloc_expression(&mut self, expr: Expression) -> ExprId {
+        self.exprs.push(expr);
+        ExprId::new(self.exprs.len() - 1)
+    }
+    /// Number of statements allocated, nested ones included
+    pub fn statement_count(&self) -> usize {
+        self.stmts.len()
+    }
+    /// Number of expressions allocated, nested ones included
+    pub fn expression_count(&self) -> usize {
+        self.exprs.len()
+    }
+    /// The value the initializer of the `const` statement `id` evaluates to
+    pub fn const_value(&self, id: StmtId) -> Option<&Literal> {
+        self.const_values.get(&id)
+    }
+    pub fn set_const_value(&mut self, id: StmtId, value: Literal) {
+        self.const_values.insert(id, value);
+    }
+    /// Span of the whole source of the statement `id`, that of its first
+    /// token if none was recorded
+    pub fn statement_span(&self, id: StmtId) -> token::Span {
+        self.statement_spans
+            .get(&id)
+            .copied()
+            .unwrap_or_else(|| self[id].token().span())
+    }
+    pub fn set_statement_span(&mut self, id: StmtId, span: token::Span) {
+        self.statement_spans.insert(id, span);
+    }
+    /// Span of the whole source of the expression `id`, that of its token if
+    /// none was recorded
+    pub fn expression_span(&self, id: ExprId) -> token::Span {
+        self.expression_spans
+            .get(&id)
+            .copied()
+            .unwrap_or_else(|| self[id].token().span())
+    }
+    pub fn set_expression_span(&mut self, id: ExprId, span: token::Span) {
+        self.expression_spans.insert(id, span);
+    }
+    /// The import statements at the top level, the only place they can be
+    pub fn imports(&self) -> impl Iterator<Item = &ImportStatement> {
+        self.statements.iter().filter_map(|id| match &self[*id] {
+            Statement::Import(stmt) => Some(stmt),
+            _ => None,
+        })
+    }
+    /// The names declared by the exported statements
+    pub fn exports(&self) -> Vec<&Identifier> {
+        let mut names = Vec::new();
+        for id in &self.statements {
+            let Statement::Export(stmt) = &self[*id] else {
+                continue;
+            };
+            match &self[stmt.declaration] {
+                Statement::Let(stmt) => names.extend(stmt.pattern.bindings()),
+                Statement::Const(stmt) => names.push(&stmt.name),
+                Statement::Struct(stmt) => names.push(&stmt.name),
+                Statement::Enum(stmt) => names.push(&stmt.name),
+                _ => {}
+            }
+        }
+        names
+    }
+}
+
+impl Index<StmtId> for Program {
+    type Output = Statement;
+
+    fn index(&self, id: StmtId) -> &Statement {
+        &self.stmts[id.index()]
+    }
+}
+
+impl IndexMut<StmtId> for Program {
+    fn index_mut(&mut self, id: StmtId) -> &mut Statement {
+        &mut self.stmts[id.index()]
+    }
+}
+
+impl Index<ExprId> for Program {
+    type Output = Expression;
+
+    fn index(&self, id: ExprId) -> &Expression {
+        &self.exprs[id.index()]
+    }
+}
+
+impl IndexMut<ExprId> for Program {
+    fn index_mut(&mut self, id: ExprId) -> &mut Expression {
+        &mut self.exprs[id.index()]
+    }
 }
 
 #[derive(Debug)]
 pub enum Statement {
-    Let(Box<LetStatement>),
+    Let(LetStatement),
+    Const(ConstStatement),
+    Return(ReturnStatement),
+    Expression(ExpressionStatement),
+    Break(BreakStatement),
+    Continue(ContinueStatement),
+    Struct(StructStatement),
+    Enum(EnumStatement),
+    Import(ImportStatement),
+    Export(ExportStatement),
 }
 
 impl Statement {
+    /// Whether the statement is `let mut x = ...`
     pub fn is_mut(&self) -> bool {
+        matches!(
+            self,
+            Statement::Let(LetStatement {
+                pattern: Pattern::Binding { is_mut: true, .. },
+                ..
+            })
+        )
+    }
+    /// The first token of the statement
+    pub fn token(&self) -> &token::Token {
         match self {
-            Statement::Let(let_statement) => let_statement.is_mut,
+            Statement::Let(stmt) => &stmt.token,
+            Statement::Const(stmt) => &stmt.token,
+            Statement::Return(stmt) => &stmt.token,
+            Statement::Expression(stmt) => &stmt.token,
+            Statement::Break(stmt) => &stmt.token,
+            Statement::Continue(stmt) => &stmt.token,
+            Statement::Struct(stmt) => &stmt.token,
+            Statement::Enum(stmt) => &stmt.token,
+            Statement::Import(stmt) => &stmt.token,
+            Statement::Export(stmt) => &stmt.token,
         }
     }
 }
 
-impl Program {
-    pub fn new() -> Self {
-        Self::default()
+#[derive(Debug)]
+pub struct LetStatement {
+    token: token::Token,
+    pattern: Pattern,
+    annotation: Option<TypeAnnotation>,
+    value: ExprId,
+}
+
+impl LetStatement {
+    pub fn new(
+        token: token::Token,
+        pattern: Pattern,
+        annotation: Option<TypeAnnotation>,
+        value: ExprId,
+    ) -> Self {
+        Self {
+            token,
+            pattern,
+            annotation,
+            value,
+        }
+    }
+    /// `x` in `let x = 1;`, or `[a, ...rest]` when the value is destructured
+    pub fn pattern(&self) -> &Pattern {
+        &self.pattern
+    }
+    /// `int` in `let x: int = 1;`
+    pub fn annotation(&self) -> Option<&TypeAnnotation> {
+        self.annotation.as_ref()
+    }
+    pub fn value(&self) -> ExprId {
+        self.value
     }
 }
 
+/// `const MAX = 60 * 60;`, the value is evaluated before the program runs
+/// and the name can't be assigned to
 #[derive(Debug)]
-pub struct LetStatement {
+pub struct ConstStatement {
     token: token::Token,
     name: Identifier,
-    is_mut: bool,
-    // value: Box<dyn Expression>,
+    annotation: Option<TypeAnnotation>,
+    value: ExprId,
 }
 
-impl LetStatement {
-    pub fn new(token: token::Token, name: Identifier, is_mut: bool) -> Self {
+impl ConstStatement {
+    pub fn new(
+        token: token::Token,
+        name: Identifier,
+        annotation: Option<TypeAnnotation>,
+        value: ExprId,
+    ) -> Self {
+        Self {
+            token,
+            name,
+            annotation,
+            value,
+        }
+    }
+    pub fn name(&self) -> &Identifier {
+        &self.name
+    }
+    pub fn annotation(&self) -> Option<&TypeAnnotation> {
+        self.annotation.as_ref()
+    }
+    pub fn value(&self) -> ExprId {
+        self.value
+    }
+}
+
+#[derive(Debug)]
+pub struct ReturnStatement {
+    token: token::Token,
+    value: ExprId,
+}
+
+impl ReturnStatement {
+    pub fn new(token: token::Token, value: ExprId) -> Self {
+        Self { token, value }
+    }
+    pub fn value(&self) -> ExprId {
+        self.value
+    }
+}
+
+/// A statement consisting of a single expression, e.g. `x + y;`
+#[derive(Debug)]
+pub struct ExpressionStatement {
+    token: token::Token,
+    expression: ExprId,
+}
+
+impl ExpressionStatement {
+    pub fn new(token: token::Token, expression: ExprId) -> Self {
+        Self { token, expression }
+    }
+    pub fn expression(&self) -> ExprId {
+        self.expression
+    }
+}
+
+/// `break` or `break value`, only a `loop` can be left with a value
+#[derive(Debug)]
+pub struct BreakStatement {
+    token: token::Token,
+    value: Option<ExprId>,
+}
+
+impl BreakStatement {
+    pub fn new(token: token::Token, value: Option<ExprId>) -> Self {
+        Self { token, value }
+    }
+    pub fn value(&self) -> Option<ExprId> {
+        self.value
+    }
+}
+
+#[derive(Debug)]
+pub struct ContinueStatement {
+    token: token::Token,
+}
+
+impl ContinueStatement {
+    pub fn new(token: token::Token) -> Self {
+        Self { token }
+    }
+}
+
+/// `struct Point { x, y }`
+#[derive(Debug)]
+pub struct StructStatement {
+    token: token::Token,
+    name: Identifier,
+    fields: Vec<Identifier>,
+}
+
+impl StructStatement {
+    pub fn new(token: token::Token, name: Identifier, fields: Vec<Identifier>) -> Self {
         Self {
             token,
             name,
-            is_mut,
+            fields,
+        }
+    }
+    pub fn name(&self) -> &Identifier {
+        &self.name
+    }
+    pub fn fields(&self) -> &[Identifier] {
+        &self.fields
+    }
+}
+
+/// `import "lib/math.pab" as math;` or `import { add, sub } from "./util.pab";`,
+/// the path is relative to the importing file
+#[derive(Debug)]
+pub struct ImportStatement {
+    token: token::Token,
+    path_token: token::Token,
+    path: String,
+    items: ImportItems,
+}
+
+impl ImportStatement {
+    pub fn new(
+        token: token::Token,
+        path_token: token::Token,
+        path: String,
+        items: ImportItems,
+    ) -> Self {
+        Self {
+            token,
+            path_token,
+            path,
+            items,
+        }
+    }
+    /// The string literal of the path
+    pub fn path_token(&self) -> &token::Token {
+        &self.path_token
+    }
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+    pub fn items(&self) -> &ImportItems {
+        &self.items
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportItems {
+    /// `as math`, the module is bound to the name
+    Module(Identifier),
+    /// `{ add, sub }`, the exports are bound to their names
+    Names(Vec<Identifier>),
+}
+
+/// `export let x = 1;`, makes the names declared by the statement importable
+#[derive(Debug)]
+pub struct ExportStatement {
+    token: token::Token,
+    declaration: StmtId,
+}
+
+impl ExportStatement {
+    pub fn new(token: token::Token, declaration: StmtId) -> Self {
+        Self { token, declaration }
+    }
+    pub fn declaration(&self) -> StmtId {
+        self.declaration
+    }
+}
+
+/// `enum Shape { Circle(r), Rect(w, h), Empty }`
+#[derive(Debug)]
+pub struct EnumStatement {
+    token: token::Token,
+    name: Identifier,
+    variants: Vec<Variant>,
+}
+
+impl EnumStatement {
+    pub fn new(token: token::Token, name: Identifier, variants: Vec<Variant>) -> Self {
+        Self {
+            token,
+            name,
+            variants,
+        }
+    }
+    pub fn name(&self) -> &Identifier {
+        &self.name
+    }
+    pub fn variants(&self) -> &[Variant] {
+        &self.variants
+    }
+}
+
+/// A variant of an enum and the names of its fields, in order
+#[derive(Debug)]
+pub struct Variant {
+    name: Identifier,
+    fields: Vec<Identifier>,
+}
+
+impl Variant {
+    pub fn new(name: Identifier, fields: Vec<Identifier>) -> Self {
+        Self { name, fields }
+    }
+    pub fn name(&self) -> &Identifier {
+        &self.name
+    }
+    pub fn fields(&self) -> &[Identifier] {
+        &self.fields
+    }
+}
+
+/// A list of statements delimited by braces
+#[derive(Debug)]
+pub struct BlockStatement {
+    token: token::Token,
+    statements: Vec<StmtId>,
+    /// Offset of the end of the closing brace, or of the expression standing
+    /// for the block
+    end: usize,
+}
+
+impl BlockStatement {
+    pub fn new(token: token::Token, statements: Vec<StmtId>, end: usize) -> Self {
+        Self {
+            token,
+            statements,
+            end,
+        }
+    }
+    pub fn token(&self) -> &token::Token {
+        &self.token
+    }
+    /// Span of the whole block, from its first token
+    pub fn span(&self) -> token::Span {
+        token::Span {
+            end: self.end,
+            ..self.token.span()
+        }
+    }
+    pub fn statements(&self) -> &[StmtId] {
+        &self.statements
+    }
+}
+
+#[derive(Debug)]
+pub enum Expression {
+    Identifier(Identifier),
+    Integer {
+        token: token::Token,
+        value: i64,
+    },
+    Float {
+        token: token::Token,
+        value: f64,
+    },
+    Boolean {
+        token: token::Token,
+        value: bool,
+    },
+    String {
+        token: token::Token,
+        value: String,
+    },
+    /// `token` is the operator, e.g. `!` in `!ok`
+    Prefix {
+        token: token::Token,
+        right: ExprId,
+    },
+    /// `token` is the operator, e.g. `+` in `x + y`
+    Infix {
+        token: token::Token,
+        left: ExprId,
+        right: ExprId,
+    },
+    /// `token` is the `=` sign
+    Assign {
+        token: token::Token,
+        target: ExprId,
+        value: ExprId,
+    },
+    If {
+        token: token::Token,
+        condition: ExprId,
+        consequence: BlockStatement,
+        alternative: Option<BlockStatement>,
+    },
+    While {
+        token: token::Token,
+        condition: ExprId,
+        body: BlockStatement,
+    },
+    /// `for variable in iterable { ... }`
+    For {
+        token: token::Token,
+        variable: Identifier,
+        iterable: ExprId,
+        body: BlockStatement,
+    },
+    /// Runs until a `break`, whose value is the value of the loop
+    Loop {
+        token: token::Token,
+        body: BlockStatement,
+    },
+    /// `token` is the `match` keyword, the arms are tried in order
+    Match {
+        token: token::Token,
+        subject: ExprId,
+        arms: Vec<MatchArm>,
+    },
+    /// `return_type` is `float` in `fn(x) -> float { ... }`
+    Function {
+        token: token::Token,
+        params: Vec<Parameter>,
+        return_type: Option<TypeAnnotation>,
+        body: BlockStatement,
+    },
+    /// `macro(params) { body }`, only bound by a top-level `let`, see
+    /// [`expand`]
+    Macro {
+        token: token::Token,
+        params: Vec<Parameter>,
+        body: BlockStatement,
+    },
+    /// `token` is the opening parenthesis of the argument list, the named
+    /// arguments `f(x: 1)` follow the positional ones
+    Call {
+        token: token::Token,
+        callee: ExprId,
+        args: Vec<ExprId>,
+        named: Vec<(Identifier, ExprId)>,
+    },
+    /// `...xs` in the arguments of a call, passes the elements of `xs` as
+    /// arguments
+    Spread {
+        token: token::Token,
+        value: ExprId,
+    },
+    Array {
+        token: token::Token,
+        elements: Vec<ExprId>,
+    },
+    Hash {
+        token: token::Token,
+        pairs: Vec<(ExprId, ExprId)>,
+    },
+    /// `token` is the opening bracket, e.g. `[` in `arr[i]`
+    Index {
+        token: token::Token,
+        left: ExprId,
+        index: ExprId,
+    },
+    /// `Point { x: 1, y: 2 }`, `token` is the opening brace
+    Struct {
+        token: token::Token,
+        name: Identifier,
+        fields: Vec<(Identifier, ExprId)>,
+    },
+    /// `token` is the dot, e.g. `.` in `p.x`
+    Field {
+        token: token::Token,
+        object: ExprId,
+        field: Identifier,
+    },
+    /// `start..end`, or `start..=end` including the end, `token` is the
+    /// operator. Both bounds are optional, `..` alone slices everything
+    Range {
+        token: token::Token,
+        start: Option<ExprId>,
+        end: Option<ExprId>,
+    },
+    /// `Shape::Circle(1)` or `Shape::Empty`, `token` is the `::`
+    Variant {
+        token: token::Token,
+        enum_name: Identifier,
+        variant: Identifier,
+        args: Vec<ExprId>,
+    },
+}
+
+impl Expression {
+    /// The token the expression was built from
+    pub fn token(&self) -> &token::Token {
+        match self {
+            Expression::Identifier(ident) => &ident.token,
+            Expression::Integer { token, .. }
+            | Expression::Float { token, .. }
+            | Expression::Boolean { token, .. }
+            | Expression::String { token, .. }
+            | Expression::Prefix { token, .. }
+            | Expression::Infix { token, .. }
+            | Expression::Assign { token, .. }
+            | Expression::If { token, .. }
+            | Expression::While { token, .. }
+            | Expression::For { token, .. }
+            | Expression::Loop { token, .. }
+            | Expression::Match { token, .. }
+            | Expression::Function { token, .. }
+            | Expression::Macro { token, .. }
+            | Expression::Call { token, .. }
+            | Expression::Spread { token, .. }
+            | Expression::Array { token, .. }
+            | Expression::Hash { token, .. }
+            | Expression::Index { token, .. }
+            | Expression::Struct { token, .. }
+            | Expression::Field { token, .. }
+            | Expression::Variant { token, .. }
+            | Expression::Range { token, .. } => token,
+        }
+    }
+}
+
+/// A function parameter, `x`, `x: int`, `x = 10` or the rest parameter
+/// `...xs` collecting the arguments left
+#[derive(Debug)]
+pub struct Parameter {
+    name: Identifier,
+    annotation: Option<TypeAnnotation>,
+    default: Option<ExprId>,
+    is_rest: bool,
+}
+
+impl Parameter {
+    pub fn new(
+        name: Identifier,
+        annotation: Option<TypeAnnotation>,
+        default: Option<ExprId>,
+        is_rest: bool,
+    ) -> Self {
+        Self {
+            name,
+            annotation,
+            default,
+            is_rest,
+        }
+    }
+    pub fn name(&self) -> &Identifier {
+        &self.name
+    }
+    pub fn annotation(&self) -> Option<&TypeAnnotation> {
+        self.annotation.as_ref()
+    }
+    /// The value of the parameter when no argument is given for it
+    pub fn default(&self) -> Option<ExprId> {
+        self.default
+    }
+    pub fn is_rest(&self) -> bool {
+        self.is_rest
+    }
+}
+
+/// `int` or `array<int>`, recorded by the parser but not checked
+#[derive(Debug)]
+pub struct TypeAnnotation {
+    name: Identifier,
+    args: Vec<TypeAnnotation>,
+}
+
+impl TypeAnnotation {
+    pub fn new(name: Identifier, args: Vec<TypeAnnotation>) -> Self {
+        Self { name, args }
+    }
+    pub fn name(&self) -> &Identifier {
+        &self.name
+    }
+    /// `int` in `array<int>`
+    pub fn args(&self) -> &[TypeAnnotation] {
+        &self.args
+    }
+}
+
+/// `pattern if guard => body`
+#[derive(Debug)]
+pub struct MatchArm {
+    pattern: Pattern,
+    guard: Option<ExprId>,
+    body: ExprId,
+}
+
+impl MatchArm {
+    pub fn new(pattern: Pattern, guard: Option<ExprId>, body: ExprId) -> Self {
+        Self {
+            pattern,
+            guard,
+            body,
+        }
+    }
+    pub fn pattern(&self) -> &Pattern {
+        &self.pattern
+    }
+    pub fn guard(&self) -> Option<ExprId> {
+        self.guard
+    }
+    pub fn body(&self) -> ExprId {
+        self.body
+    }
+}
+
+#[derive(Debug)]
+pub enum Pattern {
+    /// `_`, matches anything without binding it
+    Wildcard(token::Token),
+    /// Matches anything and binds it to the name, `mut x` binds a mutable
+    /// variable
+    Binding { name: Identifier, is_mut: bool },
+    /// `token` is the first token, the `-` of a negative number
+    Literal { token: token::Token, value: Literal },
+    /// `[a, b]`, matches the arrays of that length, or of at least that
+    /// length less the rest with `[a, ...rest, b]`
+    Array {
+        token: token::Token,
+        elements: Vec<Pattern>,
+    },
+    /// `...rest` in an array pattern, binds the elements the other patterns
+    /// leave, `...` alone ignores them
+    Rest {
+        token: token::Token,
+        name: Option<Identifier>,
+        is_mut: bool,
+    },
+    /// `Point { x, y: 0 }`, `x` alone binds the field to `x`, the fields
+    /// left out match anything. `{ x, y }` without a name matches any value
+    /// with the fields, `token` is the brace
+    Struct {
+        token: token::Token,
+        name: Option<Identifier>,
+        fields: Vec<(Identifier, Pattern)>,
+    },
+    /// `Shape::Rect(w, _)`, matches the values built with the variant
+    Variant {
+        enum_name: Identifier,
+        variant: Identifier,
+        fields: Vec<Pattern>,
+    },
+}
+
+impl Pattern {
+    pub fn token(&self) -> &token::Token {
+        match self {
+            Pattern::Wildcard(token)
+            | Pattern::Literal { token, .. }
+            | Pattern::Array { token, .. }
+            | Pattern::Rest { token, .. }
+            | Pattern::Struct {
+                name: None, token, ..
+            } => token,
+            Pattern::Binding { name: ident, .. }
+            | Pattern::Struct {
+                name: Some(ident), ..
+            }
+            | Pattern::Variant {
+                enum_name: ident, ..
+            } => &ident.token,
+        }
+    }
+    /// Whether the pattern matches every value
+    pub fn is_irrefutable(&self) -> bool {
+        matches!(self, Pattern::Wildcard(_) | Pattern::Binding { .. })
+    }
+    /// The names bound by the pattern, in source order
+    pub fn bindings(&self) -> Vec<&Identifier> {
+        let mut bindings = Vec::new();
+        self.collect_bindings(&mut bindings);
+        bindings
+    }
+    fn collect_bindings<'a>(&'a self, bindings: &mut Vec<&'a Identifier>) {
+        match self {
+            Pattern::Wildcard(_) | Pattern::Literal { .. } => {}
+            Pattern::Binding { name, .. } => bindings.push(name),
+            Pattern::Rest { name, .. } => bindings.extend(name),
+            Pattern::Array { elements, .. }
+            | Pattern::Variant {
+                fields: elements, ..
+            } => {
+                for element in elements {
+                    element.collect_bindings(bindings);
+                }
+            }
+            Pattern::Struct { fields, .. } => {
+                for (_, pattern) in fields {
+                    pattern.collect_bindings(bindings);
+                }
+            }
+        }
+    }
+}
+
+/// The value of a literal pattern, or a value computed before the program
+/// runs
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    String(String),
+}
+
+impl Literal {
+    /// Name of the type of the value, as written in annotations
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Literal::Integer(_) => "int",
+            Literal::Float(_) => "float",
+            Literal::Boolean(_) => "bool",
+            Literal::String(_) => "string",
+        }
+    }
+    /// Apply `-` or `!`, the error is the reason it can't be applied
+    pub fn prefix(operator: &token::TokenType, right: Literal) -> Result<Literal, String> {
+        use token::TokenType;
+        match (operator, right) {
+            (TokenType::Minus, Literal::Integer(value)) => value
+                .checked_neg()
+                .map(Literal::Integer)
+                .ok_or_else(|| String::from("the negation overflows")),
+            (TokenType::Minus, Literal::Float(value)) => Ok(Literal::Float(-value)),
+            (TokenType::Bang, Literal::Boolean(value)) => Ok(Literal::Boolean(!value)),
+            (operator, right) => Err(format!(
+                "'{}' can't be applied to {}",
+                operator,
+                right.type_name()
+            )),
+        }
+    }
+    /// Apply an arithmetic or comparison operator, integer arithmetic is
+    /// checked
+    pub fn infix(
+        operator: &token::TokenType,
+        left: Literal,
+        right: Literal,
+    ) -> Result<Literal, String> {
+        use token::TokenType;
+        let overflow = || String::from("the result overflows");
+        match (operator, left, right) {
+            (TokenType::Slash, Literal::Integer(_), Literal::Integer(0)) => {
+                Err(String::from("division by zero"))
+            }
+            (TokenType::Plus, Literal::Integer(a), Literal::Integer(b)) => {
+                a.checked_add(b).map(Literal::Integer).ok_or_else(overflow)
+            }
+            (TokenType::Minus, Literal::Integer(a), Literal::Integer(b)) => {
+                a.checked_sub(b).map(Literal::Integer).ok_or_else(overflow)
+            }
+            (TokenType::Asterisk, Literal::Integer(a), Literal::Integer(b)) => {
+                a.checked_mul(b).map(Literal::Integer).ok_or_else(overflow)
+            }
+            (TokenType::Slash, Literal::Integer(a), Literal::Integer(b)) => {
+                a.checked_div(b).map(Literal::Integer).ok_or_else(overflow)
+            }
+            (TokenType::LT, Literal::Integer(a), Literal::Integer(b)) => {
+                Ok(Literal::Boolean(a < b))
+            }
+            (TokenType::GT, Literal::Integer(a), Literal::Integer(b)) => {
+                Ok(Literal::Boolean(a > b))
+            }
+            (TokenType::Plus, Literal::Float(a), Literal::Float(b)) => Ok(Literal::Float(a + b)),
+            (TokenType::Minus, Literal::Float(a), Literal::Float(b)) => Ok(Literal::Float(a - b)),
+            (TokenType::Asterisk, Literal::Float(a), Literal::Float(b)) => {
+                Ok(Literal::Float(a * b))
+            }
+            (TokenType::Slash, Literal::Float(a), Literal::Float(b)) => Ok(Literal::Float(a / b)),
+            (TokenType::LT, Literal::Float(a), Literal::Float(b)) => Ok(Literal::Boolean(a < b)),
+            (TokenType::GT, Literal::Float(a), Literal::Float(b)) => Ok(Literal::Boolean(a > b)),
+            (TokenType::Plus, Literal::String(a), Literal::String(b)) => {
+                Ok(Literal::String(a + &b))
+            }
+            (TokenType::EQ, a, b) if a.type_name() == b.type_name() => Ok(Literal::Boolean(a == b)),
+            (TokenType::NotEQ, a, b) if a.type_name() == b.type_name() => {
+                Ok(Literal::Boolean(a != b))
+            }
+            (operator, a, b) => Err(format!(
+                "'{}' can't be applied to {} and {}",
+                operator,
+                a.type_name(),
+                b.type_name()
+            )),
         }
     }
 }
@@ -46,12 +958,27 @@ impl LetStatement {
 #[derive(Debug)]
 pub struct Identifier {
     token: token::Token,
+    value: String,
     start: usize,
     end: usize,
 }
 
 impl Identifier {
-    pub fn new(token: token::Token, start: usize, end: usize) -> Self {
-        Self { token, start, end }
+    pub fn new(token: token::Token, value: String, start: usize, end: usize) -> Self {
+        Self {
+            token,
+            value,
+            start,
+            end,
+        }
+    }
+    pub fn token(&self) -> &token::Token {
+        &self.token
+    }
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+    pub fn span(&self) -> (usize, usize) {
+        (self.start, self.end)
     }
 }
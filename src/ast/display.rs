@@ -0,0 +1,968 @@
+//! Printing of the AST as canonical `.pab` source
+//!
+//! `{}` only adds the parentheses needed to keep the structure of the tree,
+//! `{:#}` wraps every operator in parentheses which makes the structure
+//! visible at a glance.
+
+use super::{
+    BlockStatement, ExprId, Expression, Identifier, ImportItems, Literal, Parameter, Pattern,
+    Program, Statement, StmtId, TypeAnnotation, Variant,
+};
+use crate::parser::Precedence;
+use std::fmt::{self, Display, Formatter, Write};
+
+const INDENT: &str = "    ";
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut printer = Printer::new(f, self);
+        for (i, stmt) in self.statements.iter().enumerate() {
+            if i > 0 {
+                printer.newline()?;
+            }
+            printer.statement(*stmt)?;
+        }
+        Ok(())
+    }
+}
+
+impl Program {
+    /// Source of a single statement of the program
+    pub fn display_statement(&self, id: StmtId) -> impl Display + '_ {
+        DisplayNode {
+            program: self,
+            node: Node::Statement(id),
+        }
+    }
+    /// Source of a block of the program, indented from its braces
+    pub fn display_block<'a>(&'a self, block: &'a BlockStatement) -> impl Display + 'a {
+        DisplayNode {
+            program: self,
+            node: Node::Block(block),
+        }
+    }
+    /// Source of a single expression of the program
+    pub fn display_expression(&self, id: ExprId) -> impl Display + '_ {
+        DisplayNode {
+            program: self,
+            node: Node::Expression(id),
+        }
+    }
+}
+
+enum Node<'a> {
+    Statement(StmtId),
+    Block(&'a BlockStatement),
+    Expression(ExprId),
+}
+
+struct DisplayNode<'a> {
+    program: &'a Program,
+    node: Node<'a>,
+}
+
+impl Display for DisplayNode<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut printer = Printer::new(f, self.program);
+        match self.node {
+            Node::Statement(id) => printer.statement(id),
+            Node::Block(block) => printer.block(block),
+            Node::Expression(id) => printer.expression(id),
+        }
+    }
+}
+
+impl Display for Identifier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+impl Display for TypeAnnotation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if self.args.is_empty() {
+            return Ok(());
+        }
+        f.write_char('<')?;
+        for (i, arg) in self.args.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", arg)?;
+        }
+        f.write_char('>')
+    }
+}
+
+/// The default value is printed along with the function, see `Printer::params`
+impl Display for Parameter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.is_rest {
+            f.write_str("...")?;
+        }
+        write!(f, "{}", self.name)?;
+        match &self.annotation {
+            Some(annotation) => write!(f, ": {}", annotation),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Wildcard(_) => f.write_char('_'),
+            Pattern::Binding { name, is_mut } => {
+                if *is_mut {
+                    f.write_str("mut ")?;
+                }
+                write!(f, "{}", name)
+            }
+            Pattern::Rest { name, is_mut, .. } => {
+                f.write_str("...")?;
+                if *is_mut {
+                    f.write_str("mut ")?;
+                }
+                match name {
+                    Some(name) => write!(f, "{}", name),
+                    None => Ok(()),
+                }
+            }
+            Pattern::Literal { value, .. } => write!(f, "{}", value),
+            Pattern::Array { elements, .. } => {
+                f.write_char('[')?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                f.write_char(']')
+            }
+            Pattern::Struct { name, fields, .. } => {
+                if let Some(name) = name {
+                    write!(f, "{} ", name)?;
+                }
+                if fields.is_empty() {
+                    return f.write_str("{}");
+                }
+                f.write_str("{ ")?;
+                for (i, (field, pattern)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    match pattern {
+                        Pattern::Binding { name, .. } if name.value == field.value => {
+                            write!(f, "{}", pattern)?
+                        }
+                        _ => write!(f, "{}: {}", field, pattern)?,
+                    }
+                }
+                f.write_str(" }")
+            }
+            Pattern::Variant {
+                enum_name,
+                variant,
+                fields,
+            } => {
+                write!(f, "{}::{}", enum_name, variant)?;
+                if fields.is_empty() {
+                    return Ok(());
+                }
+                f.write_char('(')?;
+                for (i, field) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", field)?;
+                }
+                f.write_char(')')
+            }
+        }
+    }
+}
+
+impl Display for Variant {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if self.fields.is_empty() {
+            return Ok(());
+        }
+        f.write_char('(')?;
+        for (i, field) in self.fields.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", field)?;
+        }
+        f.write_char(')')
+    }
+}
+
+impl Display for Literal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Integer(value) => write!(f, "{}", value),
+            Literal::Float(value) => write_float(f, *value),
+            Literal::Boolean(value) => write!(f, "{}", value),
+            Literal::String(value) => write!(f, "\"{}\"", value),
+        }
+    }
+}
+
+/// `Display` of `f64` never uses an exponent, which the lexer can't read,
+/// but leaves out the fraction of round numbers
+fn write_float(f: &mut Formatter<'_>, value: f64) -> fmt::Result {
+    let literal = value.to_string();
+    f.write_str(&literal)?;
+    if !literal.contains('.') {
+        f.write_str(".0")?;
+    }
+    Ok(())
+}
+
+/// Writes nodes to a formatter, keeping track of the block indentation
+struct Printer<'a, 'b> {
+    f: &'a mut Formatter<'b>,
+    program: &'a Program,
+    indent: usize,
+    /// Parenthesize every operator, set by the `#` flag
+    explicit: bool,
+}
+
+impl<'a, 'b> Printer<'a, 'b> {
+    fn new(f: &'a mut Formatter<'b>, program: &'a Program) -> Self {
+        let explicit = f.alternate();
+        Self {
+            f,
+            program,
+            indent: 0,
+            explicit,
+        }
+    }
+
+    fn newline(&mut self) -> fmt::Result {
+        self.f.write_char('\n')?;
+        for _ in 0..self.indent {
+            self.f.write_str(INDENT)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, id: StmtId) -> fmt::Result {
+        match &self.program[id] {
+            // Declarations end with a brace and take no semicolon
+            Statement::Struct(stmt) => {
+                write!(self.f, "struct {}", stmt.name)?;
+                if stmt.fields.is_empty() {
+                    return self.f.write_str(" {}");
+                }
+                self.f.write_str(" { ")?;
+                for (i, field) in stmt.fields.iter().enumerate() {
+                    if i > 0 {
+                        self.f.write_str(", ")?;
+                    }
+                    write!(self.f, "{}", field)?;
+                }
+                return self.f.write_str(" }");
+            }
+            Statement::Enum(stmt) => {
+                write!(self.f, "enum {}", stmt.name)?;
+                if stmt.variants.is_empty() {
+                    return self.f.write_str(" {}");
+                }
+                self.f.write_str(" { ")?;
+                for (i, variant) in stmt.variants.iter().enumerate() {
+                    if i > 0 {
+                        self.f.write_str(", ")?;
+                    }
+                    write!(self.f, "{}", variant)?;
+                }
+                return self.f.write_str(" }");
+            }
+            // The declaration ends the statement
+            Statement::Export(stmt) => {
+                self.f.write_str("export ")?;
+                return self.statement(stmt.declaration);
+            }
+            Statement::Import(stmt) => match &stmt.items {
+                ImportItems::Module(name) => {
+                    write!(self.f, "import \"{}\" as {}", stmt.path, name)?
+                }
+                ImportItems::Names(names) => {
+                    self.f.write_str("import {")?;
+                    for (i, name) in names.iter().enumerate() {
+                        self.f.write_str(if i > 0 { ", " } else { " " })?;
+                        write!(self.f, "{}", name)?;
+                    }
+                    if !names.is_empty() {
+                        self.f.write_char(' ')?;
+                    }
+                    write!(self.f, "}} from \"{}\"", stmt.path)?;
+                }
+            },
+            Statement::Let(stmt) => {
+                write!(self.f, "let {}", stmt.pattern)?;
+                if let Some(annotation) = &stmt.annotation {
+                    write!(self.f, ": {}", annotation)?;
+                }
+                self.f.write_str(" = ")?;
+                self.expression(stmt.value)?;
+            }
+            Statement::Const(stmt) => {
+                write!(self.f, "const {}", stmt.name)?;
+                if let Some(annotation) = &stmt.annotation {
+                    write!(self.f, ": {}", annotation)?;
+                }
+                self.f.write_str(" = ")?;
+                self.expression(stmt.value)?;
+            }
+            Statement::Return(stmt) => {
+                self.f.write_str("return ")?;
+                self.expression(stmt.value)?;
+            }
+            Statement::Expression(stmt) => self.expression(stmt.expression)?,
+            Statement::Break(stmt) => {
+                self.f.write_str("break")?;
+                if let Some(value) = stmt.value {
+                    self.f.write_char(' ')?;
+                    self.expression(value)?;
+                }
+            }
+            Statement::Continue(_) => self.f.write_str("continue")?,
+        }
+        self.f.write_char(';')
+    }
+
+    fn block(&mut self, block: &BlockStatement) -> fmt::Result {
+        if block.statements.is_empty() {
+            return self.f.write_str("{}");
+        }
+        self.f.write_char('{')?;
+        self.indent += 1;
+        for stmt in &block.statements {
+            self.newline()?;
+            self.statement(*stmt)?;
+        }
+        self.indent -= 1;
+        self.newline()?;
+        self.f.write_char('}')
+    }
+
+    fn expression(&mut self, id: ExprId) -> fmt::Result {
+        let expr = &self.program[id];
+        let wrap = self.explicit && precedence(expr).is_some();
+        if wrap {
+            self.f.write_char('(')?;
+        }
+        match expr {
+            Expression::Identifier(ident) => write!(self.f, "{}", ident)?,
+            Expression::Integer { value, .. } => write!(self.f, "{}", value)?,
+            Expression::Float { value, .. } => write_float(self.f, *value)?,
+            Expression::Boolean { value, .. } => write!(self.f, "{}", value)?,
+            Expression::String { value, .. } => write!(self.f, "\"{}\"", value)?,
+            Expression::Prefix { token, right } => {
+                write!(self.f, "{}", token.token_type)?;
+                self.operand(*right, Precedence::Prefix, false)?;
+            }
+            Expression::Infix { token, left, right } => {
+                let precedence = Precedence::from(&token.token_type);
+                self.operand(*left, precedence, false)?;
+                write!(self.f, " {} ", token.token_type)?;
+                self.operand(*right, precedence, true)?;
+            }
+            Expression::Assign { target, value, .. } => {
+                self.operand(*target, Precedence::Assign, true)?;
+                self.f.write_str(" = ")?;
+                self.expression(*value)?;
+            }
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+                ..
+            } => {
+                self.f.write_str("if (")?;
+                self.expression(*condition)?;
+                self.f.write_str(") ")?;
+                self.block(consequence)?;
+                if let Some(alternative) = alternative {
+                    self.f.write_str(" else ")?;
+                    self.block(alternative)?;
+                }
+            }
+            Expression::While {
+                condition, body, ..
+            } => {
+                self.f.write_str("while (")?;
+                self.expression(*condition)?;
+                self.f.write_str(") ")?;
+                self.block(body)?;
+            }
+            Expression::For {
+                variable,
+                iterable,
+                body,
+                ..
+            } => {
+                write!(self.f, "for {} in ", variable)?;
+                self.restricted(*iterable)?;
+                self.f.write_char(' ')?;
+                self.block(body)?;
+            }
+            Expression::Loop { body, .. } => {
+                self.f.write_str("loop ")?;
+                self.block(body)?;
+            }
+            Expression::Match { subject, arms, .. } => {
+                self.f.write_str("match ")?;
+                self.restricted(*subject)?;
+                if arms.is_empty() {
+                    self.f.write_str(" {}")?;
+                } else {
+                    self.f.write_str(" {")?;
+                    self.indent += 1;
+                    for arm in arms {
+                        self.newline()?;
+                        write!(self.f, "{}", arm.pattern)?;
+                        if let Some(guard) = arm.guard {
+                            self.f.write_str(" if ")?;
+                            self.expression(guard)?;
+                        }
+                        self.f.write_str(" => ")?;
+                        self.expression(arm.body)?;
+                        self.f.write_char(',')?;
+                    }
+                    self.indent -= 1;
+                    self.newline()?;
+                    self.f.write_char('}')?;
+                }
+            }
+            Expression::Function {
+                params,
+                return_type,
+                body,
+                ..
+            } => {
+                self.f.write_str("fn")?;
+                self.params(params)?;
+                if let Some(return_type) = return_type {
+                    write!(self.f, "-> {} ", return_type)?;
+                }
+                self.block(body)?;
+            }
+            Expression::Macro { params, body, .. } => {
+                self.f.write_str("macro")?;
+                self.params(params)?;
+                self.block(body)?;
+            }
+            Expression::Call {
+                callee,
+                args,
+                named,
+                ..
+            } => {
+                // The arguments would be read as the fields of a variant
+                if matches!(self.program[*callee], Expression::Variant { .. }) {
+                    self.f.write_char('(')?;
+                    self.expression(*callee)?;
+                    self.f.write_char(')')?;
+                } else {
+                    self.operand(*callee, Precedence::Call, false)?;
+                }
+                self.f.write_char('(')?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        self.f.write_str(", ")?;
+                    }
+                    self.expression(*arg)?;
+                }
+                for (i, (name, value)) in named.iter().enumerate() {
+                    if i > 0 || !args.is_empty() {
+                        self.f.write_str(", ")?;
+                    }
+                    write!(self.f, "{}: ", name)?;
+                    self.expression(*value)?;
+                }
+                self.f.write_char(')')?;
+            }
+            Expression::Spread { value, .. } => {
+                self.f.write_str("...")?;
+                self.expression(*value)?;
+            }
+            Expression::Array { elements, .. } => self.list('[', elements, ']')?,
+            Expression::Hash { pairs, .. } => {
+                self.f.write_char('{')?;
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        self.f.write_str(", ")?;
+                    }
+                    self.expression(*key)?;
+                    self.f.write_str(": ")?;
+                    self.expression(*value)?;
+                }
+                self.f.write_char('}')?;
+            }
+            Expression::Index { left, index, .. } => {
+                // Calls and indexes chain from left to right, `f(x)[0]` needs
+                // no parentheses
+                self.operand(*left, Precedence::Call, false)?;
+                self.f.write_char('[')?;
+                self.expression(*index)?;
+                self.f.write_char(']')?;
+            }
+            Expression::Struct { name, fields, .. } => {
+                write!(self.f, "{}", name)?;
+                if fields.is_empty() {
+                    self.f.write_str(" {}")?;
+                } else {
+                    self.f.write_str(" { ")?;
+                    for (i, (field, value)) in fields.iter().enumerate() {
+                        if i > 0 {
+                            self.f.write_str(", ")?;
+                        }
+                        write!(self.f, "{}: ", field)?;
+                        self.expression(*value)?;
+                    }
+                    self.f.write_str(" }")?;
+                }
+            }
+            Expression::Field { object, field, .. } => {
+                self.operand(*object, Precedence::Call, false)?;
+                write!(self.f, ".{}", field)?;
+            }
+            // Ranges don't chain, a range bound of a range needs parentheses
+            Expression::Range { token, start, end } => {
+                if let Some(start) = start {
+                    self.operand(*start, Precedence::Range, true)?;
+                }
+                write!(self.f, "{}", token.token_type)?;
+                if let Some(end) = end {
+                    self.operand(*end, Precedence::Range, true)?;
+                }
+            }
+            Expression::Variant {
+                enum_name,
+                variant,
+                args,
+                ..
+            } => {
+                write!(self.f, "{}::{}", enum_name, variant)?;
+                if !args.is_empty() {
+                    self.list('(', args, ')')?;
+                }
+            }
+        }
+        if wrap {
+            self.f.write_char(')')?;
+        }
+        Ok(())
+    }
+
+    /// Print an operand of an operator binding with `binding`, adding
+    /// parentheses when the operand binds looser
+    ///
+    /// Operators are left associative, so the right operand also needs them
+    /// when it binds as tight as the operator
+    fn operand(&mut self, id: ExprId, binding: Precedence, right: bool) -> fmt::Result {
+        let needs_parens = !self.explicit
+            && precedence(&self.program[id])
+                .is_some_and(|p| p < binding || (right && p == binding));
+        if needs_parens {
+            self.f.write_char('(')?;
+            self.expression(id)?;
+            self.f.write_char(')')
+        } else {
+            self.expression(id)
+        }
+    }
+
+    /// Print the expression before the braces of `for` and `match`, where a
+    /// struct literal outside of delimiters needs parentheses
+    fn restricted(&mut self, id: ExprId) -> fmt::Result {
+        if self.has_bare_struct(id) {
+            self.f.write_char('(')?;
+            self.expression(id)?;
+            self.f.write_char(')')
+        } else {
+            self.expression(id)
+        }
+    }
+
+    fn has_bare_struct(&self, id: ExprId) -> bool {
+        match &self.program[id] {
+            Expression::Struct { .. } => true,
+            Expression::Prefix { right, .. } => self.has_bare_struct(*right),
+            Expression::Infix { left, right, .. } => {
+                self.has_bare_struct(*left) || self.has_bare_struct(*right)
+            }
+            Expression::Assign { target, value, .. } => {
+                self.has_bare_struct(*target) || self.has_bare_struct(*value)
+            }
+            Expression::Range { start, end, .. } => start
+                .iter()
+                .chain(end)
+                .any(|bound| self.has_bare_struct(*bound)),
+            Expression::Call { callee: left, .. }
+            | Expression::Index { left, .. }
+            | Expression::Field { object: left, .. } => self.has_bare_struct(*left),
+            _ => false,
+        }
+    }
+
+    /// The parameters in parentheses followed by a space
+    fn params(&mut self, params: &[Parameter]) -> fmt::Result {
+        self.f.write_char('(')?;
+        for (i, param) in params.iter().enumerate() {
+            if i > 0 {
+                self.f.write_str(", ")?;
+            }
+            write!(self.f, "{}", param)?;
+            if let Some(default) = param.default {
+                self.f.write_str(" = ")?;
+                self.expression(default)?;
+            }
+        }
+        self.f.write_str(") ")
+    }
+
+    fn list(&mut self, open: char, items: &[ExprId], close: char) -> fmt::Result {
+        self.f.write_char(open)?;
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.f.write_str(", ")?;
+            }
+            self.expression(*item)?;
+        }
+        self.f.write_char(close)
+    }
+}
+
+/// How tight an expression binds, `None` for the ones that never need
+/// parentheses such as literals
+fn precedence(expr: &Expression) -> Option<Precedence> {
+    match expr {
+        Expression::Prefix { .. } => Some(Precedence::Prefix),
+        Expression::Infix { token, .. } => Some(Precedence::from(&token.token_type)),
+        Expression::Assign { .. } => Some(Precedence::Assign),
+        Expression::Range { .. } => Some(Precedence::Range),
+        Expression::Call { .. } => Some(Precedence::Call),
+        Expression::Index { .. } | Expression::Field { .. } => Some(Precedence::Index),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> super::Program {
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer, "test");
+        let p = parser.parse();
+        assert!(
+            parser.errors().is_empty(),
+            "{}\n{:?}",
+            input,
+            parser.errors()
+        );
+        p
+    }
+
+    /// Parsing the printed program gives back the same tree
+    fn assert_round_trip(input: &str) -> String {
+        let program = parse(input);
+        let printed = program.to_string();
+        let reparsed = parse(&printed);
+        assert_eq!(
+            format!("{:#}", reparsed),
+            format!("{:#}", program),
+            "{}",
+            input
+        );
+        assert_eq!(reparsed.to_string(), printed);
+        printed
+    }
+
+    #[test]
+    fn print_canonical_source() {
+        let input = r#"let   mut add=fn(x,y){x+y;return x};
+add(1,2.50)*-(3+4)  ; a=b=[1,{"k":true}][0];
+if(a<b){a}else{}"#;
+
+        let printed = assert_round_trip(input);
+
+        assert_eq!(
+            printed,
+            r#"let mut add = fn(x, y) {
+    x + y;
+    return x;
+};
+add(1, 2.5) * -(3 + 4);
+a = b = [1, {"k": true}][0];
+if (a < b) {
+    a;
+} else {};"#
+        );
+    }
+    #[test]
+    fn print_loops() {
+        let input = "while(i<3){i=i+1;continue};for x in [1,2]{if(x){break}}
+let v=loop{break 1+2};";
+
+        let printed = assert_round_trip(input);
+
+        assert_eq!(
+            printed,
+            "while (i < 3) {
+    i = i + 1;
+    continue;
+};
+for x in [1, 2] {
+    if (x) {
+        break;
+    };
+};
+let v = loop {
+    break 1 + 2;
+};"
+        );
+    }
+    #[test]
+    fn print_match() {
+        let input = r#"let s=match f(x){0=>"zero",-2.0=>x,[a,[_,true]] if a>1=>a*2,_=>match y{}};"#;
+
+        let printed = assert_round_trip(input);
+
+        assert_eq!(
+            printed,
+            r#"let s = match f(x) {
+    0 => "zero",
+    -2.0 => x,
+    [a, [_, true]] if a > 1 => a * 2,
+    _ => match y {},
+};"#
+        );
+    }
+    #[test]
+    fn print_structs() {
+        let input = "struct Point{x,y};struct Empty{}
+let p=Point{x:1,y:Empty{}};p.x=-p.y.z(1)[0].w;
+for q in (Point{x:1,y:2}).x+1{match (P{}){Point{x,y:[_,b]}=>x,Empty{}=>b}}";
+
+        let printed = assert_round_trip(input);
+
+        assert_eq!(
+            printed,
+            "struct Point { x, y }
+struct Empty {}
+let p = Point { x: 1, y: Empty {} };
+p.x = -p.y.z(1)[0].w;
+for q in (Point { x: 1, y: 2 }.x + 1) {
+    match (P {}) {
+        Point { x, y: [_, b] } => x,
+        Empty {} => b,
+    };
+};"
+        );
+    }
+    #[test]
+    fn print_explicit_parentheses() {
+        let program = parse("a + b * -c - d(e)[f];");
+
+        assert_eq!(program.to_string(), "a + b * -c - d(e)[f];");
+        assert_eq!(
+            format!("{:#}", program),
+            "((a + (b * (-c))) - ((d(e))[f]));"
+        );
+    }
+    #[test]
+    fn print_single_nodes() {
+        let program = parse("let f = fn(x) { if (x) { x * 2 } else { return 0 } }; f(1) + 2;");
+        let statements: Vec<String> = program
+            .statements
+            .iter()
+            .map(|id| program.display_statement(*id).to_string())
+            .collect();
+        let super::Statement::Let(stmt) = &program[program.statements[0]] else {
+            panic!("expected a let statement");
+        };
+        let super::Expression::Function { body, .. } = &program[stmt.value] else {
+            panic!("expected a function");
+        };
+        let block = program.display_block(body).to_string();
+
+        assert_eq!(
+            statements,
+            [
+                "let f = fn(x) {\n    if (x) {\n        x * 2;\n    } else {\n        return 0;\n    };\n};",
+                "f(1) + 2;",
+            ]
+        );
+        assert_eq!(
+            block,
+            "{\n    if (x) {\n        x * 2;\n    } else {\n        return 0;\n    };\n}"
+        );
+        for (id, printed) in program.statements.iter().zip(&statements) {
+            let reparsed = parse(printed);
+            assert_eq!(
+                format!("{:#}", reparsed.display_statement(reparsed.statements[0])),
+                format!("{:#}", program.display_statement(*id))
+            );
+        }
+        let reparsed = parse(&format!("fn(x) {};", block));
+        assert_eq!(reparsed.to_string(), format!("fn(x) {};", block));
+    }
+    #[test]
+    fn round_trip_files() {
+        let input = std::fs::read_to_string("input/line_nb_error.pab").unwrap();
+        let valid = input
+            .replace("let 5 = add(five, ten);", "")
+            .replace("!-/*5;", "");
+
+        assert_round_trip(&valid);
+    }
+    #[test]
+    fn round_trip_generated_expressions() {
+        // xorshift, so the generated programs are the same on every run
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move |n: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % n
+        };
+        fn generate(next: &mut dyn FnMut(u64) -> u64, depth: u32) -> String {
+            let operators = ["+", "-", "*", "/", "<", ">", "==", "!="];
+            if depth == 0 {
+                return ["x", "1", "2.5", "true", "\"s\""][next(5) as usize].to_string();
+            }
+            let operand = |next: &mut dyn FnMut(u64) -> u64| generate(next, depth - 1);
+            match next(7) {
+                0 => format!("-{}", operand(next)),
+                1 => format!("!({})", operand(next)),
+                2 => format!("f({}, {})", operand(next), operand(next)),
+                3 => format!("[{}][{}]", operand(next), operand(next)),
+                4 => format!("({})", operand(next)),
+                _ => {
+                    let operator = operators[next(operators.len() as u64) as usize];
+                    format!("{} {} {}", operand(next), operator, operand(next))
+                }
+            }
+        }
+
+        for _ in 0..200 {
+            let depth = next(6) as u32;
+            assert_round_trip(&format!("let x = {};", generate(&mut next, depth)));
+        }
+    }
+    #[test]
+    fn print_enums() {
+        let input = "enum Shape{Circle(r),Rect(w,h),Empty,};enum Never{}
+let s=Shape::Rect(1,Shape::Circle(2));(Shape::Empty)(3);
+match s{Shape::Rect(w,Shape::Empty)=>w,Shape::Circle(_)=>0,_=>-1}";
+
+        let printed = assert_round_trip(input);
+
+        assert_eq!(
+            printed,
+            "enum Shape { Circle(r), Rect(w, h), Empty }
+enum Never {}
+let s = Shape::Rect(1, Shape::Circle(2));
+(Shape::Empty)(3);
+match s {
+    Shape::Rect(w, Shape::Empty) => w,
+    Shape::Circle(_) => 0,
+    _ => -1,
+};"
+        );
+    }
+    #[test]
+    fn print_ranges() {
+        let input = "for i in 0..n+1{a[i..]=s[..=i][..]};let r=(..2)..;[1..=2,(0..1)..2,-(1..2)]";
+
+        let printed = assert_round_trip(input);
+
+        assert_eq!(
+            printed,
+            "for i in 0..n + 1 {
+    a[i..] = s[..=i][..];
+};
+let r = (..2)..;
+[1..=2, (0..1)..2, -(1..2)];"
+        );
+    }
+    #[test]
+    fn print_destructuring() {
+        let input =
+            "let [a,mut b,...rest]=xs;let{x,y:[_,...mut z]}=p;let Point{mut x,y:_}=p;let[...]=[];";
+
+        let printed = assert_round_trip(input);
+
+        assert_eq!(
+            printed,
+            "let [a, mut b, ...rest] = xs;
+let { x, y: [_, ...mut z] } = p;
+let Point { mut x, y: _ } = p;
+let [...] = [];"
+        );
+    }
+    #[test]
+    fn print_annotations() {
+        let input = "let x:int=5;let f=fn(a:float,b)->map<string,array<int>>{a};let g=|n:int|n;";
+
+        let printed = assert_round_trip(input);
+
+        assert_eq!(
+            printed,
+            "let x: int = 5;
+let f = fn(a: float, b) -> map<string, array<int>> {
+    a;
+};
+let g = fn(n: int) {
+    n;
+};"
+        );
+    }
+    #[test]
+    fn print_imports() {
+        let input = r#"import"lib/math.pab"as math;import{add,sub}from"./util.pab";import{}from"e.pab";export let x=1;export struct P{x}"#;
+
+        let printed = assert_round_trip(input);
+
+        assert_eq!(
+            printed,
+            r#"import "lib/math.pab" as math;
+import { add, sub } from "./util.pab";
+import {} from "e.pab";
+export let x = 1;
+export struct P { x }"#
+        );
+    }
+    #[test]
+    fn print_consts() {
+        let input = r#"const MAX=60*60*24;const NAME:string="pab";export const HALF=MAX/2;"#;
+
+        let printed = assert_round_trip(input);
+
+        assert_eq!(
+            printed,
+            r#"const MAX = 60 * 60 * 24;
+const NAME: string = "pab";
+export const HALF = MAX / 2;"#
+        );
+    }
+    #[test]
+    fn print_parameters_and_arguments() {
+        let input = "let f=fn(x,y:int=1+2,...rest){x};f(1,...xs);f(2,y:3);";
+
+        let printed = assert_round_trip(input);
+
+        assert_eq!(
+            printed,
+            "let f = fn(x, y: int = 1 + 2, ...rest) {\n    x;\n};\nf(1, ...xs);\nf(2, y: 3);"
+        );
+    }
+}
@@ -0,0 +1,1325 @@
+//! Expansion of the macro calls of a program
+//!
+//! `let name = macro(params) { body };` at the top level defines a macro,
+//! the statement is removed from the program. A call `name(args)` runs the
+//! body with each parameter bound to the quoted code of its argument, the
+//! call is replaced by the code the body returns. A parameter left out is
+//! bound to the code of its default value, a rest parameter to an array of
+//! the arguments left.
+//!
+//! `quote(expr)` in a body is the code of `expr` rather than its value, and
+//! `unquote(expr)` inside it splices the value of `expr` back in. The body
+//! runs before the program does, it can only use its parameters, literals,
+//! `let`, the arithmetic and comparison operators, `quote` and `unquote`.
+//!
+//! Expansions are hygienic: the names the quoted code binds are renamed to
+//! `name#n`, a name no source can spell, where they are in scope, so they
+//! can't capture the names of the arguments nor those of the call site. The tokens of the quoted code take the span of the macro
+//! name at the call site, the arguments keep their own.
+
+use super::visit::{self, VisitorMut};
+use super::{
+    BlockStatement, BreakStatement, ConstStatement, ContinueStatement, EnumStatement,
+    ExportStatement, ExprId, Expression, ExpressionStatement, Identifier, ImportItems,
+    ImportStatement, LetStatement, Literal, MatchArm, Parameter, Pattern, Program, ReturnStatement,
+    Statement, StmtId, StructStatement, TypeAnnotation, Variant,
+};
+use crate::diagnostic::Diagnostic;
+use crate::token::{Span, Token, TokenType};
+use std::collections::HashMap;
+use thiserror::Error as ThisError;
+
+/// Number of expansions a macro call can be nested in
+const MAX_DEPTH: usize = 64;
+
+/// A macro call that can't be expanded
+#[derive(Debug, Clone, PartialEq, ThisError)]
+pub enum MacroError {
+    /// `declaration` is the macro literal
+    #[error("macro '{name}' takes {expected} argument(s), got {found}")]
+    Arity {
+        name: String,
+        expected: usize,
+        found: usize,
+        span: Span,
+        declaration: Span,
+    },
+    /// `first` is the argument given first
+    #[error("argument '{param}' is given more than once")]
+    ArgumentGivenTwice {
+        param: String,
+        span: Span,
+        first: Span,
+    },
+    #[error("macro '{name}' is missing argument(s) for {}", missing.join(", "))]
+    MissingArguments {
+        name: String,
+        missing: Vec<String>,
+        span: Span,
+        declaration: Span,
+    },
+    #[error("macro '{name}' has no parameter named '{param}'")]
+    UnknownParameter {
+        name: String,
+        param: String,
+        span: Span,
+        declaration: Span,
+    },
+    /// `span` is in the body of the macro, `call` the call being expanded
+    #[error("can't evaluate this while expanding a macro")]
+    NotExpandable { span: Span, call: Span },
+    /// `span` is the operator that can't be applied
+    #[error("can't expand the macro, {reason}")]
+    Evaluation {
+        reason: String,
+        span: Span,
+        call: Span,
+    },
+    #[error("macro '{name}' doesn't return quoted code")]
+    NotQuoted { name: String, span: Span },
+    #[error("macro literal outside of a top-level let")]
+    MisplacedMacro { span: Span },
+    #[error("macro '{name}' is expanded more than {MAX_DEPTH} times in a row")]
+    RecursionLimit { name: String, span: Span },
+}
+
+impl MacroError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Arity { .. } => "E0301",
+            Self::NotExpandable { .. } => "E0302",
+            Self::Evaluation { .. } => "E0303",
+            Self::NotQuoted { .. } => "E0304",
+            Self::MisplacedMacro { .. } => "E0305",
+            Self::RecursionLimit { .. } => "E0306",
+            Self::UnknownParameter { .. } => "E0307",
+            Self::MissingArguments { .. } => "E0308",
+            Self::ArgumentGivenTwice { .. } => "E0309",
+        }
+    }
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Arity { span, .. }
+            | Self::NotExpandable { span, .. }
+            | Self::Evaluation { span, .. }
+            | Self::NotQuoted { span, .. }
+            | Self::MisplacedMacro { span }
+            | Self::RecursionLimit { span, .. }
+            | Self::UnknownParameter { span, .. }
+            | Self::MissingArguments { span, .. }
+            | Self::ArgumentGivenTwice { span, .. } => *span,
+        }
+    }
+}
+
+impl From<&MacroError> for Diagnostic {
+    fn from(error: &MacroError) -> Self {
+        let diagnostic = Diagnostic::error(error.to_string()).with_code(error.code());
+        match error {
+            MacroError::Arity {
+                span, declaration, ..
+            }
+            | MacroError::MissingArguments {
+                span, declaration, ..
+            } => diagnostic
+                .with_label(*span, "called here")
+                .with_secondary(*declaration, "macro declared here"),
+            MacroError::NotExpandable { span, call } => diagnostic
+                .with_label(*span, "needs the program to run")
+                .with_secondary(*call, "while expanding this call")
+                .with_note("macro bodies can use their parameters, literals, let, operators, quote and unquote"),
+            MacroError::Evaluation { span, call, .. } => diagnostic
+                .with_label(*span, "evaluated here")
+                .with_secondary(*call, "while expanding this call"),
+            MacroError::NotQuoted { span, .. } => diagnostic
+                .with_label(*span, "expanded here")
+                .with_help("end the body of the macro with quote(...)"),
+            MacroError::MisplacedMacro { span } => diagnostic
+                .with_label(*span, "not bound by a top-level let")
+                .with_note("macros are declared as in let name = macro(x) { quote(x) };"),
+            MacroError::RecursionLimit { span, .. } => diagnostic
+                .with_label(*span, "expanded here")
+                .with_note("the code the macro returns calls it again"),
+            MacroError::UnknownParameter {
+                span, declaration, ..
+            } => diagnostic
+                .with_label(*span, "unknown parameter")
+                .with_secondary(*declaration, "macro declared here"),
+            MacroError::ArgumentGivenTwice { param, span, first } => diagnostic
+                .with_label(*span, "given again")
+                .with_secondary(*first, format!("'{}' given here", param)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+    span: Span,
+    /// Name and default value of the parameters before the rest one
+    params: Vec<(String, Option<ExprId>)>,
+    rest: Option<String>,
+    body: Vec<StmtId>,
+}
+
+/// A value of the body of a macro
+#[derive(Debug, Clone)]
+enum Value {
+    Literal(Literal),
+    /// The root of the quoted code, owned by the value
+    Quote(ExprId),
+}
+
+/// How a piece of code is copied
+#[derive(Default, Clone)]
+struct Template<'a> {
+    /// The span the tokens take, they keep theirs if none
+    site: Option<Span>,
+    /// The new names of the names the code binds in scope
+    renames: HashMap<String, String>,
+    /// Scope `unquote` is evaluated in, it is an ordinary call and the names
+    /// bound are kept if none
+    scope: Option<&'a HashMap<String, Value>>,
+}
+
+impl Template<'_> {
+    fn token(&self, token: &Token) -> Token {
+        match self.site {
+            Some(site) => Token::new(
+                token.token_type.clone(),
+                site.start,
+                site.end,
+                site.line,
+                site.column,
+            ),
+            None => token.clone(),
+        }
+    }
+    /// A name of a field, variant or type, which is never renamed
+    fn ident(&self, ident: &Identifier) -> Identifier {
+        let token = self.token(&ident.token);
+        let (start, end) = (token.start, token.end);
+        Identifier::new(token, ident.value.clone(), start, end)
+    }
+    /// A name of a variable
+    fn variable(&self, ident: &Identifier) -> Identifier {
+        let mut copy = self.ident(ident);
+        if let Some(name) = self.renames.get(&ident.value) {
+            copy.value = name.clone();
+        }
+        copy
+    }
+    /// The copied token, the end and the statements to copy of a block
+    fn block(&self, block: &BlockStatement) -> (Token, usize, Vec<StmtId>) {
+        let end = self.site.map_or(block.end, |site| site.end);
+        (self.token(&block.token), end, block.statements.clone())
+    }
+    fn annotation(&self, annotation: &TypeAnnotation) -> TypeAnnotation {
+        TypeAnnotation::new(
+            self.ident(&annotation.name),
+            annotation
+                .args
+                .iter()
+                .map(|arg| self.annotation(arg))
+                .collect(),
+        )
+    }
+    /// Copy a parameter, its default value is left to [`Expander::copy_defaults`]
+    fn param(&self, param: &Parameter) -> Parameter {
+        Parameter::new(
+            self.variable(&param.name),
+            param.annotation.as_ref().map(|a| self.annotation(a)),
+            param.default,
+            param.is_rest,
+        )
+    }
+    fn pattern(&self, pattern: &Pattern) -> Pattern {
+        match pattern {
+            Pattern::Wildcard(token) => Pattern::Wildcard(self.token(token)),
+            Pattern::Binding { name, is_mut } => Pattern::Binding {
+                name: self.variable(name),
+                is_mut: *is_mut,
+            },
+            Pattern::Literal { token, value } => Pattern::Literal {
+                token: self.token(token),
+                value: value.clone(),
+            },
+            Pattern::Array { token, elements } => Pattern::Array {
+                token: self.token(token),
+                elements: elements.iter().map(|p| self.pattern(p)).collect(),
+            },
+            Pattern::Rest {
+                token,
+                name,
+                is_mut,
+            } => Pattern::Rest {
+                token: self.token(token),
+                name: name.as_ref().map(|name| self.variable(name)),
+                is_mut: *is_mut,
+            },
+            Pattern::Struct {
+                token,
+                name,
+                fields,
+            } => Pattern::Struct {
+                token: self.token(token),
+                name: name.as_ref().map(|name| self.ident(name)),
+                fields: fields
+                    .iter()
+                    .map(|(field, pattern)| (self.ident(field), self.pattern(pattern)))
+                    .collect(),
+            },
+            Pattern::Variant {
+                enum_name,
+                variant,
+                fields,
+            } => Pattern::Variant {
+                enum_name: self.ident(enum_name),
+                variant: self.ident(variant),
+                fields: fields.iter().map(|p| self.pattern(p)).collect(),
+            },
+        }
+    }
+}
+
+/// Whether `callee` is the name `name`
+fn is_call_of(program: &Program, callee: ExprId, name: &str) -> bool {
+    matches!(&program[callee], Expression::Identifier(ident) if ident.value == name)
+}
+
+/// Expands the macro calls of a program, see the [module](self) docs
+#[derive(Debug, Default)]
+pub struct Expander {
+    macros: HashMap<String, Macro>,
+    errors: Vec<MacroError>,
+    /// Span of the macro name of the call being expanded
+    site: Span,
+    /// Number of expansions the current node is in
+    depth: usize,
+    /// Whether the recursion limit was reached, nothing is expanded after
+    exhausted: bool,
+    /// Number of names renamed so far, for making new ones
+    renamed: usize,
+}
+
+impl Expander {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The errors of the last call to [`Expander::expand`]
+    pub fn errors(&self) -> &[MacroError] {
+        &self.errors
+    }
+    /// Remove the macro declarations from the program and expand the calls
+    ///
+    /// The macros declared are kept for the next programs expanded, which
+    /// must be allocated in the same arena.
+    pub fn expand(&mut self, program: &mut Program) {
+        self.errors.clear();
+        self.exhausted = false;
+        for id in std::mem::take(&mut program.statements) {
+            if let Statement::Let(LetStatement {
+                pattern:
+                    Pattern::Binding {
+                        name,
+                        is_mut: false,
+                    },
+                value,
+                ..
+            }) = &program[id]
+            {
+                if let Expression::Macro {
+                    token,
+                    params,
+                    body,
+                } = &program[*value]
+                {
+                    let definition = Macro {
+                        span: token.span(),
+                        params: params
+                            .iter()
+                            .filter(|p| !p.is_rest)
+                            .map(|p| (p.name.value.clone(), p.default))
+                            .collect(),
+                        rest: params
+                            .iter()
+                            .find(|p| p.is_rest)
+                            .map(|p| p.name.value.clone()),
+                        body: body.statements.clone(),
+                    };
+                    self.macros.insert(name.value.clone(), definition);
+                    continue;
+                }
+            }
+            program.statements.push(id);
+        }
+        self.visit_program_mut(program);
+    }
+
+    /// The name, the span of the name and the definition of the macro `id`
+    /// calls
+    fn called_macro(&self, program: &Program, id: ExprId) -> Option<(String, Span, Macro)> {
+        let Expression::Call { callee, .. } = &program[id] else {
+            return None;
+        };
+        let Expression::Identifier(name) = &program[*callee] else {
+            return None;
+        };
+        let definition = self.macros.get(&name.value)?;
+        Some((name.value.clone(), name.token.span(), definition.clone()))
+    }
+
+    /// Replace the macro call `id` by its expansion, returns false if it
+    /// can't be expanded
+    fn expand_call(&mut self, program: &mut Program, id: ExprId) -> bool {
+        let Some((name, span, definition)) = self.called_macro(program, id) else {
+            return false;
+        };
+        let Expression::Call { args, named, .. } = &program[id] else {
+            return false;
+        };
+        if let Some(spread) = args
+            .iter()
+            .find(|arg| matches!(program[**arg], Expression::Spread { .. }))
+        {
+            self.errors.push(MacroError::NotExpandable {
+                span: program[*spread].token().span(),
+                call: span,
+            });
+            return false;
+        }
+
+        // Bind the positional arguments then the named ones, the rest
+        // parameter takes the positional arguments left
+        let expected = definition.params.len();
+        let mut bound: Vec<Option<ExprId>> =
+            args.iter().take(expected).copied().map(Some).collect();
+        bound.resize(expected, None);
+        if definition.rest.is_none() && args.len() > expected {
+            self.errors.push(MacroError::Arity {
+                name,
+                expected,
+                found: args.len() + named.len(),
+                span,
+                declaration: definition.span,
+            });
+            return false;
+        }
+        for (param, value) in named {
+            match definition
+                .params
+                .iter()
+                .position(|(p, _)| *p == param.value)
+            {
+                Some(i) => match bound[i] {
+                    None => bound[i] = Some(*value),
+                    Some(first) => {
+                        self.errors.push(MacroError::ArgumentGivenTwice {
+                            param: param.value.clone(),
+                            span: param.token.span(),
+                            first: program[first].token().span(),
+                        });
+                        return false;
+                    }
+                },
+                None => {
+                    self.errors.push(MacroError::UnknownParameter {
+                        name,
+                        param: param.value.clone(),
+                        span: param.token.span(),
+                        declaration: definition.span,
+                    });
+                    return false;
+                }
+            }
+        }
+        let missing: Vec<String> = definition
+            .params
+            .iter()
+            .zip(&bound)
+            .filter(|((_, default), arg)| arg.is_none() && default.is_none())
+            .map(|((param, _), _)| format!("'{}'", param))
+            .collect();
+        if !missing.is_empty() {
+            self.errors.push(MacroError::MissingArguments {
+                name,
+                missing,
+                span,
+                declaration: definition.span,
+            });
+            return false;
+        }
+
+        let rest = args.get(expected..).unwrap_or_default().to_vec();
+        let mut scope: HashMap<String, Value> = definition
+            .params
+            .into_iter()
+            .zip(bound)
+            .filter_map(|((param, default), arg)| Some((param, Value::Quote(arg.or(default)?))))
+            .collect();
+        if let Some(param) = definition.rest {
+            let token = Token::new(
+                TokenType::LBracket,
+                span.start,
+                span.end,
+                span.line,
+                span.column,
+            );
+            let elements = program.alloc_expression(Expression::Array {
+                token,
+                elements: rest,
+            });
+            scope.insert(param, Value::Quote(elements));
+        }
+
+        let outer = std::mem::replace(&mut self.site, span);
+        let result = self.evaluate_body(program, &definition.body, &mut scope);
+        self.site = outer;
+        match result {
+            Ok(Some(Value::Quote(code))) => {
+                // The call and its arguments are left unused in the arena
+                program.exprs.swap(id.index(), code.index());
+                true
+            }
+            Ok(_) => {
+                self.errors.push(MacroError::NotQuoted { name, span });
+                false
+            }
+            Err(error) => {
+                self.errors.push(error);
+                false
+            }
+        }
+    }
+
+    /// Run the statements of a macro body, the value is the one of the last
+    /// statement or of a `return`
+    fn evaluate_body(
+        &mut self,
+        program: &mut Program,
+        body: &[StmtId],
+        scope: &mut HashMap<String, Value>,
+    ) -> Result<Option<Value>, MacroError> {
+        let mut value = None;
+        for &id in body {
+            value = match &program[id] {
+                Statement::Let(LetStatement {
+                    pattern: Pattern::Binding { name, .. },
+                    value,
+                    ..
+                }) => {
+                    let name = name.value.clone();
+                    let value = self.evaluate(program, *value, scope)?;
+                    scope.insert(name, value);
+                    None
+                }
+                Statement::Expression(stmt) => {
+                    Some(self.evaluate(program, stmt.expression, scope)?)
+                }
+                Statement::Return(stmt) => {
+                    return Ok(Some(self.evaluate(program, stmt.value, scope)?))
+                }
+                stmt => {
+                    return Err(MacroError::NotExpandable {
+                        span: stmt.token().span(),
+                        call: self.site,
+                    })
+                }
+            };
+        }
+        Ok(value)
+    }
+
+    fn evaluate(
+        &mut self,
+        program: &mut Program,
+        id: ExprId,
+        scope: &HashMap<String, Value>,
+    ) -> Result<Value, MacroError> {
+        let site = self.site;
+        let evaluation = |reason: String, token: &Token| MacroError::Evaluation {
+            reason,
+            span: token.span(),
+            call: site,
+        };
+        let value = match &program[id] {
+            Expression::Integer { value, .. } => Literal::Integer(*value),
+            Expression::Float { value, .. } => Literal::Float(*value),
+            Expression::Boolean { value, .. } => Literal::Boolean(*value),
+            Expression::String { value, .. } => Literal::String(value.clone()),
+            Expression::Identifier(ident) if scope.contains_key(&ident.value) => {
+                return Ok(scope[&ident.value].clone())
+            }
+            Expression::Call { callee, args, .. }
+                if args.len() == 1 && is_call_of(program, *callee, "quote") =>
+            {
+                let code = args[0];
+                return Ok(Value::Quote(self.quote(program, code, scope)?));
+            }
+            Expression::Prefix { token, right } => {
+                let (token, right) = (token.clone(), *right);
+                match self.evaluate(program, right, scope)? {
+                    Value::Literal(right) => Literal::prefix(&token.token_type, right)
+                        .map_err(|reason| evaluation(reason, &token))?,
+                    Value::Quote(_) => {
+                        return Err(evaluation(
+                            format!("'{}' can't be applied to quoted code", token.token_type),
+                            &token,
+                        ))
+                    }
+                }
+            }
+            Expression::Infix { token, left, right } => {
+                let (token, left, right) = (token.clone(), *left, *right);
+                let left = self.evaluate(program, left, scope)?;
+                let right = self.evaluate(program, right, scope)?;
+                match (left, right) {
+                    (Value::Literal(left), Value::Literal(right)) => {
+                        Literal::infix(&token.token_type, left, right)
+                            .map_err(|reason| evaluation(reason, &token))?
+                    }
+                    _ => {
+                        return Err(evaluation(
+                            format!("'{}' can't be applied to quoted code", token.token_type),
+                            &token,
+                        ))
+                    }
+                }
+            }
+            expression => {
+                return Err(MacroError::NotExpandable {
+                    span: expression.token().span(),
+                    call: self.site,
+                })
+            }
+        };
+        Ok(Value::Literal(value))
+    }
+
+    /// Copy the code of `quote(id)` to the call site, with the names it
+    /// binds renamed and the `unquote` calls replaced by their values
+    fn quote(
+        &mut self,
+        program: &mut Program,
+        id: ExprId,
+        scope: &HashMap<String, Value>,
+    ) -> Result<ExprId, MacroError> {
+        let template = Template {
+            site: Some(self.site),
+            renames: HashMap::new(),
+            scope: Some(scope),
+        };
+        self.copy(program, id, &template)
+    }
+
+    /// The template for the code in the scope of `names`, which are given
+    /// new names when quoting
+    fn bind<'a, 'i>(
+        &mut self,
+        template: &Template<'a>,
+        names: impl IntoIterator<Item = &'i Identifier>,
+    ) -> Template<'a> {
+        let mut inner = template.clone();
+        if template.scope.is_some() {
+            for name in names {
+                let renamed = format!("{}#{}", name.value, self.renamed);
+                self.renamed += 1;
+                inner.renames.insert(name.value.clone(), renamed);
+            }
+        }
+        inner
+    }
+
+    /// The code standing for the value of `unquote(...)`
+    fn unquote(&mut self, program: &mut Program, value: Value) -> Result<ExprId, MacroError> {
+        let token = |token_type| {
+            let site = self.site;
+            Token::new(token_type, site.start, site.end, site.line, site.column)
+        };
+        let expression = match value {
+            Value::Quote(code) => return self.copy(program, code, &Template::default()),
+            Value::Literal(Literal::Integer(value)) => Expression::Integer {
+                token: token(TokenType::Int),
+                value,
+            },
+            Value::Literal(Literal::Float(value)) => Expression::Float {
+                token: token(TokenType::Float),
+                value,
+            },
+            Value::Literal(Literal::Boolean(value)) => Expression::Boolean {
+                token: token(if value {
+                    TokenType::True
+                } else {
+                    TokenType::False
+                }),
+                value,
+            },
+            Value::Literal(Literal::String(value)) => Expression::String {
+                token: token(TokenType::String),
+                value,
+            },
+        };
+        Ok(program.alloc_expression(expression))
+    }
+
+    fn copy(
+        &mut self,
+        program: &mut Program,
+        id: ExprId,
+        template: &Template,
+    ) -> Result<ExprId, MacroError> {
+        if let (Some(scope), Expression::Call { callee, args, .. }) = (template.scope, &program[id])
+        {
+            if args.len() == 1 && is_call_of(program, *callee, "unquote") {
+                let arg = args[0];
+                let value = self.evaluate(program, arg, scope)?;
+                return self.unquote(program, value);
+            }
+        }
+        let t = template;
+        let expression = match &program[id] {
+            Expression::Identifier(ident) => Expression::Identifier(t.variable(ident)),
+            Expression::Integer { token, value } => Expression::Integer {
+                token: t.token(token),
+                value: *value,
+            },
+            Expression::Float { token, value } => Expression::Float {
+                token: t.token(token),
+                value: *value,
+            },
+            Expression::Boolean { token, value } => Expression::Boolean {
+                token: t.token(token),
+                value: *value,
+            },
+            Expression::String { token, value } => Expression::String {
+                token: t.token(token),
+                value: value.clone(),
+            },
+            Expression::Prefix { token, right } => {
+                let (token, right) = (t.token(token), *right);
+                Expression::Prefix {
+                    token,
+                    right: self.copy(program, right, t)?,
+                }
+            }
+            Expression::Infix { token, left, right } => {
+                let (token, left, right) = (t.token(token), *left, *right);
+                Expression::Infix {
+                    token,
+                    left: self.copy(program, left, t)?,
+                    right: self.copy(program, right, t)?,
+                }
+            }
+            Expression::Assign {
+                token,
+                target,
+                value,
+            } => {
+                let (token, target, value) = (t.token(token), *target, *value);
+                Expression::Assign {
+                    token,
+                    target: self.copy(program, target, t)?,
+                    value: self.copy(program, value, t)?,
+                }
+            }
+            Expression::If {
+                token,
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let token = t.token(token);
+                let condition = *condition;
+                let consequence = t.block(consequence);
+                let alternative = alternative.as_ref().map(|block| t.block(block));
+                Expression::If {
+                    token,
+                    condition: self.copy(program, condition, t)?,
+                    consequence: self.copy_block(program, consequence, t)?,
+                    alternative: match alternative {
+                        Some(block) => Some(self.copy_block(program, block, t)?),
+                        None => None,
+                    },
+                }
+            }
+            Expression::While {
+                token,
+                condition,
+                body,
+            } => {
+                let (token, condition) = (t.token(token), *condition);
+                let body = t.block(body);
+                Expression::While {
+                    token,
+                    condition: self.copy(program, condition, t)?,
+                    body: self.copy_block(program, body, t)?,
+                }
+            }
+            Expression::For {
+                token,
+                variable,
+                iterable,
+                body,
+            } => {
+                let inner = self.bind(t, [variable]);
+                let (token, variable, iterable) =
+                    (t.token(token), inner.variable(variable), *iterable);
+                let body = t.block(body);
+                Expression::For {
+                    token,
+                    variable,
+                    iterable: self.copy(program, iterable, t)?,
+                    body: self.copy_block(program, body, &inner)?,
+                }
+            }
+            Expression::Loop { token, body } => {
+                let token = t.token(token);
+                let body = t.block(body);
+                Expression::Loop {
+                    token,
+                    body: self.copy_block(program, body, t)?,
+                }
+            }
+            Expression::Match {
+                token,
+                subject,
+                arms,
+            } => {
+                let (token, subject) = (t.token(token), *subject);
+                let arms: Vec<_> = arms
+                    .iter()
+                    .map(|arm| {
+                        let inner = self.bind(t, arm.pattern.bindings());
+                        (inner.pattern(&arm.pattern), arm.guard, arm.body, inner)
+                    })
+                    .collect();
+                let subject = self.copy(program, subject, t)?;
+                let mut copies = Vec::new();
+                for (pattern, guard, body, inner) in arms {
+                    let guard = match guard {
+                        Some(guard) => Some(self.copy(program, guard, &inner)?),
+                        None => None,
+                    };
+                    let body = self.copy(program, body, &inner)?;
+                    copies.push(MatchArm::new(pattern, guard, body));
+                }
+                Expression::Match {
+                    token,
+                    subject,
+                    arms: copies,
+                }
+            }
+            Expression::Function {
+                token,
+                params,
+                return_type,
+                body,
+            } => {
+                let inner = self.bind(t, params.iter().map(|param| &param.name));
+                let token = t.token(token);
+                let params = params.iter().map(|param| inner.param(param)).collect();
+                let return_type = return_type.as_ref().map(|ty| t.annotation(ty));
+                let body = t.block(body);
+                Expression::Function {
+                    token,
+                    params: self.copy_defaults(program, params, t)?,
+                    return_type,
+                    body: self.copy_block(program, body, &inner)?,
+                }
+            }
+            Expression::Macro {
+                token,
+                params,
+                body,
+            } => {
+                let inner = self.bind(t, params.iter().map(|param| &param.name));
+                let token = t.token(token);
+                let params = params.iter().map(|param| inner.param(param)).collect();
+                let body = t.block(body);
+                Expression::Macro {
+                    token,
+                    params: self.copy_defaults(program, params, t)?,
+                    body: self.copy_block(program, body, &inner)?,
+                }
+            }
+            Expression::Call {
+                token,
+                callee,
+                args,
+                named,
+            } => {
+                let (token, callee, args) = (t.token(token), *callee, args.clone());
+                let named: Vec<_> = named
+                    .iter()
+                    .map(|(name, value)| (t.ident(name), *value))
+                    .collect();
+                let callee = self.copy(program, callee, t)?;
+                let args = self.copy_all(program, &args, t)?;
+                let mut copies = Vec::new();
+                for (name, value) in named {
+                    copies.push((name, self.copy(program, value, t)?));
+                }
+                Expression::Call {
+                    token,
+                    callee,
+                    args,
+                    named: copies,
+                }
+            }
+            Expression::Spread { token, value } => {
+                let (token, value) = (t.token(token), *value);
+                Expression::Spread {
+                    token,
+                    value: self.copy(program, value, t)?,
+                }
+            }
+            Expression::Array { token, elements } => {
+                let (token, elements) = (t.token(token), elements.clone());
+                Expression::Array {
+                    token,
+                    elements: self.copy_all(program, &elements, t)?,
+                }
+            }
+            Expression::Hash { token, pairs } => {
+                let (token, pairs) = (t.token(token), pairs.clone());
+                let mut copies = Vec::new();
+                for (key, value) in pairs {
+                    copies.push((self.copy(program, key, t)?, self.copy(program, value, t)?));
+                }
+                Expression::Hash {
+                    token,
+                    pairs: copies,
+                }
+            }
+            Expression::Index { token, left, index } => {
+                let (token, left, index) = (t.token(token), *left, *index);
+                Expression::Index {
+                    token,
+                    left: self.copy(program, left, t)?,
+                    index: self.copy(program, index, t)?,
+                }
+            }
+            Expression::Struct {
+                token,
+                name,
+                fields,
+            } => {
+                let (token, name) = (t.token(token), t.ident(name));
+                let fields: Vec<_> = fields
+                    .iter()
+                    .map(|(field, value)| (t.ident(field), *value))
+                    .collect();
+                let mut copies = Vec::new();
+                for (field, value) in fields {
+                    copies.push((field, self.copy(program, value, t)?));
+                }
+                Expression::Struct {
+                    token,
+                    name,
+                    fields: copies,
+                }
+            }
+            Expression::Field {
+                token,
+                object,
+                field,
+            } => {
+                let (token, object, field) = (t.token(token), *object, t.ident(field));
+                Expression::Field {
+                    token,
+                    object: self.copy(program, object, t)?,
+                    field,
+                }
+            }
+            Expression::Range { token, start, end } => {
+                let (token, start, end) = (t.token(token), *start, *end);
+                Expression::Range {
+                    token,
+                    start: match start {
+                        Some(start) => Some(self.copy(program, start, t)?),
+                        None => None,
+                    },
+                    end: match end {
+                        Some(end) => Some(self.copy(program, end, t)?),
+                        None => None,
+                    },
+                }
+            }
+            Expression::Variant {
+                token,
+                enum_name,
+                variant,
+                args,
+            } => {
+                let token = t.token(token);
+                let (enum_name, variant) = (t.ident(enum_name), t.ident(variant));
+                let args = args.clone();
+                Expression::Variant {
+                    token,
+                    enum_name,
+                    variant,
+                    args: self.copy_all(program, &args, t)?,
+                }
+            }
+        };
+        Ok(program.alloc_expression(expression))
+    }
+
+    fn copy_all(
+        &mut self,
+        program: &mut Program,
+        ids: &[ExprId],
+        template: &Template,
+    ) -> Result<Vec<ExprId>, MacroError> {
+        ids.iter()
+            .map(|id| self.copy(program, *id, template))
+            .collect()
+    }
+
+    /// Replace the default values of copied parameters by copies
+    fn copy_defaults(
+        &mut self,
+        program: &mut Program,
+        mut params: Vec<Parameter>,
+        template: &Template,
+    ) -> Result<Vec<Parameter>, MacroError> {
+        for param in &mut params {
+            if let Some(default) = param.default {
+                param.default = Some(self.copy(program, default, template)?);
+            }
+        }
+        Ok(params)
+    }
+
+    /// Copy a block given by [`Template::block`]
+    fn copy_block(
+        &mut self,
+        program: &mut Program,
+        (token, end, statements): (Token, usize, Vec<StmtId>),
+        template: &Template,
+    ) -> Result<BlockStatement, MacroError> {
+        let mut template = template.clone();
+        let mut copies = Vec::new();
+        for id in statements {
+            copies.push(self.copy_statement(program, id, &mut template)?);
+        }
+        Ok(BlockStatement::new(token, copies, end))
+    }
+
+    /// Copy a statement, the names it binds are in the scope of `template`
+    /// afterwards
+    fn copy_statement(
+        &mut self,
+        program: &mut Program,
+        id: StmtId,
+        template: &mut Template,
+    ) -> Result<StmtId, MacroError> {
+        let t = template;
+        let stmt = match &program[id] {
+            Statement::Let(stmt) => {
+                let inner = self.bind(t, stmt.pattern.bindings());
+                let (token, pattern, value) = (
+                    t.token(&stmt.token),
+                    inner.pattern(&stmt.pattern),
+                    stmt.value,
+                );
+                let annotation = stmt.annotation.as_ref().map(|a| t.annotation(a));
+                let value = self.copy(program, value, t)?;
+                *t = inner;
+                Statement::Let(LetStatement::new(token, pattern, annotation, value))
+            }
+            Statement::Const(stmt) => {
+                let inner = self.bind(t, [&stmt.name]);
+                let (token, name, value) =
+                    (t.token(&stmt.token), inner.variable(&stmt.name), stmt.value);
+                let annotation = stmt.annotation.as_ref().map(|a| t.annotation(a));
+                let value = self.copy(program, value, t)?;
+                *t = inner;
+                Statement::Const(ConstStatement::new(token, name, annotation, value))
+            }
+            Statement::Return(stmt) => {
+                let (token, value) = (t.token(&stmt.token), stmt.value);
+                Statement::Return(ReturnStatement::new(token, self.copy(program, value, t)?))
+            }
+            Statement::Expression(stmt) => {
+                let (token, expression) = (t.token(&stmt.token), stmt.expression);
+                let expression = self.copy(program, expression, t)?;
+                Statement::Expression(ExpressionStatement::new(token, expression))
+            }
+            Statement::Break(stmt) => {
+                let (token, value) = (t.token(&stmt.token), stmt.value);
+                let value = match value {
+                    Some(value) => Some(self.copy(program, value, t)?),
+                    None => None,
+                };
+                Statement::Break(BreakStatement::new(token, value))
+            }
+            Statement::Continue(stmt) => {
+                Statement::Continue(ContinueStatement::new(t.token(&stmt.token)))
+            }
+            Statement::Struct(stmt) => Statement::Struct(StructStatement::new(
+                t.token(&stmt.token),
+                t.ident(&stmt.name),
+                stmt.fields.iter().map(|field| t.ident(field)).collect(),
+            )),
+            Statement::Enum(stmt) => Statement::Enum(EnumStatement::new(
+                t.token(&stmt.token),
+                t.ident(&stmt.name),
+                stmt.variants
+                    .iter()
+                    .map(|variant| {
+                        Variant::new(
+                            t.ident(&variant.name),
+                            variant.fields.iter().map(|field| t.ident(field)).collect(),
+                        )
+                    })
+                    .collect(),
+            )),
+            Statement::Import(stmt) => Statement::Import(ImportStatement::new(
+                t.token(&stmt.token),
+                t.token(&stmt.path_token),
+                stmt.path.clone(),
+                match &stmt.items {
+                    ImportItems::Module(name) => ImportItems::Module(t.ident(name)),
+                    ImportItems::Names(names) => {
+                        ImportItems::Names(names.iter().map(|name| t.ident(name)).collect())
+                    }
+                },
+            )),
+            Statement::Export(stmt) => {
+                let (token, declaration) = (t.token(&stmt.token), stmt.declaration);
+                let declaration = self.copy_statement(program, declaration, t)?;
+                Statement::Export(ExportStatement::new(token, declaration))
+            }
+        };
+        Ok(program.alloc_statement(stmt))
+    }
+}
+
+impl VisitorMut for Expander {
+    /// Calls are expanded before their arguments, which are passed as they
+    /// are written, then the expansion is visited in turn
+    fn visit_expression_mut(&mut self, program: &mut Program, id: ExprId) {
+        if let Expression::Macro { token, .. } = &program[id] {
+            self.errors
+                .push(MacroError::MisplacedMacro { span: token.span() });
+            return;
+        }
+        let mut expansions = 0;
+        while !self.exhausted {
+            let Some((name, span, _)) = self.called_macro(program, id) else {
+                break;
+            };
+            if self.depth + expansions == MAX_DEPTH {
+                self.errors.push(MacroError::RecursionLimit { name, span });
+                self.exhausted = true;
+                break;
+            }
+            if !self.expand_call(program, id) {
+                break;
+            }
+            expansions += 1;
+        }
+        self.depth += expansions;
+        visit::walk_expression_mut(self, program, id);
+        self.depth -= expansions;
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn expand(input: &str) -> (Program, Vec<MacroError>) {
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer, "test");
+        let mut program = parser.parse();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        let mut expander = Expander::new();
+        expander.expand(&mut program);
+        (program, expander.errors().to_vec())
+    }
+
+    #[test]
+    fn expand_macros() {
+        let input = r#"let unless = macro(condition, consequence, alternative) {
+    quote(if (!(unquote(condition))) { unquote(consequence); } else { unquote(alternative); })
+};
+let twice = macro(x) { let n = 1 + 1; quote(unquote(n) * unquote(x)) };
+unless(10 > 5, puts("not greater"), puts("greater"));
+twice(twice(y + 1));"#;
+
+        let (program, errors) = expand(input);
+
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            program.to_string(),
+            r#"if (!(10 > 5)) {
+    puts("not greater");
+} else {
+    puts("greater");
+};
+2 * (2 * (y + 1));"#
+        );
+    }
+
+    #[test]
+    fn expansions_are_hygienic() {
+        let input = "let each = macro(xs, f) { quote(fn(x) { for i in unquote(xs) { unquote(f)(i + x) } }) };
+let x = [1];
+let g = each(x, |y| x);";
+
+        let (program, errors) = expand(input);
+
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            program.to_string(),
+            "let x = [1];
+let g = fn(x#0) {
+    for i#1 in x {
+        fn(y) {
+            x;
+        }(i#1 + x#0);
+    };
+};"
+        );
+    }
+
+    #[test]
+    fn only_bound_names_are_renamed() {
+        let input = "let m = macro(a) { quote([fn(tmp) { tmp + unquote(a) }, tmp]) };
+let n = macro(c) { quote(if (unquote(c)) { let x = 1; x } else { x }) };
+let tmp = 5;
+m(1);
+n(true);";
+
+        let (program, errors) = expand(input);
+
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            program.to_string(),
+            "let tmp = 5;
+[fn(tmp#0) {
+    tmp#0 + 1;
+}, tmp];
+if (true) {
+    let x#1 = 1;
+    x#1;
+} else {
+    x;
+};"
+        );
+    }
+
+    #[test]
+    fn expansions_point_to_the_call() {
+        let input =
+            "let double = macro(x) {\n    quote(unquote(x) + unquote(x))\n};\n\nf(double(a));";
+
+        let (program, _) = expand(input);
+
+        let stmt = program.statements[0];
+        let Statement::Expression(stmt) = &program[stmt] else {
+            panic!("expected an expression statement")
+        };
+        let Expression::Call { args, .. } = &program[stmt.expression] else {
+            panic!("expected a call")
+        };
+        let Expression::Infix { token, left, right } = &program[args[0]] else {
+            panic!("expected an infix expression, got {:?}", program[args[0]])
+        };
+        assert_eq!(
+            token.span(),
+            Span {
+                start: 65,
+                end: 71,
+                line: 5,
+                column: 3
+            }
+        );
+        assert_eq!(program[*left].token().span().start, 72);
+        assert_eq!(program[*right].token().span().start, 72);
+        assert_ne!(left, right);
+    }
+
+    #[test]
+    fn expansion_errors() {
+        let input = "let m = macro(x) { quote(unquote(x)) };
+let bad = macro(x) { f(x) };
+let plain = macro(x) { 1 + 1 };
+let sum = macro(x) { quote(unquote(x + 1)) };
+let forever = macro(x) { quote(forever(unquote(x))) };
+m(1, 2);
+bad(1);
+plain(1);
+sum(2);
+forever(0);
+let inner = fn() { macro() { quote(1) } };";
+
+        let (_, errors) = expand(input);
+
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "macro 'm' takes 1 argument(s), got 2",
+                "can't evaluate this while expanding a macro",
+                "macro 'plain' doesn't return quoted code",
+                "can't expand the macro, '+' can't be applied to quoted code",
+                "macro 'forever' is expanded more than 64 times in a row",
+                "macro literal outside of a top-level let",
+            ]
+        );
+        let lines: Vec<usize> = errors.iter().map(|e| e.span().line).collect();
+        assert_eq!(lines, [6, 2, 8, 4, 10, 11]);
+        let codes: Vec<&str> = errors.iter().map(|e| e.code()).collect();
+        assert_eq!(
+            codes,
+            ["E0301", "E0302", "E0304", "E0303", "E0306", "E0305"]
+        );
+    }
+    #[test]
+    fn expand_default_named_and_rest_arguments() {
+        let input = "let add = macro(x, y = 1) { quote(unquote(x) + unquote(y)) };
+let all = macro(first, ...rest) { quote(f(unquote(first), unquote(rest))) };
+add(2);
+add(y: 3, x: 4);
+all(1, 2, 3);
+all(1);";
+
+        let (program, errors) = expand(input);
+
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            program.to_string(),
+            "2 + 1;\n4 + 3;\nf(1, [2, 3]);\nf(1, []);"
+        );
+    }
+
+    #[test]
+    fn macro_argument_errors() {
+        let input = "let m = macro(x, y, z = 1) { quote(unquote(x)) };
+m(1);
+m(1, 2, w: 3);
+m(1, 2, x: 3);
+m(...xs);";
+
+        let (_, errors) = expand(input);
+
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "macro 'm' is missing argument(s) for 'y'",
+                "macro 'm' has no parameter named 'w'",
+                "argument 'x' is given more than once",
+                "can't evaluate this while expanding a macro",
+            ]
+        );
+        let codes: Vec<&str> = errors.iter().map(|e| e.code()).collect();
+        assert_eq!(codes, ["E0308", "E0307", "E0309", "E0302"]);
+        let columns: Vec<usize> = errors.iter().map(|e| e.span().column).collect();
+        assert_eq!(columns, [1, 9, 9, 3]);
+    }
+}
@@ -0,0 +1,919 @@
+//! Conversion of the AST to JSON and S-expressions, for tools that don't
+//! link against the crate
+//!
+//! Statements, blocks and expressions carry the span of their whole source,
+//! the other nodes that of their first token. In S-expressions the span is
+//! appended to the node kind, `(infix@0..5 + (ident@0..1 a) (int@4..5 1))`.
+
+use super::{
+    BlockStatement, ExprId, Expression, Identifier, ImportItems, Literal, Parameter, Pattern,
+    Program, Statement, StmtId, TypeAnnotation,
+};
+use crate::json::Json;
+use crate::sexp::Sexp;
+use crate::token::{Span, TokenType};
+
+impl From<&Program> for Json {
+    fn from(program: &Program) -> Self {
+        let statements = program
+            .statements
+            .iter()
+            .map(|stmt| program.statement_json(*stmt))
+            .collect();
+        Json::object([
+            ("kind", "Program".into()),
+            ("statements", Json::Array(statements)),
+        ])
+    }
+}
+
+impl From<&Identifier> for Json {
+    fn from(ident: &Identifier) -> Self {
+        Json::object([
+            ("kind", "Identifier".into()),
+            ("span", ident.token.span().into()),
+            ("name", ident.value.as_str().into()),
+        ])
+    }
+}
+
+impl From<&TypeAnnotation> for Json {
+    fn from(annotation: &TypeAnnotation) -> Self {
+        Json::object([
+            ("kind", "Type".into()),
+            ("span", annotation.name.token.span().into()),
+            ("name", annotation.name.value.as_str().into()),
+            (
+                "args",
+                Json::Array(annotation.args.iter().map(Json::from).collect()),
+            ),
+        ])
+    }
+}
+
+impl From<&Pattern> for Json {
+    fn from(pattern: &Pattern) -> Self {
+        let node = |kind: &str| {
+            Json::object([
+                ("kind", kind.into()),
+                ("span", pattern.token().span().into()),
+            ])
+        };
+        match pattern {
+            Pattern::Wildcard(_) => node("WildcardPattern"),
+            Pattern::Binding { name, is_mut } => node("BindingPattern")
+                .with("mut", *is_mut)
+                .with("name", name.value.as_str()),
+            Pattern::Rest { name, is_mut, .. } => node("RestPattern")
+                .with("mut", *is_mut)
+                .with("name", name.as_ref().map(|name| name.value.as_str())),
+            Pattern::Literal { value, .. } => node("LiteralPattern").with("value", value),
+            Pattern::Array { elements, .. } => node("ArrayPattern").with(
+                "elements",
+                Json::Array(elements.iter().map(Json::from).collect()),
+            ),
+            Pattern::Struct { name, fields, .. } => {
+                let fields = fields
+                    .iter()
+                    .map(|(field, pattern)| {
+                        Json::object([("name", field.into()), ("pattern", pattern.into())])
+                    })
+                    .collect();
+                node("StructPattern")
+                    .with("name", name.as_ref())
+                    .with("fields", Json::Array(fields))
+            }
+            Pattern::Variant {
+                enum_name,
+                variant,
+                fields,
+            } => node("VariantPattern")
+                .with("enum", enum_name)
+                .with("variant", variant)
+                .with(
+                    "fields",
+                    Json::Array(fields.iter().map(Json::from).collect()),
+                ),
+        }
+    }
+}
+
+impl From<&Literal> for Json {
+    fn from(literal: &Literal) -> Self {
+        match literal {
+            Literal::Integer(value) => Json::from(*value),
+            Literal::Float(value) => Json::from(*value),
+            Literal::Boolean(value) => Json::from(*value),
+            Literal::String(value) => Json::from(value.as_str()),
+        }
+    }
+}
+
+impl From<&Program> for Sexp {
+    fn from(program: &Program) -> Self {
+        let mut items = vec![Sexp::atom("program")];
+        items.extend(
+            program
+                .statements
+                .iter()
+                .map(|stmt| program.statement_sexp(*stmt)),
+        );
+        Sexp::List(items)
+    }
+}
+
+impl From<&Identifier> for Sexp {
+    fn from(ident: &Identifier) -> Self {
+        Sexp::List(vec![
+            head("ident", ident.token.span()),
+            Sexp::atom(&ident.value),
+        ])
+    }
+}
+
+impl From<&TypeAnnotation> for Sexp {
+    fn from(annotation: &TypeAnnotation) -> Self {
+        let mut items = vec![
+            head("type", annotation.name.token.span()),
+            Sexp::atom(&annotation.name.value),
+        ];
+        items.extend(annotation.args.iter().map(Sexp::from));
+        Sexp::List(items)
+    }
+}
+
+impl From<&Pattern> for Sexp {
+    fn from(pattern: &Pattern) -> Self {
+        let span = pattern.token().span();
+        match pattern {
+            Pattern::Wildcard(_) => Sexp::List(vec![head("wildcard", span)]),
+            Pattern::Binding { name, is_mut } => {
+                let mut items = vec![head("bind", span)];
+                if *is_mut {
+                    items.push(Sexp::atom("mut"));
+                }
+                items.push(Sexp::atom(&name.value));
+                Sexp::List(items)
+            }
+            Pattern::Rest { name, is_mut, .. } => {
+                let mut items = vec![head("rest", span)];
+                if *is_mut {
+                    items.push(Sexp::atom("mut"));
+                }
+                items.extend(name.iter().map(|name| Sexp::atom(&name.value)));
+                Sexp::List(items)
+            }
+            Pattern::Literal { value, .. } => {
+                let value = match value {
+                    Literal::Float(value) => Sexp::atom(format!("{:?}", value)),
+                    Literal::String(value) => Sexp::String(value.clone()),
+                    value => Sexp::atom(value),
+                };
+                Sexp::List(vec![head("literal", span), value])
+            }
+            Pattern::Array { elements, .. } => {
+                let mut items = vec![head("array", span)];
+                items.extend(elements.iter().map(Sexp::from));
+                Sexp::List(items)
+            }
+            Pattern::Struct { name, fields, .. } => {
+                let mut items = vec![head("struct", span)];
+                items.extend(name.iter().map(|name| Sexp::atom(&name.value)));
+                items.extend(
+                    fields
+                        .iter()
+                        .map(|(field, pattern)| Sexp::List(vec![field.into(), pattern.into()])),
+                );
+                Sexp::List(items)
+            }
+            Pattern::Variant {
+                enum_name,
+                variant,
+                fields,
+            } => {
+                let mut items = vec![
+                    head("variant", span),
+                    Sexp::atom(&enum_name.value),
+                    Sexp::atom(&variant.value),
+                ];
+                items.extend(fields.iter().map(Sexp::from));
+                Sexp::List(items)
+            }
+        }
+    }
+}
+
+impl Program {
+    /// An identifier, with a `type`, `rest` or `default` when the parameter
+    /// has one
+    fn param_json(&self, param: &Parameter) -> Json {
+        let json = Json::from(&param.name);
+        let json = match &param.annotation {
+            Some(annotation) => json.with("type", annotation),
+            None => json,
+        };
+        let json = if param.is_rest {
+            json.with("rest", true)
+        } else {
+            json
+        };
+        match param.default {
+            Some(default) => json.with("default", self.expression_json(default)),
+            None => json,
+        }
+    }
+    fn params_json(&self, params: &[Parameter]) -> Json {
+        Json::Array(params.iter().map(|param| self.param_json(param)).collect())
+    }
+
+    fn statement_json(&self, id: StmtId) -> Json {
+        let span = self.statement_span(id);
+        match &self[id] {
+            // A single name keeps the shape it had before let took patterns
+            Statement::Let(stmt) => {
+                let json = Json::object([("kind", "Let".into()), ("span", span.into())]);
+                let json = match &stmt.pattern {
+                    Pattern::Binding { name, is_mut } => {
+                        json.with("mut", *is_mut).with("name", name)
+                    }
+                    pattern => json.with("pattern", pattern),
+                };
+                let json = match &stmt.annotation {
+                    Some(annotation) => json.with("type", annotation),
+                    None => json,
+                };
+                json.with("value", self.expression_json(stmt.value))
+            }
+            Statement::Const(stmt) => {
+                let json = Json::object([
+                    ("kind", "Const".into()),
+                    ("span", span.into()),
+                    ("name", (&stmt.name).into()),
+                ]);
+                let json = match &stmt.annotation {
+                    Some(annotation) => json.with("type", annotation),
+                    None => json,
+                };
+                json.with("value", self.expression_json(stmt.value))
+            }
+            Statement::Return(stmt) => Json::object([
+                ("kind", "Return".into()),
+                ("span", span.into()),
+                ("value", self.expression_json(stmt.value)),
+            ]),
+            Statement::Expression(stmt) => Json::object([
+                ("kind", "Expression".into()),
+                ("span", span.into()),
+                ("expression", self.expression_json(stmt.expression)),
+            ]),
+            Statement::Break(stmt) => Json::object([
+                ("kind", "Break".into()),
+                ("span", span.into()),
+                (
+                    "value",
+                    stmt.value
+                        .map_or(Json::Null, |value| self.expression_json(value)),
+                ),
+            ]),
+            Statement::Continue(_) => {
+                Json::object([("kind", "Continue".into()), ("span", span.into())])
+            }
+            Statement::Struct(stmt) => Json::object([
+                ("kind", "Struct".into()),
+                ("span", span.into()),
+                ("name", (&stmt.name).into()),
+                (
+                    "fields",
+                    Json::Array(stmt.fields.iter().map(Json::from).collect()),
+                ),
+            ]),
+            Statement::Enum(stmt) => {
+                let variants = stmt
+                    .variants
+                    .iter()
+                    .map(|variant| {
+                        Json::object([
+                            ("name", (&variant.name).into()),
+                            (
+                                "fields",
+                                Json::Array(variant.fields.iter().map(Json::from).collect()),
+                            ),
+                        ])
+                    })
+                    .collect();
+                Json::object([
+                    ("kind", "Enum".into()),
+                    ("span", span.into()),
+                    ("name", (&stmt.name).into()),
+                    ("variants", Json::Array(variants)),
+                ])
+            }
+            Statement::Import(stmt) => {
+                let json = Json::object([
+                    ("kind", "Import".into()),
+                    ("span", span.into()),
+                    ("path", stmt.path.as_str().into()),
+                ]);
+                match &stmt.items {
+                    ImportItems::Module(name) => json.with("alias", name),
+                    ImportItems::Names(names) => {
+                        json.with("names", Json::Array(names.iter().map(Json::from).collect()))
+                    }
+                }
+            }
+            Statement::Export(stmt) => Json::object([
+                ("kind", "Export".into()),
+                ("span", span.into()),
+                ("declaration", self.statement_json(stmt.declaration)),
+            ]),
+        }
+    }
+
+    fn block_json(&self, block: &BlockStatement) -> Json {
+        let statements = block
+            .statements
+            .iter()
+            .map(|stmt| self.statement_json(*stmt))
+            .collect();
+        Json::object([
+            ("kind", "Block".into()),
+            ("span", block.span().into()),
+            ("statements", Json::Array(statements)),
+        ])
+    }
+
+    fn expressions_json(&self, ids: &[ExprId]) -> Json {
+        Json::Array(ids.iter().map(|id| self.expression_json(*id)).collect())
+    }
+
+    fn expression_json(&self, id: ExprId) -> Json {
+        let span = self.expression_span(id);
+        let node = |kind: &str, fields: Vec<(&str, Json)>| {
+            let mut pairs = vec![
+                ("kind".to_string(), kind.into()),
+                ("span".to_string(), span.into()),
+            ];
+            pairs.extend(
+                fields
+                    .into_iter()
+                    .map(|(key, value)| (key.to_string(), value)),
+            );
+            Json::Object(pairs)
+        };
+        match &self[id] {
+            Expression::Identifier(ident) => ident.into(),
+            Expression::Integer { value, .. } => node("Integer", vec![("value", (*value).into())]),
+            Expression::Float { value, .. } => node("Float", vec![("value", (*value).into())]),
+            Expression::Boolean { value, .. } => node("Boolean", vec![("value", (*value).into())]),
+            Expression::String { value, .. } => {
+                node("String", vec![("value", value.as_str().into())])
+            }
+            Expression::Prefix { token, right } => node(
+                "Prefix",
+                vec![
+                    ("operator", token.token_type.to_string().into()),
+                    ("right", self.expression_json(*right)),
+                ],
+            ),
+            Expression::Infix { token, left, right } => node(
+                "Infix",
+                vec![
+                    ("operator", token.token_type.to_string().into()),
+                    ("left", self.expression_json(*left)),
+                    ("right", self.expression_json(*right)),
+                ],
+            ),
+            Expression::Assign { target, value, .. } => node(
+                "Assign",
+                vec![
+                    ("target", self.expression_json(*target)),
+                    ("value", self.expression_json(*value)),
+                ],
+            ),
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+                ..
+            } => node(
+                "If",
+                vec![
+                    ("condition", self.expression_json(*condition)),
+                    ("consequence", self.block_json(consequence)),
+                    (
+                        "alternative",
+                        alternative
+                            .as_ref()
+                            .map_or(Json::Null, |block| self.block_json(block)),
+                    ),
+                ],
+            ),
+            Expression::While {
+                condition, body, ..
+            } => node(
+                "While",
+                vec![
+                    ("condition", self.expression_json(*condition)),
+                    ("body", self.block_json(body)),
+                ],
+            ),
+            Expression::For {
+                variable,
+                iterable,
+                body,
+                ..
+            } => node(
+                "For",
+                vec![
+                    ("variable", variable.into()),
+                    ("iterable", self.expression_json(*iterable)),
+                    ("body", self.block_json(body)),
+                ],
+            ),
+            Expression::Loop { body, .. } => node("Loop", vec![("body", self.block_json(body))]),
+            Expression::Match { subject, arms, .. } => {
+                let arms = arms
+                    .iter()
+                    .map(|arm| {
+                        Json::object([
+                            ("pattern", (&arm.pattern).into()),
+                            (
+                                "guard",
+                                arm.guard
+                                    .map_or(Json::Null, |guard| self.expression_json(guard)),
+                            ),
+                            ("body", self.expression_json(arm.body)),
+                        ])
+                    })
+                    .collect();
+                node(
+                    "Match",
+                    vec![
+                        ("subject", self.expression_json(*subject)),
+                        ("arms", Json::Array(arms)),
+                    ],
+                )
+            }
+            Expression::Function {
+                params,
+                return_type,
+                body,
+                ..
+            } => {
+                let mut fields = vec![("params", self.params_json(params))];
+                fields.extend(return_type.as_ref().map(|ty| ("returns", ty.into())));
+                fields.push(("body", self.block_json(body)));
+                node("Function", fields)
+            }
+            Expression::Macro { params, body, .. } => node(
+                "Macro",
+                vec![
+                    ("params", self.params_json(params)),
+                    ("body", self.block_json(body)),
+                ],
+            ),
+            Expression::Call {
+                callee,
+                args,
+                named,
+                ..
+            } => {
+                let mut fields = vec![
+                    ("callee", self.expression_json(*callee)),
+                    ("args", self.expressions_json(args)),
+                ];
+                if !named.is_empty() {
+                    let named = named
+                        .iter()
+                        .map(|(name, value)| {
+                            Json::object([
+                                ("name", name.into()),
+                                ("value", self.expression_json(*value)),
+                            ])
+                        })
+                        .collect();
+                    fields.push(("named", Json::Array(named)));
+                }
+                node("Call", fields)
+            }
+            Expression::Spread { value, .. } => {
+                node("Spread", vec![("value", self.expression_json(*value))])
+            }
+            Expression::Array { elements, .. } => {
+                node("Array", vec![("elements", self.expressions_json(elements))])
+            }
+            Expression::Hash { pairs, .. } => {
+                let pairs = pairs
+                    .iter()
+                    .map(|(key, value)| {
+                        Json::object([
+                            ("key", self.expression_json(*key)),
+                            ("value", self.expression_json(*value)),
+                        ])
+                    })
+                    .collect();
+                node("Hash", vec![("pairs", Json::Array(pairs))])
+            }
+            Expression::Index { left, index, .. } => node(
+                "Index",
+                vec![
+                    ("left", self.expression_json(*left)),
+                    ("index", self.expression_json(*index)),
+                ],
+            ),
+            Expression::Struct { name, fields, .. } => {
+                let fields = fields
+                    .iter()
+                    .map(|(field, value)| {
+                        Json::object([
+                            ("name", field.into()),
+                            ("value", self.expression_json(*value)),
+                        ])
+                    })
+                    .collect();
+                node(
+                    "StructLiteral",
+                    vec![("name", name.into()), ("fields", Json::Array(fields))],
+                )
+            }
+            Expression::Field { object, field, .. } => node(
+                "Field",
+                vec![
+                    ("object", self.expression_json(*object)),
+                    ("field", field.into()),
+                ],
+            ),
+            Expression::Range { token, start, end } => node(
+                "Range",
+                vec![
+                    (
+                        "start",
+                        start.map_or(Json::Null, |start| self.expression_json(start)),
+                    ),
+                    (
+                        "end",
+                        end.map_or(Json::Null, |end| self.expression_json(end)),
+                    ),
+                    (
+                        "inclusive",
+                        (token.token_type == TokenType::DotDotEq).into(),
+                    ),
+                ],
+            ),
+            Expression::Variant {
+                enum_name,
+                variant,
+                args,
+                ..
+            } => node(
+                "Variant",
+                vec![
+                    ("enum", enum_name.into()),
+                    ("variant", variant.into()),
+                    ("args", self.expressions_json(args)),
+                ],
+            ),
+        }
+    }
+
+    /// `(ident x)`, or a list of `...` for a rest parameter, the
+    /// identifier, the type and the default value the parameter has
+    fn param_sexp(&self, param: &Parameter) -> Sexp {
+        if param.annotation.is_none() && param.default.is_none() && !param.is_rest {
+            return (&param.name).into();
+        }
+        let mut items = Vec::new();
+        if param.is_rest {
+            items.push(Sexp::atom("..."));
+        }
+        items.push((&param.name).into());
+        items.extend(param.annotation.as_ref().map(Sexp::from));
+        items.extend(param.default.map(|default| self.expression_sexp(default)));
+        Sexp::List(items)
+    }
+    fn params_sexp(&self, params: &[Parameter]) -> Sexp {
+        let mut items = vec![Sexp::atom("params")];
+        items.extend(params.iter().map(|param| self.param_sexp(param)));
+        Sexp::List(items)
+    }
+
+    fn statement_sexp(&self, id: StmtId) -> Sexp {
+        let span = self.statement_span(id);
+        match &self[id] {
+            Statement::Let(stmt) => {
+                let mut items = vec![head("let", span)];
+                match &stmt.pattern {
+                    Pattern::Binding { name, is_mut } => {
+                        if *is_mut {
+                            items.push(Sexp::atom("mut"));
+                        }
+                        items.push(name.into());
+                    }
+                    pattern => items.push(pattern.into()),
+                }
+                items.extend(stmt.annotation.as_ref().map(Sexp::from));
+                items.push(self.expression_sexp(stmt.value));
+                Sexp::List(items)
+            }
+            Statement::Const(stmt) => {
+                let mut items = vec![head("const", span), (&stmt.name).into()];
+                items.extend(stmt.annotation.as_ref().map(Sexp::from));
+                items.push(self.expression_sexp(stmt.value));
+                Sexp::List(items)
+            }
+            Statement::Return(stmt) => {
+                Sexp::List(vec![head("return", span), self.expression_sexp(stmt.value)])
+            }
+            Statement::Expression(stmt) => Sexp::List(vec![
+                head("expr", span),
+                self.expression_sexp(stmt.expression),
+            ]),
+            Statement::Break(stmt) => {
+                let mut items = vec![head("break", span)];
+                items.extend(stmt.value.map(|value| self.expression_sexp(value)));
+                Sexp::List(items)
+            }
+            Statement::Continue(_) => Sexp::List(vec![head("continue", span)]),
+            Statement::Struct(stmt) => {
+                let mut items = vec![head("struct", span), (&stmt.name).into()];
+                items.extend(stmt.fields.iter().map(Sexp::from));
+                Sexp::List(items)
+            }
+            Statement::Enum(stmt) => {
+                let mut items = vec![head("enum", span), (&stmt.name).into()];
+                items.extend(stmt.variants.iter().map(|variant| {
+                    let mut fields = vec![(&variant.name).into()];
+                    fields.extend(variant.fields.iter().map(Sexp::from));
+                    Sexp::List(fields)
+                }));
+                Sexp::List(items)
+            }
+            Statement::Import(stmt) => {
+                let items = match &stmt.items {
+                    ImportItems::Module(name) => name.into(),
+                    ImportItems::Names(names) => Sexp::List(names.iter().map(Sexp::from).collect()),
+                };
+                Sexp::List(vec![
+                    head("import", span),
+                    Sexp::String(stmt.path.clone()),
+                    items,
+                ])
+            }
+            Statement::Export(stmt) => Sexp::List(vec![
+                head("export", span),
+                self.statement_sexp(stmt.declaration),
+            ]),
+        }
+    }
+
+    fn block_sexp(&self, block: &BlockStatement) -> Sexp {
+        let mut items = vec![head("block", block.span())];
+        items.extend(
+            block
+                .statements
+                .iter()
+                .map(|stmt| self.statement_sexp(*stmt)),
+        );
+        Sexp::List(items)
+    }
+
+    fn expression_sexp(&self, id: ExprId) -> Sexp {
+        let span = self.expression_span(id);
+        let node = |kind: &str, children: Vec<Sexp>| {
+            let mut items = vec![head(kind, span)];
+            items.extend(children);
+            Sexp::List(items)
+        };
+        let sexp = |id: &ExprId| self.expression_sexp(*id);
+        match &self[id] {
+            Expression::Identifier(ident) => ident.into(),
+            Expression::Integer { value, .. } => node("int", vec![Sexp::atom(value)]),
+            Expression::Float { value, .. } => {
+                node("float", vec![Sexp::atom(format!("{:?}", value))])
+            }
+            Expression::Boolean { value, .. } => node("bool", vec![Sexp::atom(value)]),
+            Expression::String { value, .. } => node("string", vec![Sexp::String(value.clone())]),
+            Expression::Prefix { token, right } => {
+                node("prefix", vec![Sexp::atom(&token.token_type), sexp(right)])
+            }
+            Expression::Infix { token, left, right } => node(
+                "infix",
+                vec![Sexp::atom(&token.token_type), sexp(left), sexp(right)],
+            ),
+            Expression::Assign { target, value, .. } => {
+                node("assign", vec![sexp(target), sexp(value)])
+            }
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+                ..
+            } => {
+                let mut children = vec![sexp(condition), self.block_sexp(consequence)];
+                children.extend(alternative.iter().map(|block| self.block_sexp(block)));
+                node("if", children)
+            }
+            Expression::While {
+                condition, body, ..
+            } => node("while", vec![sexp(condition), self.block_sexp(body)]),
+            Expression::For {
+                variable,
+                iterable,
+                body,
+                ..
+            } => node(
+                "for",
+                vec![variable.into(), sexp(iterable), self.block_sexp(body)],
+            ),
+            Expression::Loop { body, .. } => node("loop", vec![self.block_sexp(body)]),
+            Expression::Match { subject, arms, .. } => {
+                let mut children = vec![sexp(subject)];
+                children.extend(arms.iter().map(|arm| {
+                    let mut items = vec![Sexp::atom("arm"), (&arm.pattern).into()];
+                    items.extend(
+                        arm.guard
+                            .map(|guard| Sexp::List(vec![Sexp::atom("guard"), sexp(&guard)])),
+                    );
+                    items.push(sexp(&arm.body));
+                    Sexp::List(items)
+                }));
+                node("match", children)
+            }
+            Expression::Function {
+                params,
+                return_type,
+                body,
+                ..
+            } => {
+                let mut children = vec![self.params_sexp(params)];
+                children.extend(return_type.as_ref().map(Sexp::from));
+                children.push(self.block_sexp(body));
+                node("fn", children)
+            }
+            Expression::Macro { params, body, .. } => node(
+                "macro",
+                vec![self.params_sexp(params), self.block_sexp(body)],
+            ),
+            Expression::Call {
+                callee,
+                args,
+                named,
+                ..
+            } => {
+                let mut children = vec![sexp(callee)];
+                children.extend(args.iter().map(sexp));
+                if !named.is_empty() {
+                    let mut items = vec![Sexp::atom("named")];
+                    items.extend(
+                        named
+                            .iter()
+                            .map(|(name, value)| Sexp::List(vec![name.into(), sexp(value)])),
+                    );
+                    children.push(Sexp::List(items));
+                }
+                node("call", children)
+            }
+            Expression::Spread { value, .. } => node("spread", vec![sexp(value)]),
+            Expression::Array { elements, .. } => {
+                node("array", elements.iter().map(sexp).collect())
+            }
+            Expression::Hash { pairs, .. } => {
+                let pairs = pairs
+                    .iter()
+                    .map(|(key, value)| Sexp::List(vec![sexp(key), sexp(value)]))
+                    .collect();
+                node("hash", pairs)
+            }
+            Expression::Index { left, index, .. } => node("index", vec![sexp(left), sexp(index)]),
+            Expression::Struct { name, fields, .. } => {
+                let mut children = vec![name.into()];
+                children.extend(
+                    fields
+                        .iter()
+                        .map(|(field, value)| Sexp::List(vec![field.into(), sexp(value)])),
+                );
+                node("struct-literal", children)
+            }
+            Expression::Field { object, field, .. } => {
+                node("field", vec![sexp(object), field.into()])
+            }
+            Expression::Variant {
+                enum_name,
+                variant,
+                args,
+                ..
+            } => {
+                let mut children = vec![enum_name.into(), variant.into()];
+                children.extend(args.iter().map(sexp));
+                node("variant", children)
+            }
+            // A missing bound is `()`
+            Expression::Range { token, start, end } => {
+                let bound =
+                    |bound: &Option<ExprId>| bound.as_ref().map_or(Sexp::List(vec![]), sexp);
+                node(
+                    "range",
+                    vec![Sexp::atom(&token.token_type), bound(start), bound(end)],
+                )
+            }
+        }
+    }
+}
+
+/// The first atom of a node, its kind and its span
+fn head(kind: &str, span: Span) -> Sexp {
+    Sexp::Atom(format!("{}@{}..{}", kind, span.start, span.end))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer, "test");
+        let p = parser.parse();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        p
+    }
+
+    #[test]
+    fn serialize_json() {
+        let program = parse("let mut a = -b;");
+
+        assert_eq!(
+            Json::from(&program).to_string(),
+            concat!(
+                r#"{"kind":"Program","statements":[{"kind":"Let","#,
+                r#""span":{"start":0,"end":15,"line":1,"column":1},"mut":true,"#,
+                r#""name":{"kind":"Identifier","span":{"start":8,"end":9,"line":1,"column":9},"name":"a"},"#,
+                r#""value":{"kind":"Prefix","span":{"start":12,"end":14,"line":1,"column":13},"operator":"-","#,
+                r#""right":{"kind":"Identifier","span":{"start":13,"end":14,"line":1,"column":14},"name":"b"}}}]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn serialize_sexp() {
+        let program = parse(r#"if (a) { f(1.0, "s")[0] } else { {true: [x]} };"#);
+
+        assert_eq!(
+            Sexp::from(&program).to_string(),
+            concat!(
+                r#"(program (expr@0..47 (if@0..46 (ident@4..5 a) (block@7..25 (expr@9..23 "#,
+                r#"(index@9..23 (call@9..20 (ident@9..10 f) (float@11..14 1.0) (string@16..19 "s")) "#,
+                r#"(int@21..22 0)))) (block@31..46 (expr@33..44 (hash@33..44 "#,
+                r#"((bool@34..38 true) (array@40..43 (ident@41..42 x)))))))))"#
+            )
+        );
+    }
+    #[test]
+    fn serialize_destructuring() {
+        let program = parse("let [mut a, ...] = b; let { x } = c;");
+
+        assert_eq!(
+            Sexp::from(&program).to_string(),
+            concat!(
+                "(program (let@0..21 (array@4..5 (bind@9..10 mut a) (rest@12..15)) (ident@19..20 b)) ",
+                "(let@22..36 (struct@26..27 ((ident@28..29 x) (bind@28..29 x))) (ident@34..35 c)))"
+            )
+        );
+        let json = Json::from(&program).to_string();
+        assert!(json.contains(r#""kind":"RestPattern","span":{"start":12,"end":15,"line":1,"column":13},"mut":false,"name":null"#));
+        assert!(json.contains(r#""kind":"StructPattern","span":{"start":26,"end":27,"line":1,"column":27},"name":null"#));
+    }
+    #[test]
+    fn serialize_annotations() {
+        let program = parse("let x: array<int> = fn(a: int) -> int { a };");
+
+        assert_eq!(
+            Sexp::from(&program).to_string(),
+            concat!(
+                "(program (let@0..44 (ident@4..5 x) (type@7..12 array (type@13..16 int)) ",
+                "(fn@20..43 (params ((ident@23..24 a) (type@26..29 int))) (type@34..37 int) ",
+                "(block@38..43 (expr@40..41 (ident@40..41 a))))))"
+            )
+        );
+        let json = Json::from(&program).to_string();
+        assert!(json.contains(r#""type":{"kind":"Type","span":{"start":7,"end":12,"line":1,"column":8},"name":"array","args":[{"kind":"Type""#));
+        assert!(json.contains(r#""name":"a","type":{"kind":"Type""#));
+        assert!(json.contains(r#""returns":{"kind":"Type""#));
+    }
+    #[test]
+    fn serialize_calls() {
+        let program = parse("f(1, y: 2) |> g(...xs);");
+
+        assert_eq!(
+            Sexp::from(&program).to_string(),
+            concat!(
+                "(program (expr@0..23 (call@0..22 (ident@14..15 g) ",
+                "(call@0..10 (ident@0..1 f) (int@2..3 1) (named ((ident@5..6 y) (int@8..9 2)))) ",
+                "(spread@16..21 (ident@19..21 xs)))))"
+            )
+        );
+        let json = Json::from(&program).to_string();
+        assert!(json.contains(r#""kind":"Call","span":{"start":0,"end":10,"line":1,"column":1}"#));
+    }
+}
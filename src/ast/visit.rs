@@ -0,0 +1,1128 @@
+//! Traversal of the AST
+//!
+//! Every node has a `visit_*` method whose default implementation calls the
+//! matching `walk_*` function, which visits the children of the node. A pass
+//! overrides the methods of the nodes it cares about and calls `walk_*` from
+//! them to keep descending into the tree.
+//!
+//! [`Visitor`] borrows the program. [`VisitorMut`] can change it in place,
+//! its methods for nodes with children take the program and the id of the
+//! node so that the pass is free to replace any node of the arena.
+
+use super::{
+    BlockStatement, BreakStatement, ConstStatement, ContinueStatement, EnumStatement,
+    ExportStatement, ExprId, Expression, ExpressionStatement, Identifier, ImportItems,
+    ImportStatement, LetStatement, MatchArm, Parameter, Pattern, Program, ReturnStatement,
+    Statement, StmtId, StructStatement, TypeAnnotation,
+};
+use crate::token::Token;
+
+pub trait Visitor: Sized {
+    fn visit_program(&mut self, program: &Program) {
+        walk_program(self, program)
+    }
+    fn visit_statement(&mut self, program: &Program, id: StmtId) {
+        walk_statement(self, program, id)
+    }
+    fn visit_let(&mut self, program: &Program, stmt: &LetStatement) {
+        walk_let(self, program, stmt)
+    }
+    fn visit_const(&mut self, program: &Program, stmt: &ConstStatement) {
+        walk_const(self, program, stmt)
+    }
+    fn visit_return(&mut self, program: &Program, stmt: &ReturnStatement) {
+        walk_return(self, program, stmt)
+    }
+    fn visit_expression_stmt(&mut self, program: &Program, stmt: &ExpressionStatement) {
+        walk_expression_stmt(self, program, stmt)
+    }
+    fn visit_break(&mut self, program: &Program, stmt: &BreakStatement) {
+        walk_break(self, program, stmt)
+    }
+    fn visit_continue(&mut self, _stmt: &ContinueStatement) {}
+    fn visit_struct_decl(&mut self, stmt: &StructStatement) {
+        walk_struct_decl(self, stmt)
+    }
+    fn visit_enum_decl(&mut self, stmt: &EnumStatement) {
+        walk_enum_decl(self, stmt)
+    }
+    fn visit_import(&mut self, stmt: &ImportStatement) {
+        walk_import(self, stmt)
+    }
+    fn visit_export(&mut self, program: &Program, stmt: &ExportStatement) {
+        walk_export(self, program, stmt)
+    }
+    fn visit_block(&mut self, program: &Program, block: &BlockStatement) {
+        walk_block(self, program, block)
+    }
+    fn visit_expression(&mut self, program: &Program, id: ExprId) {
+        walk_expression(self, program, id)
+    }
+    fn visit_identifier(&mut self, _ident: &Identifier) {}
+    fn visit_type_annotation(&mut self, _annotation: &TypeAnnotation) {}
+    fn visit_integer(&mut self, _token: &Token, _value: i64) {}
+    fn visit_float(&mut self, _token: &Token, _value: f64) {}
+    fn visit_boolean(&mut self, _token: &Token, _value: bool) {}
+    fn visit_string(&mut self, _token: &Token, _value: &str) {}
+    fn visit_prefix(&mut self, program: &Program, token: &Token, right: ExprId) {
+        walk_prefix(self, program, token, right)
+    }
+    fn visit_infix(&mut self, program: &Program, token: &Token, left: ExprId, right: ExprId) {
+        walk_infix(self, program, token, left, right)
+    }
+    fn visit_assign(&mut self, program: &Program, token: &Token, target: ExprId, value: ExprId) {
+        walk_assign(self, program, token, target, value)
+    }
+    fn visit_if(
+        &mut self,
+        program: &Program,
+        token: &Token,
+        condition: ExprId,
+        consequence: &BlockStatement,
+        alternative: Option<&BlockStatement>,
+    ) {
+        walk_if(self, program, token, condition, consequence, alternative)
+    }
+    fn visit_while(
+        &mut self,
+        program: &Program,
+        token: &Token,
+        condition: ExprId,
+        body: &BlockStatement,
+    ) {
+        walk_while(self, program, token, condition, body)
+    }
+    fn visit_for(
+        &mut self,
+        program: &Program,
+        token: &Token,
+        variable: &Identifier,
+        iterable: ExprId,
+        body: &BlockStatement,
+    ) {
+        walk_for(self, program, token, variable, iterable, body)
+    }
+    fn visit_loop(&mut self, program: &Program, token: &Token, body: &BlockStatement) {
+        walk_loop(self, program, token, body)
+    }
+    fn visit_match(
+        &mut self,
+        program: &Program,
+        token: &Token,
+        subject: ExprId,
+        arms: &[MatchArm],
+    ) {
+        walk_match(self, program, token, subject, arms)
+    }
+    fn visit_pattern(&mut self, pattern: &Pattern) {
+        walk_pattern(self, pattern)
+    }
+    fn visit_function(
+        &mut self,
+        program: &Program,
+        token: &Token,
+        params: &[Parameter],
+        return_type: Option<&TypeAnnotation>,
+        body: &BlockStatement,
+    ) {
+        walk_function(self, program, token, params, return_type, body)
+    }
+    fn visit_macro(
+        &mut self,
+        program: &Program,
+        token: &Token,
+        params: &[Parameter],
+        body: &BlockStatement,
+    ) {
+        walk_macro(self, program, token, params, body)
+    }
+    fn visit_call(
+        &mut self,
+        program: &Program,
+        token: &Token,
+        callee: ExprId,
+        args: &[ExprId],
+        named: &[(Identifier, ExprId)],
+    ) {
+        walk_call(self, program, token, callee, args, named)
+    }
+    fn visit_spread(&mut self, program: &Program, token: &Token, value: ExprId) {
+        walk_spread(self, program, token, value)
+    }
+    fn visit_array(&mut self, program: &Program, token: &Token, elements: &[ExprId]) {
+        walk_array(self, program, token, elements)
+    }
+    fn visit_hash(&mut self, program: &Program, token: &Token, pairs: &[(ExprId, ExprId)]) {
+        walk_hash(self, program, token, pairs)
+    }
+    fn visit_index(&mut self, program: &Program, token: &Token, left: ExprId, index: ExprId) {
+        walk_index(self, program, token, left, index)
+    }
+    fn visit_struct(
+        &mut self,
+        program: &Program,
+        token: &Token,
+        name: &Identifier,
+        fields: &[(Identifier, ExprId)],
+    ) {
+        walk_struct(self, program, token, name, fields)
+    }
+    fn visit_field(
+        &mut self,
+        program: &Program,
+        token: &Token,
+        object: ExprId,
+        field: &Identifier,
+    ) {
+        walk_field(self, program, token, object, field)
+    }
+    fn visit_range(
+        &mut self,
+        program: &Program,
+        token: &Token,
+        start: Option<ExprId>,
+        end: Option<ExprId>,
+    ) {
+        walk_range(self, program, token, start, end)
+    }
+    fn visit_variant(
+        &mut self,
+        program: &Program,
+        token: &Token,
+        enum_name: &Identifier,
+        variant: &Identifier,
+        args: &[ExprId],
+    ) {
+        walk_variant(self, program, token, enum_name, variant, args)
+    }
+}
+
+pub fn walk_program<V: Visitor>(visitor: &mut V, program: &Program) {
+    for stmt in &program.statements {
+        visitor.visit_statement(program, *stmt);
+    }
+}
+
+pub fn walk_statement<V: Visitor>(visitor: &mut V, program: &Program, id: StmtId) {
+    match &program[id] {
+        Statement::Let(stmt) => visitor.visit_let(program, stmt),
+        Statement::Const(stmt) => visitor.visit_const(program, stmt),
+        Statement::Return(stmt) => visitor.visit_return(program, stmt),
+        Statement::Expression(stmt) => visitor.visit_expression_stmt(program, stmt),
+        Statement::Break(stmt) => visitor.visit_break(program, stmt),
+        Statement::Continue(stmt) => visitor.visit_continue(stmt),
+        Statement::Struct(stmt) => visitor.visit_struct_decl(stmt),
+        Statement::Enum(stmt) => visitor.visit_enum_decl(stmt),
+        Statement::Import(stmt) => visitor.visit_import(stmt),
+        Statement::Export(stmt) => visitor.visit_export(program, stmt),
+    }
+}
+
+pub fn walk_let<V: Visitor>(visitor: &mut V, program: &Program, stmt: &LetStatement) {
+    visitor.visit_pattern(&stmt.pattern);
+    if let Some(annotation) = &stmt.annotation {
+        visitor.visit_type_annotation(annotation);
+    }
+    visitor.visit_expression(program, stmt.value);
+}
+
+pub fn walk_const<V: Visitor>(visitor: &mut V, program: &Program, stmt: &ConstStatement) {
+    visitor.visit_identifier(&stmt.name);
+    if let Some(annotation) = &stmt.annotation {
+        visitor.visit_type_annotation(annotation);
+    }
+    visitor.visit_expression(program, stmt.value);
+}
+
+pub fn walk_return<V: Visitor>(visitor: &mut V, program: &Program, stmt: &ReturnStatement) {
+    visitor.visit_expression(program, stmt.value);
+}
+
+pub fn walk_expression_stmt<V: Visitor>(
+    visitor: &mut V,
+    program: &Program,
+    stmt: &ExpressionStatement,
+) {
+    visitor.visit_expression(program, stmt.expression);
+}
+
+pub fn walk_break<V: Visitor>(visitor: &mut V, program: &Program, stmt: &BreakStatement) {
+    if let Some(value) = stmt.value {
+        visitor.visit_expression(program, value);
+    }
+}
+
+pub fn walk_struct_decl<V: Visitor>(visitor: &mut V, stmt: &StructStatement) {
+    visitor.visit_identifier(&stmt.name);
+    for field in &stmt.fields {
+        visitor.visit_identifier(field);
+    }
+}
+
+pub fn walk_enum_decl<V: Visitor>(visitor: &mut V, stmt: &EnumStatement) {
+    visitor.visit_identifier(&stmt.name);
+    for variant in &stmt.variants {
+        visitor.visit_identifier(&variant.name);
+        for field in &variant.fields {
+            visitor.visit_identifier(field);
+        }
+    }
+}
+
+pub fn walk_import<V: Visitor>(visitor: &mut V, stmt: &ImportStatement) {
+    match &stmt.items {
+        ImportItems::Module(name) => visitor.visit_identifier(name),
+        ImportItems::Names(names) => {
+            for name in names {
+                visitor.visit_identifier(name);
+            }
+        }
+    }
+}
+
+pub fn walk_export<V: Visitor>(visitor: &mut V, program: &Program, stmt: &ExportStatement) {
+    visitor.visit_statement(program, stmt.declaration);
+}
+
+pub fn walk_block<V: Visitor>(visitor: &mut V, program: &Program, block: &BlockStatement) {
+    for stmt in &block.statements {
+        visitor.visit_statement(program, *stmt);
+    }
+}
+
+pub fn walk_expression<V: Visitor>(visitor: &mut V, program: &Program, id: ExprId) {
+    match &program[id] {
+        Expression::Identifier(ident) => visitor.visit_identifier(ident),
+        Expression::Integer { token, value } => visitor.visit_integer(token, *value),
+        Expression::Float { token, value } => visitor.visit_float(token, *value),
+        Expression::Boolean { token, value } => visitor.visit_boolean(token, *value),
+        Expression::String { token, value } => visitor.visit_string(token, value),
+        Expression::Prefix { token, right } => visitor.visit_prefix(program, token, *right),
+        Expression::Infix { token, left, right } => {
+            visitor.visit_infix(program, token, *left, *right)
+        }
+        Expression::Assign {
+            token,
+            target,
+            value,
+        } => visitor.visit_assign(program, token, *target, *value),
+        Expression::If {
+            token,
+            condition,
+            consequence,
+            alternative,
+        } => visitor.visit_if(
+            program,
+            token,
+            *condition,
+            consequence,
+            alternative.as_ref(),
+        ),
+        Expression::While {
+            token,
+            condition,
+            body,
+        } => visitor.visit_while(program, token, *condition, body),
+        Expression::For {
+            token,
+            variable,
+            iterable,
+            body,
+        } => visitor.visit_for(program, token, variable, *iterable, body),
+        Expression::Loop { token, body } => visitor.visit_loop(program, token, body),
+        Expression::Match {
+            token,
+            subject,
+            arms,
+        } => visitor.visit_match(program, token, *subject, arms),
+        Expression::Function {
+            token,
+            params,
+            return_type,
+            body,
+        } => visitor.visit_function(program, token, params, return_type.as_ref(), body),
+        Expression::Macro {
+            token,
+            params,
+            body,
+        } => visitor.visit_macro(program, token, params, body),
+        Expression::Call {
+            token,
+            callee,
+            args,
+            named,
+        } => visitor.visit_call(program, token, *callee, args, named),
+        Expression::Spread { token, value } => visitor.visit_spread(program, token, *value),
+        Expression::Array { token, elements } => visitor.visit_array(program, token, elements),
+        Expression::Hash { token, pairs } => visitor.visit_hash(program, token, pairs),
+        Expression::Index { token, left, index } => {
+            visitor.visit_index(program, token, *left, *index)
+        }
+        Expression::Struct {
+            token,
+            name,
+            fields,
+        } => visitor.visit_struct(program, token, name, fields),
+        Expression::Field {
+            token,
+            object,
+            field,
+        } => visitor.visit_field(program, token, *object, field),
+        Expression::Variant {
+            token,
+            enum_name,
+            variant,
+            args,
+        } => visitor.visit_variant(program, token, enum_name, variant, args),
+        Expression::Range { token, start, end } => {
+            visitor.visit_range(program, token, *start, *end)
+        }
+    }
+}
+
+pub fn walk_prefix<V: Visitor>(visitor: &mut V, program: &Program, _token: &Token, right: ExprId) {
+    visitor.visit_expression(program, right);
+}
+
+pub fn walk_infix<V: Visitor>(
+    visitor: &mut V,
+    program: &Program,
+    _token: &Token,
+    left: ExprId,
+    right: ExprId,
+) {
+    visitor.visit_expression(program, left);
+    visitor.visit_expression(program, right);
+}
+
+pub fn walk_assign<V: Visitor>(
+    visitor: &mut V,
+    program: &Program,
+    _token: &Token,
+    target: ExprId,
+    value: ExprId,
+) {
+    visitor.visit_expression(program, target);
+    visitor.visit_expression(program, value);
+}
+
+pub fn walk_if<V: Visitor>(
+    visitor: &mut V,
+    program: &Program,
+    _token: &Token,
+    condition: ExprId,
+    consequence: &BlockStatement,
+    alternative: Option<&BlockStatement>,
+) {
+    visitor.visit_expression(program, condition);
+    visitor.visit_block(program, consequence);
+    if let Some(alternative) = alternative {
+        visitor.visit_block(program, alternative);
+    }
+}
+
+pub fn walk_while<V: Visitor>(
+    visitor: &mut V,
+    program: &Program,
+    _token: &Token,
+    condition: ExprId,
+    body: &BlockStatement,
+) {
+    visitor.visit_expression(program, condition);
+    visitor.visit_block(program, body);
+}
+
+pub fn walk_for<V: Visitor>(
+    visitor: &mut V,
+    program: &Program,
+    _token: &Token,
+    variable: &Identifier,
+    iterable: ExprId,
+    body: &BlockStatement,
+) {
+    visitor.visit_identifier(variable);
+    visitor.visit_expression(program, iterable);
+    visitor.visit_block(program, body);
+}
+
+pub fn walk_loop<V: Visitor>(
+    visitor: &mut V,
+    program: &Program,
+    _token: &Token,
+    body: &BlockStatement,
+) {
+    visitor.visit_block(program, body);
+}
+
+pub fn walk_match<V: Visitor>(
+    visitor: &mut V,
+    program: &Program,
+    _token: &Token,
+    subject: ExprId,
+    arms: &[MatchArm],
+) {
+    visitor.visit_expression(program, subject);
+    for arm in arms {
+        visitor.visit_pattern(&arm.pattern);
+        if let Some(guard) = arm.guard {
+            visitor.visit_expression(program, guard);
+        }
+        visitor.visit_expression(program, arm.body);
+    }
+}
+
+pub fn walk_pattern<V: Visitor>(visitor: &mut V, pattern: &Pattern) {
+    match pattern {
+        Pattern::Wildcard(_) | Pattern::Literal { .. } => {}
+        Pattern::Binding { name, .. } => visitor.visit_identifier(name),
+        Pattern::Rest { name, .. } => {
+            if let Some(name) = name {
+                visitor.visit_identifier(name);
+            }
+        }
+        Pattern::Array { elements, .. } => {
+            for element in elements {
+                visitor.visit_pattern(element);
+            }
+        }
+        Pattern::Struct { name, fields, .. } => {
+            if let Some(name) = name {
+                visitor.visit_identifier(name);
+            }
+            for (_, pattern) in fields {
+                visitor.visit_pattern(pattern);
+            }
+        }
+        Pattern::Variant {
+            enum_name, fields, ..
+        } => {
+            visitor.visit_identifier(enum_name);
+            for field in fields {
+                visitor.visit_pattern(field);
+            }
+        }
+    }
+}
+
+pub fn walk_function<V: Visitor>(
+    visitor: &mut V,
+    program: &Program,
+    _token: &Token,
+    params: &[Parameter],
+    return_type: Option<&TypeAnnotation>,
+    body: &BlockStatement,
+) {
+    for param in params {
+        visitor.visit_identifier(&param.name);
+        if let Some(annotation) = &param.annotation {
+            visitor.visit_type_annotation(annotation);
+        }
+        if let Some(default) = param.default {
+            visitor.visit_expression(program, default);
+        }
+    }
+    if let Some(return_type) = return_type {
+        visitor.visit_type_annotation(return_type);
+    }
+    visitor.visit_block(program, body);
+}
+
+pub fn walk_macro<V: Visitor>(
+    visitor: &mut V,
+    program: &Program,
+    token: &Token,
+    params: &[Parameter],
+    body: &BlockStatement,
+) {
+    walk_function(visitor, program, token, params, None, body)
+}
+
+pub fn walk_call<V: Visitor>(
+    visitor: &mut V,
+    program: &Program,
+    _token: &Token,
+    callee: ExprId,
+    args: &[ExprId],
+    named: &[(Identifier, ExprId)],
+) {
+    visitor.visit_expression(program, callee);
+    for arg in args {
+        visitor.visit_expression(program, *arg);
+    }
+    for (_, value) in named {
+        visitor.visit_expression(program, *value);
+    }
+}
+
+pub fn walk_spread<V: Visitor>(visitor: &mut V, program: &Program, _token: &Token, value: ExprId) {
+    visitor.visit_expression(program, value);
+}
+
+pub fn walk_array<V: Visitor>(
+    visitor: &mut V,
+    program: &Program,
+    _token: &Token,
+    elements: &[ExprId],
+) {
+    for element in elements {
+        visitor.visit_expression(program, *element);
+    }
+}
+
+pub fn walk_hash<V: Visitor>(
+    visitor: &mut V,
+    program: &Program,
+    _token: &Token,
+    pairs: &[(ExprId, ExprId)],
+) {
+    for (key, value) in pairs {
+        visitor.visit_expression(program, *key);
+        visitor.visit_expression(program, *value);
+    }
+}
+
+pub fn walk_index<V: Visitor>(
+    visitor: &mut V,
+    program: &Program,
+    _token: &Token,
+    left: ExprId,
+    index: ExprId,
+) {
+    visitor.visit_expression(program, left);
+    visitor.visit_expression(program, index);
+}
+
+/// The field names are not visited, only the name of the struct and the values
+pub fn walk_struct<V: Visitor>(
+    visitor: &mut V,
+    program: &Program,
+    _token: &Token,
+    name: &Identifier,
+    fields: &[(Identifier, ExprId)],
+) {
+    visitor.visit_identifier(name);
+    for (_, value) in fields {
+        visitor.visit_expression(program, *value);
+    }
+}
+
+pub fn walk_field<V: Visitor>(
+    visitor: &mut V,
+    program: &Program,
+    _token: &Token,
+    object: ExprId,
+    _field: &Identifier,
+) {
+    visitor.visit_expression(program, object);
+}
+
+pub fn walk_range<V: Visitor>(
+    visitor: &mut V,
+    program: &Program,
+    _token: &Token,
+    start: Option<ExprId>,
+    end: Option<ExprId>,
+) {
+    for bound in start.into_iter().chain(end) {
+        visitor.visit_expression(program, bound);
+    }
+}
+
+/// The variant name is not visited, only the name of the enum and the values
+pub fn walk_variant<V: Visitor>(
+    visitor: &mut V,
+    program: &Program,
+    _token: &Token,
+    enum_name: &Identifier,
+    _variant: &Identifier,
+    args: &[ExprId],
+) {
+    visitor.visit_identifier(enum_name);
+    for arg in args {
+        visitor.visit_expression(program, *arg);
+    }
+}
+
+pub trait VisitorMut: Sized {
+    fn visit_program_mut(&mut self, program: &mut Program) {
+        walk_program_mut(self, program)
+    }
+    fn visit_statement_mut(&mut self, program: &mut Program, id: StmtId) {
+        walk_statement_mut(self, program, id)
+    }
+    fn visit_let_mut(&mut self, program: &mut Program, id: StmtId) {
+        walk_let_mut(self, program, id)
+    }
+    fn visit_const_mut(&mut self, program: &mut Program, id: StmtId) {
+        walk_const_mut(self, program, id)
+    }
+    fn visit_return_mut(&mut self, program: &mut Program, id: StmtId) {
+        walk_return_mut(self, program, id)
+    }
+    fn visit_expression_stmt_mut(&mut self, program: &mut Program, id: StmtId) {
+        walk_expression_stmt_mut(self, program, id)
+    }
+    fn visit_break_mut(&mut self, program: &mut Program, id: StmtId) {
+        walk_break_mut(self, program, id)
+    }
+    fn visit_continue_mut(&mut self, _stmt: &mut ContinueStatement) {}
+    fn visit_struct_decl_mut(&mut self, stmt: &mut StructStatement) {
+        walk_struct_decl_mut(self, stmt)
+    }
+    fn visit_enum_decl_mut(&mut self, stmt: &mut EnumStatement) {
+        walk_enum_decl_mut(self, stmt)
+    }
+    fn visit_import_mut(&mut self, stmt: &mut ImportStatement) {
+        walk_import_mut(self, stmt)
+    }
+    fn visit_export_mut(&mut self, program: &mut Program, id: StmtId) {
+        walk_export_mut(self, program, id)
+    }
+    fn visit_block_mut(&mut self, program: &mut Program, statements: &[StmtId]) {
+        walk_block_mut(self, program, statements)
+    }
+    /// Override this one to replace whole expressions
+    fn visit_expression_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_expression_mut(self, program, id)
+    }
+    fn visit_identifier_mut(&mut self, _ident: &mut Identifier) {}
+    fn visit_type_annotation_mut(&mut self, _annotation: &mut TypeAnnotation) {}
+    fn visit_integer_mut(&mut self, _token: &mut Token, _value: &mut i64) {}
+    fn visit_float_mut(&mut self, _token: &mut Token, _value: &mut f64) {}
+    fn visit_boolean_mut(&mut self, _token: &mut Token, _value: &mut bool) {}
+    fn visit_string_mut(&mut self, _token: &mut Token, _value: &mut String) {}
+    fn visit_prefix_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_children_mut(self, program, id)
+    }
+    fn visit_infix_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_children_mut(self, program, id)
+    }
+    fn visit_assign_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_children_mut(self, program, id)
+    }
+    fn visit_if_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_children_mut(self, program, id)
+    }
+    fn visit_while_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_children_mut(self, program, id)
+    }
+    fn visit_for_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_children_mut(self, program, id)
+    }
+    fn visit_loop_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_children_mut(self, program, id)
+    }
+    fn visit_match_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_children_mut(self, program, id)
+    }
+    fn visit_pattern_mut(&mut self, pattern: &mut Pattern) {
+        walk_pattern_mut(self, pattern)
+    }
+    fn visit_function_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_children_mut(self, program, id)
+    }
+    fn visit_macro_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_children_mut(self, program, id)
+    }
+    fn visit_call_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_children_mut(self, program, id)
+    }
+    fn visit_spread_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_children_mut(self, program, id)
+    }
+    fn visit_array_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_children_mut(self, program, id)
+    }
+    fn visit_hash_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_children_mut(self, program, id)
+    }
+    fn visit_index_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_children_mut(self, program, id)
+    }
+    fn visit_struct_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_children_mut(self, program, id)
+    }
+    fn visit_field_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_children_mut(self, program, id)
+    }
+    fn visit_variant_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_children_mut(self, program, id)
+    }
+    fn visit_range_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_children_mut(self, program, id)
+    }
+}
+
+pub fn walk_program_mut<V: VisitorMut>(visitor: &mut V, program: &mut Program) {
+    for stmt in program.statements.clone() {
+        visitor.visit_statement_mut(program, stmt);
+    }
+}
+
+pub fn walk_statement_mut<V: VisitorMut>(visitor: &mut V, program: &mut Program, id: StmtId) {
+    match &mut program[id] {
+        Statement::Let(_) => visitor.visit_let_mut(program, id),
+        Statement::Const(_) => visitor.visit_const_mut(program, id),
+        Statement::Return(_) => visitor.visit_return_mut(program, id),
+        Statement::Expression(_) => visitor.visit_expression_stmt_mut(program, id),
+        Statement::Break(_) => visitor.visit_break_mut(program, id),
+        Statement::Continue(stmt) => visitor.visit_continue_mut(stmt),
+        Statement::Struct(stmt) => visitor.visit_struct_decl_mut(stmt),
+        Statement::Enum(stmt) => visitor.visit_enum_decl_mut(stmt),
+        Statement::Import(stmt) => visitor.visit_import_mut(stmt),
+        Statement::Export(_) => visitor.visit_export_mut(program, id),
+    }
+}
+
+pub fn walk_let_mut<V: VisitorMut>(visitor: &mut V, program: &mut Program, id: StmtId) {
+    if let Statement::Let(stmt) = &mut program[id] {
+        visitor.visit_pattern_mut(&mut stmt.pattern);
+        if let Some(annotation) = &mut stmt.annotation {
+            visitor.visit_type_annotation_mut(annotation);
+        }
+        let value = stmt.value;
+        visitor.visit_expression_mut(program, value);
+    }
+}
+
+pub fn walk_const_mut<V: VisitorMut>(visitor: &mut V, program: &mut Program, id: StmtId) {
+    if let Statement::Const(stmt) = &mut program[id] {
+        visitor.visit_identifier_mut(&mut stmt.name);
+        if let Some(annotation) = &mut stmt.annotation {
+            visitor.visit_type_annotation_mut(annotation);
+        }
+        let value = stmt.value;
+        visitor.visit_expression_mut(program, value);
+    }
+}
+
+pub fn walk_return_mut<V: VisitorMut>(visitor: &mut V, program: &mut Program, id: StmtId) {
+    if let Statement::Return(stmt) = &program[id] {
+        visitor.visit_expression_mut(program, stmt.value);
+    }
+}
+
+pub fn walk_expression_stmt_mut<V: VisitorMut>(visitor: &mut V, program: &mut Program, id: StmtId) {
+    if let Statement::Expression(stmt) = &program[id] {
+        visitor.visit_expression_mut(program, stmt.expression);
+    }
+}
+
+pub fn walk_break_mut<V: VisitorMut>(visitor: &mut V, program: &mut Program, id: StmtId) {
+    if let Statement::Break(stmt) = &program[id] {
+        if let Some(value) = stmt.value {
+            visitor.visit_expression_mut(program, value);
+        }
+    }
+}
+
+pub fn walk_struct_decl_mut<V: VisitorMut>(visitor: &mut V, stmt: &mut StructStatement) {
+    visitor.visit_identifier_mut(&mut stmt.name);
+    for field in &mut stmt.fields {
+        visitor.visit_identifier_mut(field);
+    }
+}
+
+pub fn walk_enum_decl_mut<V: VisitorMut>(visitor: &mut V, stmt: &mut EnumStatement) {
+    visitor.visit_identifier_mut(&mut stmt.name);
+    for variant in &mut stmt.variants {
+        visitor.visit_identifier_mut(&mut variant.name);
+        for field in &mut variant.fields {
+            visitor.visit_identifier_mut(field);
+        }
+    }
+}
+
+pub fn walk_import_mut<V: VisitorMut>(visitor: &mut V, stmt: &mut ImportStatement) {
+    match &mut stmt.items {
+        ImportItems::Module(name) => visitor.visit_identifier_mut(name),
+        ImportItems::Names(names) => {
+            for name in names {
+                visitor.visit_identifier_mut(name);
+            }
+        }
+    }
+}
+
+pub fn walk_export_mut<V: VisitorMut>(visitor: &mut V, program: &mut Program, id: StmtId) {
+    if let Statement::Export(stmt) = &program[id] {
+        visitor.visit_statement_mut(program, stmt.declaration);
+    }
+}
+
+pub fn walk_block_mut<V: VisitorMut>(
+    visitor: &mut V,
+    program: &mut Program,
+    statements: &[StmtId],
+) {
+    for stmt in statements {
+        visitor.visit_statement_mut(program, *stmt);
+    }
+}
+
+pub fn walk_expression_mut<V: VisitorMut>(visitor: &mut V, program: &mut Program, id: ExprId) {
+    match &mut program[id] {
+        Expression::Identifier(ident) => visitor.visit_identifier_mut(ident),
+        Expression::Integer { token, value } => visitor.visit_integer_mut(token, value),
+        Expression::Float { token, value } => visitor.visit_float_mut(token, value),
+        Expression::Boolean { token, value } => visitor.visit_boolean_mut(token, value),
+        Expression::String { token, value } => visitor.visit_string_mut(token, value),
+        Expression::Prefix { .. } => visitor.visit_prefix_mut(program, id),
+        Expression::Infix { .. } => visitor.visit_infix_mut(program, id),
+        Expression::Assign { .. } => visitor.visit_assign_mut(program, id),
+        Expression::If { .. } => visitor.visit_if_mut(program, id),
+        Expression::While { .. } => visitor.visit_while_mut(program, id),
+        Expression::For { .. } => visitor.visit_for_mut(program, id),
+        Expression::Loop { .. } => visitor.visit_loop_mut(program, id),
+        Expression::Match { .. } => visitor.visit_match_mut(program, id),
+        Expression::Function { .. } => visitor.visit_function_mut(program, id),
+        Expression::Macro { .. } => visitor.visit_macro_mut(program, id),
+        Expression::Call { .. } => visitor.visit_call_mut(program, id),
+        Expression::Spread { .. } => visitor.visit_spread_mut(program, id),
+        Expression::Array { .. } => visitor.visit_array_mut(program, id),
+        Expression::Hash { .. } => visitor.visit_hash_mut(program, id),
+        Expression::Index { .. } => visitor.visit_index_mut(program, id),
+        Expression::Struct { .. } => visitor.visit_struct_mut(program, id),
+        Expression::Field { .. } => visitor.visit_field_mut(program, id),
+        Expression::Variant { .. } => visitor.visit_variant_mut(program, id),
+        Expression::Range { .. } => visitor.visit_range_mut(program, id),
+    }
+}
+
+pub fn walk_pattern_mut<V: VisitorMut>(visitor: &mut V, pattern: &mut Pattern) {
+    match pattern {
+        Pattern::Wildcard(_) | Pattern::Literal { .. } => {}
+        Pattern::Binding { name, .. } => visitor.visit_identifier_mut(name),
+        Pattern::Rest { name, .. } => {
+            if let Some(name) = name {
+                visitor.visit_identifier_mut(name);
+            }
+        }
+        Pattern::Array { elements, .. } => {
+            for element in elements {
+                visitor.visit_pattern_mut(element);
+            }
+        }
+        Pattern::Struct { name, fields, .. } => {
+            if let Some(name) = name {
+                visitor.visit_identifier_mut(name);
+            }
+            for (_, pattern) in fields {
+                visitor.visit_pattern_mut(pattern);
+            }
+        }
+        Pattern::Variant {
+            enum_name, fields, ..
+        } => {
+            visitor.visit_identifier_mut(enum_name);
+            for field in fields {
+                visitor.visit_pattern_mut(field);
+            }
+        }
+    }
+}
+
+/// Visit the children of an expression in source order, the walk of every
+/// expression with children
+pub fn walk_children_mut<V: VisitorMut>(visitor: &mut V, program: &mut Program, id: ExprId) {
+    let (expressions, blocks) = match &mut program[id] {
+        Expression::Identifier(_)
+        | Expression::Integer { .. }
+        | Expression::Float { .. }
+        | Expression::Boolean { .. }
+        | Expression::String { .. } => (vec![], vec![]),
+        Expression::Prefix { right, .. } => (vec![*right], vec![]),
+        Expression::Infix { left, right, .. } => (vec![*left, *right], vec![]),
+        Expression::Assign { target, value, .. } => (vec![*target, *value], vec![]),
+        Expression::If {
+            condition,
+            consequence,
+            alternative,
+            ..
+        } => {
+            let mut blocks = vec![consequence.statements.clone()];
+            blocks.extend(alternative.iter().map(|block| block.statements.clone()));
+            (vec![*condition], blocks)
+        }
+        Expression::While {
+            condition, body, ..
+        } => (vec![*condition], vec![body.statements.clone()]),
+        Expression::For {
+            variable,
+            iterable,
+            body,
+            ..
+        } => {
+            visitor.visit_identifier_mut(variable);
+            (vec![*iterable], vec![body.statements.clone()])
+        }
+        Expression::Loop { body, .. } => (vec![], vec![body.statements.clone()]),
+        // The patterns are visited first, the guards and bodies can't be
+        // borrowed along with them
+        Expression::Match { subject, arms, .. } => {
+            let mut expressions = vec![*subject];
+            for arm in arms {
+                visitor.visit_pattern_mut(&mut arm.pattern);
+                expressions.extend(arm.guard);
+                expressions.push(arm.body);
+            }
+            (expressions, vec![])
+        }
+        Expression::Function {
+            params,
+            return_type,
+            body,
+            ..
+        } => {
+            for param in params.iter_mut() {
+                visitor.visit_identifier_mut(&mut param.name);
+                if let Some(annotation) = &mut param.annotation {
+                    visitor.visit_type_annotation_mut(annotation);
+                }
+            }
+            if let Some(return_type) = return_type {
+                visitor.visit_type_annotation_mut(return_type);
+            }
+            let defaults = params.iter().filter_map(|param| param.default).collect();
+            (defaults, vec![body.statements.clone()])
+        }
+        Expression::Macro { params, body, .. } => {
+            for param in params.iter_mut() {
+                visitor.visit_identifier_mut(&mut param.name);
+                if let Some(annotation) = &mut param.annotation {
+                    visitor.visit_type_annotation_mut(annotation);
+                }
+            }
+            let defaults = params.iter().filter_map(|param| param.default).collect();
+            (defaults, vec![body.statements.clone()])
+        }
+        Expression::Call {
+            callee,
+            args,
+            named,
+            ..
+        } => (
+            std::iter::once(*callee)
+                .chain(args.iter().copied())
+                .chain(named.iter().map(|(_, value)| *value))
+                .collect(),
+            vec![],
+        ),
+        Expression::Spread { value, .. } => (vec![*value], vec![]),
+        Expression::Array { elements, .. } => (elements.clone(), vec![]),
+        Expression::Hash { pairs, .. } => (
+            pairs
+                .iter()
+                .flat_map(|(key, value)| [*key, *value])
+                .collect(),
+            vec![],
+        ),
+        Expression::Index { left, index, .. } => (vec![*left, *index], vec![]),
+        Expression::Struct { name, fields, .. } => {
+            visitor.visit_identifier_mut(name);
+            (fields.iter().map(|(_, value)| *value).collect(), vec![])
+        }
+        Expression::Field { object, .. } => (vec![*object], vec![]),
+        Expression::Variant {
+            enum_name, args, ..
+        } => {
+            visitor.visit_identifier_mut(enum_name);
+            (args.clone(), vec![])
+        }
+        Expression::Range { start, end, .. } => {
+            (start.iter().chain(end.iter()).copied().collect(), vec![])
+        }
+    };
+    for expr in expressions {
+        visitor.visit_expression_mut(program, expr);
+    }
+    for statements in blocks {
+        visitor.visit_block_mut(program, &statements);
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer, "test");
+        let p = parser.parse();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        p
+    }
+
+    /// Collects the names of the variables used, but not the declared ones
+    #[derive(Default)]
+    struct Uses(Vec<String>);
+
+    impl Visitor for Uses {
+        fn visit_let(&mut self, program: &Program, stmt: &LetStatement) {
+            self.visit_expression(program, stmt.value());
+        }
+        fn visit_function(
+            &mut self,
+            program: &Program,
+            _token: &Token,
+            _params: &[Parameter],
+            _return_type: Option<&TypeAnnotation>,
+            body: &BlockStatement,
+        ) {
+            self.visit_block(program, body);
+        }
+        fn visit_identifier(&mut self, ident: &Identifier) {
+            self.0.push(ident.value().to_string());
+        }
+    }
+
+    #[test]
+    fn visit_identifiers() {
+        let program = parse(
+            r#"let f = fn(x, y) { if (x < y) { g(x)[y] } else { {"k": [z]} } };
+let w = -a + b;"#,
+        );
+        let mut uses = Uses::default();
+
+        uses.visit_program(&program);
+
+        assert_eq!(uses.0, ["x", "y", "g", "x", "y", "z", "a", "b"]);
+    }
+
+    /// Folds additions of integer literals
+    struct FoldAdditions;
+
+    impl VisitorMut for FoldAdditions {
+        fn visit_expression_mut(&mut self, program: &mut Program, id: ExprId) {
+            walk_expression_mut(self, program, id);
+            if let Expression::Infix { token, left, right } = &program[id] {
+                if let (
+                    crate::token::TokenType::Plus,
+                    Expression::Integer { value: a, .. },
+                    Expression::Integer { value: b, .. },
+                ) = (&token.token_type, &program[*left], &program[*right])
+                {
+                    program[id] = Expression::Integer {
+                        token: token.clone(),
+                        value: a + b,
+                    };
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn visit_mut_replaces_expressions() {
+        let mut program =
+            parse("let x = f(1 + 2 + 3, y + 1) * (4 + 5); let g = fn() { if (x) { 1 + 1 } };");
+
+        FoldAdditions.visit_program_mut(&mut program);
+
+        assert_eq!(
+            program.to_string(),
+            "let x = f(6, y + 1) * 9;\nlet g = fn() {\n    if (x) {\n        2;\n    };\n};"
+        );
+    }
+}
@@ -0,0 +1,513 @@
+//! Lossless concrete syntax tree
+//!
+//! The green tree holds the kind and text of every token, whitespace and
+//! comment, so printing it gives back the input byte for byte. Green nodes
+//! only know their width, the red [`SyntaxNode`] built on top of them adds
+//! offsets and parents.
+//!
+//! The parser records the range of every node it finishes, the tree is then
+//! built by lexing the input again and nesting the tokens in these ranges.
+//! Tokens skipped while recovering from an error stay in the enclosing node.
+
+use crate::ast::Expression;
+use crate::lexer::Lexer;
+use crate::token::TokenType;
+use std::fmt::{self, Display, Formatter};
+use std::ops::Range;
+use std::rc::Rc;
+
+mod lower;
+
+pub use lower::lower;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxKind {
+    Token(TokenType),
+    Whitespace,
+    /// A `//` comment, without the line break ending it
+    Comment,
+
+    Program,
+    LetStmt,
+    ConstStmt,
+    ReturnStmt,
+    ExprStmt,
+    BreakStmt,
+    ContinueStmt,
+    StructStmt,
+    EnumStmt,
+    EnumVariant,
+    ImportStmt,
+    ExportStmt,
+    Block,
+    Name,
+    Literal,
+    PrefixExpr,
+    InfixExpr,
+    AssignExpr,
+    ParenExpr,
+    IfExpr,
+    WhileExpr,
+    ForExpr,
+    LoopExpr,
+    MatchExpr,
+    MatchArm,
+    FnExpr,
+    MacroExpr,
+    CallExpr,
+    /// `name: value` in the arguments of a call
+    NamedArg,
+    /// `...xs` in the arguments of a call
+    SpreadExpr,
+    /// `value |> f`, a call of `f` in the AST
+    PipeExpr,
+    ArrayExpr,
+    HashExpr,
+    IndexExpr,
+    StructExpr,
+    FieldExpr,
+    VariantExpr,
+    RangeExpr,
+    WildcardPat,
+    BindingPat,
+    LiteralPat,
+    ArrayPat,
+    /// `...rest` in an array pattern
+    RestPat,
+    StructPat,
+    VariantPat,
+    /// `int` or `array<int>` after a name or an arrow
+    TypeExpr,
+}
+
+impl SyntaxKind {
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, Self::Whitespace | Self::Comment)
+    }
+}
+
+impl From<&Expression> for SyntaxKind {
+    fn from(expr: &Expression) -> Self {
+        match expr {
+            Expression::Identifier(_) => Self::Name,
+            Expression::Integer { .. }
+            | Expression::Float { .. }
+            | Expression::Boolean { .. }
+            | Expression::String { .. } => Self::Literal,
+            Expression::Prefix { .. } => Self::PrefixExpr,
+            Expression::Infix { .. } => Self::InfixExpr,
+            Expression::Assign { .. } => Self::AssignExpr,
+            Expression::If { .. } => Self::IfExpr,
+            Expression::While { .. } => Self::WhileExpr,
+            Expression::For { .. } => Self::ForExpr,
+            Expression::Loop { .. } => Self::LoopExpr,
+            Expression::Match { .. } => Self::MatchExpr,
+            Expression::Function { .. } => Self::FnExpr,
+            Expression::Macro { .. } => Self::MacroExpr,
+            Expression::Call { .. } => Self::CallExpr,
+            Expression::Spread { .. } => Self::SpreadExpr,
+            Expression::Array { .. } => Self::ArrayExpr,
+            Expression::Hash { .. } => Self::HashExpr,
+            Expression::Index { .. } => Self::IndexExpr,
+            Expression::Struct { .. } => Self::StructExpr,
+            Expression::Field { .. } => Self::FieldExpr,
+            Expression::Variant { .. } => Self::VariantExpr,
+            Expression::Range { .. } => Self::RangeExpr,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct GreenToken {
+    kind: SyntaxKind,
+    text: String,
+}
+
+impl GreenToken {
+    pub fn kind(&self) -> &SyntaxKind {
+        &self.kind
+    }
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum GreenElement {
+    Node(Rc<GreenNode>),
+    Token(Rc<GreenToken>),
+}
+
+impl GreenElement {
+    fn width(&self) -> usize {
+        match self {
+            Self::Node(node) => node.width,
+            Self::Token(token) => token.text.len(),
+        }
+    }
+}
+
+/// A node without position, identical subtrees can be shared
+#[derive(Debug, PartialEq)]
+pub struct GreenNode {
+    kind: SyntaxKind,
+    /// Length of the text of the node in bytes
+    width: usize,
+    children: Vec<GreenElement>,
+}
+
+impl GreenNode {
+    pub fn new(kind: SyntaxKind, children: Vec<GreenElement>) -> Self {
+        Self {
+            kind,
+            width: children.iter().map(GreenElement::width).sum(),
+            children,
+        }
+    }
+    pub fn kind(&self) -> &SyntaxKind {
+        &self.kind
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn children(&self) -> &[GreenElement] {
+        &self.children
+    }
+}
+
+impl Display for GreenNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for child in &self.children {
+            match child {
+                GreenElement::Node(node) => write!(f, "{}", node)?,
+                GreenElement::Token(token) => f.write_str(&token.text)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A node of the tree, positioned in the source
+#[derive(Debug, Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+#[derive(Debug)]
+struct NodeData {
+    green: Rc<GreenNode>,
+    offset: usize,
+    parent: Option<SyntaxNode>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SyntaxToken {
+    green: Rc<GreenToken>,
+    offset: usize,
+    parent: SyntaxNode,
+}
+
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxNode {
+    pub fn new_root(green: GreenNode) -> Self {
+        Self(Rc::new(NodeData {
+            green: Rc::new(green),
+            offset: 0,
+            parent: None,
+        }))
+    }
+    pub fn kind(&self) -> &SyntaxKind {
+        &self.0.green.kind
+    }
+    pub fn green(&self) -> &GreenNode {
+        &self.0.green
+    }
+    /// Byte range of the node in the source, trivia included
+    pub fn range(&self) -> Range<usize> {
+        self.0.offset..self.0.offset + self.0.green.width
+    }
+    pub fn parent(&self) -> Option<&SyntaxNode> {
+        self.0.parent.as_ref()
+    }
+    pub fn children(&self) -> impl Iterator<Item = SyntaxElement> + '_ {
+        let mut offset = self.0.offset;
+        self.0.green.children.iter().map(move |child| {
+            let start = offset;
+            offset += child.width();
+            match child {
+                GreenElement::Node(green) => SyntaxElement::Node(SyntaxNode(Rc::new(NodeData {
+                    green: green.clone(),
+                    offset: start,
+                    parent: Some(self.clone()),
+                }))),
+                GreenElement::Token(green) => SyntaxElement::Token(SyntaxToken {
+                    green: green.clone(),
+                    offset: start,
+                    parent: self.clone(),
+                }),
+            }
+        })
+    }
+    pub fn child_nodes(&self) -> impl Iterator<Item = SyntaxNode> + '_ {
+        self.children().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+    /// The tokens directly in this node, trivia excluded
+    pub fn child_tokens(&self) -> impl Iterator<Item = SyntaxToken> + '_ {
+        self.children().filter_map(|child| match child {
+            SyntaxElement::Token(token) if !token.kind().is_trivia() => Some(token),
+            _ => None,
+        })
+    }
+    /// The first token of the node or of its descendants, trivia excluded
+    pub fn first_token(&self) -> Option<SyntaxToken> {
+        self.children().find_map(|child| match child {
+            SyntaxElement::Node(node) => node.first_token(),
+            SyntaxElement::Token(token) if !token.kind().is_trivia() => Some(token),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+    /// The last token of the node or of its descendants, trivia excluded
+    pub fn last_token(&self) -> Option<SyntaxToken> {
+        self.children()
+            .filter_map(|child| match child {
+                SyntaxElement::Node(node) => node.last_token(),
+                SyntaxElement::Token(token) if !token.kind().is_trivia() => Some(token),
+                SyntaxElement::Token(_) => None,
+            })
+            .last()
+    }
+}
+
+impl Display for SyntaxNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.green)
+    }
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> &SyntaxKind {
+        &self.green.kind
+    }
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.green.text.len()
+    }
+    pub fn parent(&self) -> &SyntaxNode {
+        &self.parent
+    }
+}
+
+/// Build the tree of `source` from the `(kind, start, end)` byte ranges of
+/// its nodes, in the order they were finished
+pub(crate) fn build(source: &str, nodes: &[(SyntaxKind, usize, usize)]) -> SyntaxNode {
+    // A node is finished after the nodes it contains, so among nodes with
+    // the same range the last one recorded is the outermost
+    let mut order: Vec<usize> = (0..nodes.len())
+        .filter(|&i| nodes[i].1 < nodes[i].2)
+        .collect();
+    order.sort_by(|&i, &j| {
+        let ((_, start_i, end_i), (_, start_j, end_j)) = (&nodes[i], &nodes[j]);
+        start_i.cmp(start_j).then(end_j.cmp(end_i)).then(j.cmp(&i))
+    });
+    let mut pending = order.into_iter().map(|i| &nodes[i]).peekable();
+
+    let mut builder = Builder {
+        stack: vec![(SyntaxKind::Program, usize::MAX, Vec::new())],
+    };
+    let mut lexer = Lexer::new(source);
+    let mut position = 0;
+    loop {
+        let token = lexer.next_token();
+        builder.trivia(&source[position..token.start], position);
+        if token.token_type == TokenType::EOF {
+            break;
+        }
+        builder.close(token.start);
+        while let Some((kind, _, end)) = pending.next_if(|(_, start, _)| *start <= token.start) {
+            if *end > token.start {
+                builder.open(kind.clone(), *end);
+            }
+        }
+        builder.push(
+            SyntaxKind::Token(token.token_type.clone()),
+            &source[token.start..token.end],
+        );
+        position = token.end;
+    }
+
+    SyntaxNode::new_root(builder.finish())
+}
+
+struct Builder {
+    /// The open nodes, with the offset they end at and their children
+    stack: Vec<(SyntaxKind, usize, Vec<GreenElement>)>,
+}
+
+impl Builder {
+    fn open(&mut self, kind: SyntaxKind, end: usize) {
+        // Ranges recorded around an error could overlap the enclosing node
+        let parent_end = self.stack.last().map_or(usize::MAX, |(_, end, _)| *end);
+        self.stack.push((kind, end.min(parent_end), Vec::new()));
+    }
+
+    /// Close the nodes ending at or before `offset`
+    fn close(&mut self, offset: usize) {
+        while self.stack.len() > 1 && self.stack.last().is_some_and(|(_, end, _)| *end <= offset) {
+            self.close_last();
+        }
+    }
+
+    fn close_last(&mut self) {
+        if let Some((kind, _, children)) = self.stack.pop() {
+            let node = GreenElement::Node(Rc::new(GreenNode::new(kind, children)));
+            if let Some((_, _, parent)) = self.stack.last_mut() {
+                parent.push(node);
+            }
+        }
+    }
+
+    fn push(&mut self, kind: SyntaxKind, text: &str) {
+        if let Some((_, _, children)) = self.stack.last_mut() {
+            children.push(GreenElement::Token(Rc::new(GreenToken {
+                kind,
+                text: text.to_string(),
+            })));
+        }
+    }
+
+    /// Split the text between two tokens into whitespace runs and comments
+    fn trivia(&mut self, mut text: &str, mut offset: usize) {
+        while !text.is_empty() {
+            self.close(offset);
+            let (kind, len) = if text.starts_with("//") {
+                (SyntaxKind::Comment, text.find('\n').unwrap_or(text.len()))
+            } else {
+                let len = text
+                    .find(|c: char| !c.is_whitespace())
+                    .unwrap_or(text.len());
+                (SyntaxKind::Whitespace, len)
+            };
+            self.push(kind, &text[..len]);
+            text = &text[len..];
+            offset += len;
+        }
+    }
+
+    fn finish(mut self) -> GreenNode {
+        while self.stack.len() > 1 {
+            self.close_last();
+        }
+        let (kind, _, children) = self.stack.pop().unwrap_or((SyntaxKind::Program, 0, vec![]));
+        GreenNode::new(kind, children)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::json::Json;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> SyntaxNode {
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer, "test");
+        let _p = parser.parse();
+        parser.syntax_tree()
+    }
+
+    #[test]
+    fn print_input_back() {
+        let inputs = [
+            "let x = 5; // five\n\n  let  mut\ty=fn(a ,b){a+b} ;\n// end",
+            "if (a) { [1, 2][0] } else { {\"k\": -b} }\r\n",
+            "let = 5; let y = @ 1; f(1,, 2) { let z = 3",
+            "let s = \"unterminated",
+            "let a = 1;\0 let b = 2;",
+            "\"a\0b\"; // c\0d\nx\0",
+            "",
+        ];
+        for input in inputs {
+            assert_eq!(parse(input).to_string(), input);
+        }
+        let file = std::fs::read_to_string("input/line_nb_error.pab").unwrap();
+        assert_eq!(parse(&file).to_string(), file);
+    }
+
+    #[test]
+    fn nodes_and_tokens() {
+        let root = parse("let x = a + b; // sum\nx");
+
+        let stmts: Vec<_> = root.child_nodes().collect();
+        assert_eq!(stmts.len(), 2);
+        assert_eq!(stmts[0].kind(), &SyntaxKind::LetStmt);
+        assert_eq!(stmts[0].range(), 0..14);
+        assert_eq!(stmts[0].to_string(), "let x = a + b;");
+        assert_eq!(stmts[1].kind(), &SyntaxKind::ExprStmt);
+        assert_eq!(stmts[1].range(), 22..23);
+
+        let mut children = stmts[0].child_nodes();
+        assert_eq!(children.next().unwrap().kind(), &SyntaxKind::BindingPat);
+        let infix = children.next().unwrap();
+        assert_eq!(infix.kind(), &SyntaxKind::InfixExpr);
+        assert_eq!(infix.parent().map(|p| p.range()), Some(0..14));
+        let operator = infix.child_tokens().next().unwrap();
+        assert_eq!(operator.kind(), &SyntaxKind::Token(TokenType::Plus));
+        assert_eq!(operator.range(), 10..11);
+
+        let comment = root
+            .children()
+            .find_map(|child| match child {
+                SyntaxElement::Token(token) if token.kind() == &SyntaxKind::Comment => Some(token),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(comment.text(), "// sum");
+    }
+
+    #[test]
+    fn lower_to_ast() {
+        let file = std::fs::read_to_string("input/line_nb_error.pab").unwrap();
+        let inputs = [
+            "let mut f = fn(a, b) { return (a + b) * -a; }; f(1, 2.5)[0] = \"s\";",
+            "if (a < b) { x } else { {true: [y, z]} }; let = 5; g(h)",
+            "while (i < n) { for x in xs { continue; }; break } loop { break (1 + 2) * 3 }",
+            "match f(x) { -1 => a, [_, b, \"s\"] if b => (b), c => match c {}, }",
+            "struct P { x, y }; let p = P { x: 1, y: P {} }; p.x.y = 2; match p { P { x, y: [a] } => a }",
+            "enum S { C(r), E }; let s = S::C(S::E); match s { S::C(S::E) => 1, S::E => 2, S::C(_) => 3 }",
+            "map(xs, |x, y| x + y * 2); let f = || { g(|| (1)) }; |a| a = |b| b",
+            "let h = |k| { \"k\": k }; let e = || {}; |k| { k: 1 }[k]",
+            "a + 1 |> f |> g(2) |> (h(3)) |> (i)(4) == xs[0] |> |x| x |> j",
+            "for i in 0..n + 1 { a[i..] = s[..=i][..]; }; (..2)..; [1..=2, (0..1)..2]",
+            "let x: array<int> = f(fn(a: int, b) -> map<int, float> { a }, |c: int| c); let y: int = 1;",
+            "import \"m.pab\" as m; import { a, b } from \"n.pab\"; export let x = a; export struct P { x } export enum E { A }",
+            "let m = macro(x, y) { quote(unquote(x) + y) }; m(1, 2);",
+            "let f = fn(x, y: int = 1 + 2, ...rest) { x }; f(1, ...xs); f(2, y: 3);",
+            "const A = 1; const B: int = -A * 2; export const C = A + B; b = B",
+            "let [a, mut b, ...] = xs; let { x, mut y, z: [...rest] } = p; let P { x } = p; match q { [...mut r] => r }",
+            file.as_str(),
+        ];
+        for input in inputs {
+            let lexer = Lexer::new(input);
+            let mut parser = Parser::new(lexer, "test");
+            let program = parser.parse();
+
+            let lowered = lower(&parser.syntax_tree());
+
+            assert_eq!(format!("{:#}", lowered), format!("{:#}", program));
+            assert_eq!(
+                Json::from(&lowered).to_string(),
+                Json::from(&program).to_string(),
+                "{}",
+                input
+            );
+        }
+    }
+}
@@ -0,0 +1,725 @@
+//! Lowering of the concrete syntax tree to the AST
+//!
+//! Nodes the parser could not finish are not in the tree, so the program
+//! lowered from the tree of an input with errors matches the one returned by
+//! the parser.
+
+use super::{SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken};
+use crate::ast::{
+    BlockStatement, BreakStatement, ConstStatement, ContinueStatement, EnumStatement,
+    ExportStatement, ExprId, Expression, ExpressionStatement, Identifier, ImportItems,
+    ImportStatement, LetStatement, Literal, MatchArm, Parameter, Pattern, Program, ReturnStatement,
+    Statement, StmtId, StructStatement, TypeAnnotation, Variant,
+};
+use crate::token::{Span, Token, TokenType};
+
+/// Build the AST of a tree returned by `Parser::syntax_tree`
+pub fn lower(root: &SyntaxNode) -> Program {
+    let source = root.to_string();
+    let line_starts = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let mut lowerer = Lowerer {
+        source,
+        line_starts,
+        program: Program::new(),
+    };
+    let statements = root
+        .child_nodes()
+        .filter_map(|node| lowerer.statement(&node))
+        .collect();
+    let mut program = lowerer.program;
+    program.statements = statements;
+    program
+}
+
+struct Lowerer {
+    source: String,
+    /// Offset of the first byte of every line
+    line_starts: Vec<usize>,
+    program: Program,
+}
+
+impl Lowerer {
+    fn token(&self, token: &SyntaxToken) -> Token {
+        let token_type = match token.kind() {
+            SyntaxKind::Token(token_type) => token_type.clone(),
+            _ => TokenType::Illegal,
+        };
+        let range = token.range();
+        let line = self
+            .line_starts
+            .partition_point(|&start| start <= range.start);
+        let column = self.source[self.line_starts[line - 1]..range.start]
+            .chars()
+            .count()
+            + 1;
+        Token::new(token_type, range.start, range.end, line, column)
+    }
+
+    fn identifier(&self, token: &SyntaxToken) -> Identifier {
+        let range = token.range();
+        Identifier::new(
+            self.token(token),
+            token.text().to_string(),
+            range.start,
+            range.end,
+        )
+    }
+
+    /// Span of the tokens of the node, trivia excluded
+    fn span(&self, node: &SyntaxNode) -> Option<Span> {
+        let start = self.token(&node.first_token()?).span();
+        Some(Span {
+            end: node.last_token()?.range().end,
+            ..start
+        })
+    }
+
+    fn statement(&mut self, node: &SyntaxNode) -> Option<StmtId> {
+        let id = self.alloc_statement(node)?;
+        let span = self.span(node)?;
+        self.program.set_statement_span(id, span);
+        Some(id)
+    }
+
+    fn alloc_statement(&mut self, node: &SyntaxNode) -> Option<StmtId> {
+        let first = node.first_token()?;
+        let mut children = node.child_nodes();
+        match node.kind() {
+            SyntaxKind::LetStmt => {
+                let pattern = self.pattern(&children.next()?)?;
+                let mut value = children.next()?;
+                let annotation = if value.kind() == &SyntaxKind::TypeExpr {
+                    let annotation = self.annotation(&value)?;
+                    value = children.next()?;
+                    Some(annotation)
+                } else {
+                    None
+                };
+                let value = self.expression(&value)?;
+                Some(
+                    self.program
+                        .alloc_statement(Statement::Let(LetStatement::new(
+                            self.token(&first),
+                            pattern,
+                            annotation,
+                            value,
+                        ))),
+                )
+            }
+            SyntaxKind::ConstStmt => {
+                let name = self.identifiers(node).next()?;
+                let mut value = children.next()?;
+                let annotation = if value.kind() == &SyntaxKind::TypeExpr {
+                    let annotation = self.annotation(&value)?;
+                    value = children.next()?;
+                    Some(annotation)
+                } else {
+                    None
+                };
+                let value = self.expression(&value)?;
+                Some(
+                    self.program
+                        .alloc_statement(Statement::Const(ConstStatement::new(
+                            self.token(&first),
+                            name,
+                            annotation,
+                            value,
+                        ))),
+                )
+            }
+            SyntaxKind::ReturnStmt => {
+                let value = self.expression(&children.next()?)?;
+                Some(
+                    self.program
+                        .alloc_statement(Statement::Return(ReturnStatement::new(
+                            self.token(&first),
+                            value,
+                        ))),
+                )
+            }
+            SyntaxKind::ExprStmt => {
+                let expression = self.expression(&children.next()?)?;
+                Some(
+                    self.program
+                        .alloc_statement(Statement::Expression(ExpressionStatement::new(
+                            self.token(&first),
+                            expression,
+                        ))),
+                )
+            }
+            SyntaxKind::BreakStmt => {
+                let value = match children.next() {
+                    Some(node) => Some(self.expression(&node)?),
+                    None => None,
+                };
+                Some(
+                    self.program
+                        .alloc_statement(Statement::Break(BreakStatement::new(
+                            self.token(&first),
+                            value,
+                        ))),
+                )
+            }
+            SyntaxKind::StructStmt => {
+                let mut names = self.identifiers(node);
+                let name = names.next()?;
+                Some(
+                    self.program
+                        .alloc_statement(Statement::Struct(StructStatement::new(
+                            self.token(&first),
+                            name,
+                            names.collect(),
+                        ))),
+                )
+            }
+            SyntaxKind::EnumStmt => {
+                let name = self.identifiers(node).next()?;
+                let variants = children
+                    .map(|variant| {
+                        let mut names = self.identifiers(&variant);
+                        Some(Variant::new(names.next()?, names.collect()))
+                    })
+                    .collect::<Option<_>>()?;
+                Some(
+                    self.program
+                        .alloc_statement(Statement::Enum(EnumStatement::new(
+                            self.token(&first),
+                            name,
+                            variants,
+                        ))),
+                )
+            }
+            SyntaxKind::ImportStmt => {
+                let path_token = node
+                    .child_tokens()
+                    .find(|token| token.kind() == &SyntaxKind::Token(TokenType::String))?;
+                let path = path_token.text();
+                let path = path[1..path.len() - 1].to_string();
+                let is_module = node
+                    .child_tokens()
+                    .any(|token| token.kind() == &SyntaxKind::Token(TokenType::As));
+                let items = {
+                    let mut names = self.identifiers(node);
+                    if is_module {
+                        ImportItems::Module(names.next()?)
+                    } else {
+                        ImportItems::Names(names.collect())
+                    }
+                };
+                Some(
+                    self.program
+                        .alloc_statement(Statement::Import(ImportStatement::new(
+                            self.token(&first),
+                            self.token(&path_token),
+                            path,
+                            items,
+                        ))),
+                )
+            }
+            SyntaxKind::ExportStmt => {
+                let declaration = self.statement(&children.next()?)?;
+                Some(
+                    self.program
+                        .alloc_statement(Statement::Export(ExportStatement::new(
+                            self.token(&first),
+                            declaration,
+                        ))),
+                )
+            }
+            SyntaxKind::ContinueStmt => Some(self.program.alloc_statement(Statement::Continue(
+                ContinueStatement::new(self.token(&first)),
+            ))),
+            _ => None,
+        }
+    }
+
+    fn block(&mut self, node: &SyntaxNode) -> Option<BlockStatement> {
+        if node.kind() != &SyntaxKind::Block {
+            return None;
+        }
+        let statements = node
+            .child_nodes()
+            .filter_map(|node| self.statement(&node))
+            .collect();
+        Some(BlockStatement::new(
+            self.token(&node.first_token()?),
+            statements,
+            node.last_token()?.range().end,
+        ))
+    }
+
+    /// The body of a function, an expression is the body of a lambda
+    /// standing for a block of one statement
+    fn body(&mut self, node: &SyntaxNode) -> Option<BlockStatement> {
+        if node.kind() == &SyntaxKind::Block {
+            return self.block(node);
+        }
+        let token = self.token(&node.first_token()?);
+        let expression = self.expression(node)?;
+        let stmt = self
+            .program
+            .alloc_statement(Statement::Expression(ExpressionStatement::new(
+                token.clone(),
+                expression,
+            )));
+        let span = self.span(node)?;
+        self.program.set_statement_span(stmt, span);
+        Some(BlockStatement::new(token, vec![stmt], span.end))
+    }
+
+    fn expression(&mut self, node: &SyntaxNode) -> Option<ExprId> {
+        let id = self.alloc_expression(node)?;
+        // Parentheses are not part of the expression they group
+        if node.kind() != &SyntaxKind::ParenExpr {
+            let span = self.span(node)?;
+            self.program.set_expression_span(id, span);
+        }
+        Some(id)
+    }
+
+    fn alloc_expression(&mut self, node: &SyntaxNode) -> Option<ExprId> {
+        let mut children = node.child_nodes();
+        // The keyword, operator or opening delimiter of the node
+        let token = node.child_tokens().next();
+        let expr = match node.kind() {
+            SyntaxKind::Name => Expression::Identifier(self.identifier(&token?)),
+            SyntaxKind::Literal => {
+                let token = token?;
+                let text = token.text();
+                let ast_token = self.token(&token);
+                match ast_token.token_type {
+                    TokenType::Int => Expression::Integer {
+                        value: text.parse().ok()?,
+                        token: ast_token,
+                    },
+                    TokenType::Float => Expression::Float {
+                        value: text.parse().ok()?,
+                        token: ast_token,
+                    },
+                    TokenType::String => Expression::String {
+                        value: text[1..text.len() - 1].to_string(),
+                        token: ast_token,
+                    },
+                    TokenType::True | TokenType::False => Expression::Boolean {
+                        value: ast_token.token_type == TokenType::True,
+                        token: ast_token,
+                    },
+                    _ => return None,
+                }
+            }
+            SyntaxKind::PrefixExpr => Expression::Prefix {
+                token: self.token(&token?),
+                right: self.child(children.next())?,
+            },
+            SyntaxKind::InfixExpr => Expression::Infix {
+                left: self.child(children.next())?,
+                token: self.token(&token?),
+                right: self.child(children.next())?,
+            },
+            SyntaxKind::AssignExpr => Expression::Assign {
+                target: self.child(children.next())?,
+                token: self.token(&token?),
+                value: self.child(children.next())?,
+            },
+            SyntaxKind::ParenExpr => return self.expression(&children.next()?),
+            SyntaxKind::IfExpr => Expression::If {
+                token: self.token(&token?),
+                condition: self.child(children.next())?,
+                consequence: self.block(&children.next()?)?,
+                alternative: match children.next() {
+                    Some(node) => Some(self.block(&node)?),
+                    None => None,
+                },
+            },
+            SyntaxKind::WhileExpr => Expression::While {
+                token: self.token(&token?),
+                condition: self.child(children.next())?,
+                body: self.block(&children.next()?)?,
+            },
+            SyntaxKind::ForExpr => Expression::For {
+                token: self.token(&token?),
+                variable: self.identifier(
+                    &node
+                        .child_tokens()
+                        .find(|token| token.kind() == &SyntaxKind::Token(TokenType::Ident))?,
+                ),
+                iterable: self.child(children.next())?,
+                body: self.block(&children.next()?)?,
+            },
+            SyntaxKind::LoopExpr => Expression::Loop {
+                token: self.token(&token?),
+                body: self.block(&children.next()?)?,
+            },
+            SyntaxKind::MatchExpr => Expression::Match {
+                token: self.token(&token?),
+                subject: self.child(children.next())?,
+                arms: children
+                    .map(|arm| self.match_arm(&arm))
+                    .collect::<Option<_>>()?,
+            },
+            SyntaxKind::FnExpr | SyntaxKind::MacroExpr => {
+                // A type follows the name of its parameter, or the arrow of
+                // the return type, a default value follows an '=' and the
+                // body is the last node
+                let mut params: Vec<(Identifier, Option<TypeAnnotation>, Option<ExprId>, bool)> =
+                    Vec::new();
+                let mut return_type = None;
+                let (mut arrow, mut rest, mut default) = (false, false, false);
+                let mut body = None;
+                for child in node.children() {
+                    match child {
+                        SyntaxElement::Token(token)
+                            if token.kind() == &SyntaxKind::Token(TokenType::Ident) =>
+                        {
+                            params.push((self.identifier(&token), None, None, rest))
+                        }
+                        SyntaxElement::Token(token)
+                            if token.kind() == &SyntaxKind::Token(TokenType::DotDotDot) =>
+                        {
+                            rest = true
+                        }
+                        SyntaxElement::Token(token)
+                            if token.kind() == &SyntaxKind::Token(TokenType::Assign) =>
+                        {
+                            default = true
+                        }
+                        SyntaxElement::Token(token)
+                            if token.kind() == &SyntaxKind::Token(TokenType::Arrow) =>
+                        {
+                            arrow = true
+                        }
+                        SyntaxElement::Node(node) if node.kind() == &SyntaxKind::TypeExpr => {
+                            let annotation = Some(self.annotation(&node)?);
+                            if arrow {
+                                return_type = annotation;
+                            } else {
+                                params.last_mut()?.1 = annotation;
+                            }
+                        }
+                        SyntaxElement::Node(node) if default => {
+                            params.last_mut()?.2 = Some(self.expression(&node)?);
+                            default = false;
+                        }
+                        SyntaxElement::Node(node) => body = Some(node),
+                        SyntaxElement::Token(_) => {}
+                    }
+                }
+                let token = self.token(&token?);
+                let params = params
+                    .into_iter()
+                    .map(|(name, annotation, default, is_rest)| {
+                        Parameter::new(name, annotation, default, is_rest)
+                    })
+                    .collect();
+                let body = self.body(&body?)?;
+                if node.kind() == &SyntaxKind::MacroExpr {
+                    Expression::Macro {
+                        token,
+                        params,
+                        body,
+                    }
+                } else {
+                    Expression::Function {
+                        token,
+                        params,
+                        return_type,
+                        body,
+                    }
+                }
+            }
+            SyntaxKind::CallExpr => {
+                let callee = self.child(children.next())?;
+                let (mut args, mut named) = (Vec::new(), Vec::new());
+                for child in children {
+                    if child.kind() == &SyntaxKind::NamedArg {
+                        let name = self.identifiers(&child).next()?;
+                        named.push((name, self.child(child.child_nodes().next())?));
+                    } else {
+                        args.push(self.expression(&child)?);
+                    }
+                }
+                Expression::Call {
+                    token: self.token(&token?),
+                    callee,
+                    args,
+                    named,
+                }
+            }
+            SyntaxKind::SpreadExpr => Expression::Spread {
+                token: self.token(&token?),
+                value: self.child(children.next())?,
+            },
+            SyntaxKind::PipeExpr => {
+                let value = self.child(children.next())?;
+                let function = children.next()?;
+                let grouped = function.kind() == &SyntaxKind::ParenExpr;
+                let function = self.expression(&function)?;
+                match &mut self.program[function] {
+                    Expression::Call { args, .. } if !grouped => {
+                        args.insert(0, value);
+                        return Some(function);
+                    }
+                    _ => Expression::Call {
+                        token: self.token(&token?),
+                        callee: function,
+                        args: vec![value],
+                        named: vec![],
+                    },
+                }
+            }
+            SyntaxKind::RangeExpr => {
+                // The bounds are told apart by their side of the operator
+                let token = token?;
+                let operator = token.range().start;
+                let (mut start, mut end) = (None, None);
+                for child in children {
+                    let bound = self.expression(&child)?;
+                    if child.range().start < operator {
+                        start = Some(bound);
+                    } else {
+                        end = Some(bound);
+                    }
+                }
+                Expression::Range {
+                    token: self.token(&token),
+                    start,
+                    end,
+                }
+            }
+            SyntaxKind::ArrayExpr => Expression::Array {
+                token: self.token(&token?),
+                elements: self.expressions(children)?,
+            },
+            SyntaxKind::HashExpr => {
+                let values = self.expressions(children)?;
+                let mut values = values.into_iter();
+                let mut pairs = Vec::new();
+                while let (Some(key), Some(value)) = (values.next(), values.next()) {
+                    pairs.push((key, value));
+                }
+                Expression::Hash {
+                    token: self.token(&token?),
+                    pairs,
+                }
+            }
+            SyntaxKind::IndexExpr => Expression::Index {
+                left: self.child(children.next())?,
+                token: self.token(&token?),
+                index: self.child(children.next())?,
+            },
+            SyntaxKind::StructExpr => {
+                let mut names = self.identifiers(node);
+                let name = names.next()?;
+                let names: Vec<Identifier> = names.collect();
+                let values = self.expressions(children)?;
+                let brace = node
+                    .child_tokens()
+                    .find(|token| token.kind() == &SyntaxKind::Token(TokenType::LBrace))?;
+                Expression::Struct {
+                    token: self.token(&brace),
+                    name,
+                    fields: names.into_iter().zip(values).collect(),
+                }
+            }
+            SyntaxKind::FieldExpr => Expression::Field {
+                token: self.token(&token?),
+                object: self.child(children.next())?,
+                field: self.identifiers(node).next()?,
+            },
+            SyntaxKind::VariantExpr => {
+                let (enum_name, variant) = {
+                    let mut names = self.identifiers(node);
+                    (names.next()?, names.next()?)
+                };
+                let path = node
+                    .child_tokens()
+                    .find(|token| token.kind() == &SyntaxKind::Token(TokenType::ColonColon))?;
+                Expression::Variant {
+                    token: self.token(&path),
+                    enum_name,
+                    variant,
+                    args: self.expressions(children)?,
+                }
+            }
+            _ => return None,
+        };
+        Some(self.program.alloc_expression(expr))
+    }
+
+    /// The identifiers directly in the node
+    fn identifiers<'n>(&'n self, node: &'n SyntaxNode) -> impl Iterator<Item = Identifier> + 'n {
+        node.child_tokens()
+            .filter(|token| token.kind() == &SyntaxKind::Token(TokenType::Ident))
+            .map(|token| self.identifier(&token))
+    }
+
+    fn annotation(&self, node: &SyntaxNode) -> Option<TypeAnnotation> {
+        let name = self.identifiers(node).next()?;
+        let args = node
+            .child_nodes()
+            .map(|node| self.annotation(&node))
+            .collect::<Option<_>>()?;
+        Some(TypeAnnotation::new(name, args))
+    }
+
+    fn match_arm(&mut self, node: &SyntaxNode) -> Option<MatchArm> {
+        let mut children = node.child_nodes();
+        let pattern = self.pattern(&children.next()?)?;
+        let has_guard = node
+            .child_tokens()
+            .any(|token| token.kind() == &SyntaxKind::Token(TokenType::If));
+        let guard = if has_guard {
+            Some(self.child(children.next())?)
+        } else {
+            None
+        };
+        Some(MatchArm::new(pattern, guard, self.child(children.next())?))
+    }
+
+    fn pattern(&self, node: &SyntaxNode) -> Option<Pattern> {
+        let mut tokens = node.child_tokens();
+        let first = tokens.next()?;
+        let pattern = match node.kind() {
+            SyntaxKind::WildcardPat => Pattern::Wildcard(self.token(&first)),
+            SyntaxKind::BindingPat => {
+                let is_mut = first.kind() == &SyntaxKind::Token(TokenType::Mut);
+                let name = if is_mut {
+                    tokens.next()?
+                } else {
+                    first.clone()
+                };
+                Pattern::Binding {
+                    name: self.identifier(&name),
+                    is_mut,
+                }
+            }
+            SyntaxKind::RestPat => {
+                let is_mut = tokens.any(|token| token.kind() == &SyntaxKind::Token(TokenType::Mut));
+                Pattern::Rest {
+                    token: self.token(&first),
+                    name: self.identifiers(node).next(),
+                    is_mut,
+                }
+            }
+            SyntaxKind::LiteralPat => {
+                let negative = first.kind() == &SyntaxKind::Token(TokenType::Minus);
+                let literal = if negative {
+                    tokens.next()?
+                } else {
+                    first.clone()
+                };
+                let text = literal.text();
+                let value = match literal.kind() {
+                    SyntaxKind::Token(TokenType::Int) => {
+                        let value: i64 = text.parse().ok()?;
+                        Literal::Integer(if negative { -value } else { value })
+                    }
+                    SyntaxKind::Token(TokenType::Float) => {
+                        let value: f64 = text.parse().ok()?;
+                        Literal::Float(if negative { -value } else { value })
+                    }
+                    SyntaxKind::Token(TokenType::String) => {
+                        Literal::String(text[1..text.len() - 1].to_string())
+                    }
+                    SyntaxKind::Token(TokenType::True) => Literal::Boolean(true),
+                    SyntaxKind::Token(TokenType::False) => Literal::Boolean(false),
+                    _ => return None,
+                };
+                Pattern::Literal {
+                    token: self.token(&first),
+                    value,
+                }
+            }
+            SyntaxKind::StructPat => {
+                // A field followed by a pattern node was written `field: pattern`,
+                // one followed by a comma is the shorthand and a pattern node
+                // alone is the shorthand `mut field`
+                let shorthand = |field: &SyntaxToken| Pattern::Binding {
+                    name: self.identifier(field),
+                    is_mut: false,
+                };
+                let mut fields = Vec::new();
+                let mut pending: Option<SyntaxToken> = None;
+                let mut brace = None;
+                for child in node.children().skip_while(|child| match child {
+                    SyntaxElement::Token(token) => {
+                        token.kind() != &SyntaxKind::Token(TokenType::LBrace)
+                    }
+                    SyntaxElement::Node(_) => true,
+                }) {
+                    match child {
+                        SyntaxElement::Token(token)
+                            if token.kind() == &SyntaxKind::Token(TokenType::LBrace)
+                                && brace.is_none() =>
+                        {
+                            brace = Some(token)
+                        }
+                        SyntaxElement::Token(token)
+                            if token.kind() == &SyntaxKind::Token(TokenType::Ident) =>
+                        {
+                            pending = Some(token)
+                        }
+                        SyntaxElement::Token(token)
+                            if token.kind() == &SyntaxKind::Token(TokenType::Comma) =>
+                        {
+                            if let Some(field) = pending.take() {
+                                fields.push((self.identifier(&field), shorthand(&field)));
+                            }
+                        }
+                        SyntaxElement::Node(node) => {
+                            let pattern = self.pattern(&node)?;
+                            let field = match pending.take() {
+                                Some(field) => self.identifier(&field),
+                                None if matches!(pattern, Pattern::Binding { .. }) => {
+                                    self.identifiers(&node).next()?
+                                }
+                                None => return None,
+                            };
+                            fields.push((field, pattern));
+                        }
+                        SyntaxElement::Token(_) => {}
+                    }
+                }
+                if let Some(field) = pending {
+                    fields.push((self.identifier(&field), shorthand(&field)));
+                }
+                let named = first.kind() == &SyntaxKind::Token(TokenType::Ident);
+                Pattern::Struct {
+                    token: self.token(&brace?),
+                    name: named.then(|| self.identifier(&first)),
+                    fields,
+                }
+            }
+            SyntaxKind::ArrayPat => Pattern::Array {
+                token: self.token(&first),
+                elements: node
+                    .child_nodes()
+                    .map(|node| self.pattern(&node))
+                    .collect::<Option<_>>()?,
+            },
+            SyntaxKind::VariantPat => {
+                let mut names = self.identifiers(node);
+                Pattern::Variant {
+                    enum_name: names.next()?,
+                    variant: names.next()?,
+                    fields: node
+                        .child_nodes()
+                        .map(|node| self.pattern(&node))
+                        .collect::<Option<_>>()?,
+                }
+            }
+            _ => return None,
+        };
+        Some(pattern)
+    }
+
+    fn child(&mut self, node: Option<SyntaxNode>) -> Option<ExprId> {
+        self.expression(&node?)
+    }
+
+    fn expressions(&mut self, nodes: impl Iterator<Item = SyntaxNode>) -> Option<Vec<ExprId>> {
+        nodes.map(|node| self.expression(&node)).collect()
+    }
+}
@@ -0,0 +1,475 @@
+use crate::json::Json;
+use crate::token::Span;
+use std::fmt::Write;
+use std::io::IsTerminal;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+        }
+    }
+    fn color(&self) -> &'static str {
+        match self {
+            Self::Error => RED,
+            Self::Warning => YELLOW,
+        }
+    }
+}
+
+/// A message attached to a location of the source
+///
+/// The primary label points at the cause of the diagnostic, secondary labels
+/// give context such as where something was first declared
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+/// A change of the source fixing a diagnostic, `replacement` takes the place
+/// of the text covered by `span`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub span: Span,
+    pub replacement: String,
+    pub message: String,
+}
+
+/// A problem found in a program, independent of how it is displayed
+///
+/// Lexer, parser and runtime errors are all converted to a diagnostic
+/// before being rendered
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Stable identifier of the kind of problem, e.g. `E0101`
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Vec<String>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            code: None,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: Vec::new(),
+            suggestions: Vec::new(),
+        }
+    }
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message)
+    }
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message)
+    }
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+    /// Set the location the diagnostic is about
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: true,
+        });
+        self
+    }
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: false,
+        });
+        self
+    }
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help.push(help.into());
+        self
+    }
+    pub fn with_suggestion(
+        mut self,
+        span: Span,
+        replacement: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Self {
+        self.suggestions.push(Suggestion {
+            span,
+            replacement: replacement.into(),
+            message: message.into(),
+        });
+        self
+    }
+    /// Location of the primary label
+    pub fn span(&self) -> Option<Span> {
+        self.labels.iter().find(|l| l.primary).map(|l| l.span)
+    }
+
+    /// Render the diagnostic the way rustc does, quoting the labelled lines
+    /// of `source` and underlining the spans
+    pub fn render(&self, file_path: &str, source: &str, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("{}{}{}", style, text, RESET)
+            } else {
+                text.to_string()
+            }
+        };
+        let severity = self.severity.color();
+
+        let mut labels: Vec<(SourceLine, &Label)> = self
+            .labels
+            .iter()
+            .map(|label| (SourceLine::locate(source, label.span), label))
+            .collect();
+        labels.sort_by_key(|(line, label)| (line.number, line.column, !label.primary));
+
+        let gutter = labels
+            .iter()
+            .map(|(line, _)| line.number.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let pad = " ".repeat(gutter);
+        let bar = paint(BLUE, "|");
+
+        let mut out = String::new();
+        let title = match self.code {
+            Some(code) => format!("{}[{}]", self.severity.as_str(), code),
+            None => self.severity.as_str().to_string(),
+        };
+        let _ = writeln!(
+            out,
+            "{}{}",
+            paint(severity, &title),
+            paint(BOLD, &format!(": {}", self.message))
+        );
+
+        if let Some((line, _)) = labels.iter().find(|(_, l)| l.primary).or(labels.first()) {
+            let _ = writeln!(
+                out,
+                "{}{} {}:{}:{}",
+                pad,
+                paint(BLUE, "-->"),
+                file_path,
+                line.number,
+                line.column
+            );
+            let _ = writeln!(out, "{} {}", pad, bar);
+        }
+
+        let mut previous: Option<usize> = None;
+        for (line, label) in &labels {
+            if previous != Some(line.number) {
+                if previous.is_some_and(|p| line.number > p + 1) {
+                    let _ = writeln!(out, "{}", paint(BLUE, "..."));
+                }
+                let quoted = format!(
+                    "{} {} {}",
+                    paint(BLUE, &format!("{:>gutter$}", line.number)),
+                    bar,
+                    line.text
+                );
+                let _ = writeln!(out, "{}", quoted.trim_end());
+                previous = Some(line.number);
+            }
+            let (marker, style) = if label.primary {
+                ("^", severity)
+            } else {
+                ("-", BLUE)
+            };
+            let underline = format!("{} {}", marker.repeat(line.width), label.message);
+            let _ = writeln!(
+                out,
+                "{} {} {}{}",
+                pad,
+                bar,
+                line.indent,
+                paint(style, underline.trim_end())
+            );
+        }
+
+        if !self.notes.is_empty() || !self.help.is_empty() || !self.suggestions.is_empty() {
+            if !labels.is_empty() {
+                let _ = writeln!(out, "{} {}", pad, bar);
+            }
+            for note in &self.notes {
+                let _ = writeln!(out, "{} {} note: {}", pad, paint(BLUE, "="), note);
+            }
+            for help in &self.help {
+                let _ = writeln!(out, "{} {} help: {}", pad, paint(BLUE, "="), help);
+            }
+            for suggestion in &self.suggestions {
+                let _ = writeln!(
+                    out,
+                    "{} {} help: {}: `{}`",
+                    pad,
+                    paint(BLUE, "="),
+                    suggestion.message,
+                    suggestion.replacement
+                );
+            }
+        }
+
+        out
+    }
+
+    /// Machine readable form of the diagnostic, for editors and CI
+    ///
+    /// Spans are given both as byte offsets and as line and column ranges,
+    /// the end of a range is exclusive
+    pub fn to_json(&self, file_path: &str, source: &str) -> Json {
+        let span = |span: Span| {
+            let (line_start, column_start) = location(source, span.start);
+            let (line_end, column_end) = location(source, span.end);
+            Json::object([
+                ("byte_start", span.start.into()),
+                ("byte_end", span.end.into()),
+                ("line_start", line_start.into()),
+                ("column_start", column_start.into()),
+                ("line_end", line_end.into()),
+                ("column_end", column_end.into()),
+            ])
+        };
+        let labels = self
+            .labels
+            .iter()
+            .map(|label| {
+                Json::object([
+                    ("primary", label.primary.into()),
+                    ("message", label.message.as_str().into()),
+                    ("span", span(label.span)),
+                ])
+            })
+            .collect();
+        let suggestions = self
+            .suggestions
+            .iter()
+            .map(|suggestion| {
+                Json::object([
+                    ("message", suggestion.message.as_str().into()),
+                    ("replacement", suggestion.replacement.as_str().into()),
+                    ("span", span(suggestion.span)),
+                ])
+            })
+            .collect();
+
+        Json::object([
+            ("severity", self.severity.as_str().into()),
+            ("code", self.code.into()),
+            ("message", self.message.as_str().into()),
+            ("file", file_path.into()),
+            ("span", self.span().map_or(Json::Null, span)),
+            ("labels", Json::Array(labels)),
+            ("notes", self.notes.clone().into()),
+            ("help", self.help.clone().into()),
+            ("suggestions", Json::Array(suggestions)),
+        ])
+    }
+}
+
+/// Line and column of a byte offset of the source, both starting at 1
+///
+/// Columns count characters so they match what editors display
+pub fn location(source: &str, offset: usize) -> (usize, usize) {
+    let offset = floor_char_boundary(source, offset);
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    (
+        source[..line_start].matches('\n').count() + 1,
+        source[line_start..offset].chars().count() + 1,
+    )
+}
+
+/// Whether diagnostics written to stderr should be colored
+///
+/// Colors are disabled when stderr isn't a terminal or when the `NO_COLOR`
+/// environment variable is set to a non empty value, see https://no-color.org
+pub fn use_color() -> bool {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    !no_color && std::io::stderr().is_terminal()
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+
+/// The line of the source a span starts on
+#[derive(Debug)]
+struct SourceLine<'a> {
+    number: usize,
+    column: usize,
+    text: &'a str,
+    /// Whitespace lining up the underline with the span, tabs are kept so the
+    /// alignment doesn't depend on the tab width
+    indent: String,
+    /// Width of the underline, spans going past the end of the line are cut
+    width: usize,
+}
+
+impl<'a> SourceLine<'a> {
+    fn locate(source: &'a str, span: Span) -> Self {
+        let start = floor_char_boundary(source, span.start);
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let text = source[line_start..line_end].trim_end_matches('\r');
+
+        let prefix = &source[line_start..start];
+        let indent = prefix
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let end = floor_char_boundary(source, span.end.clamp(start, line_end));
+        let width = source[start..end].chars().count().max(1);
+
+        let (number, column) = location(source, start);
+        Self {
+            number,
+            column,
+            text,
+            indent,
+            width,
+        }
+    }
+}
+
+fn floor_char_boundary(source: &str, index: usize) -> usize {
+    let mut index = index.min(source.len());
+    while !source.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn span(source: &str, needle: &str) -> Span {
+        let start = source.find(needle).unwrap();
+        Span {
+            start,
+            end: start + needle.len(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn render_snippet() {
+        let source = "let add = fn(x, y, x) {\n\tx + y;\n};\n";
+        let first = span(source, "x");
+        let second = span(source, "x) {");
+        let diagnostic = Diagnostic::error("duplicate parameter name 'x'")
+            .with_label(
+                Span {
+                    end: second.start + 1,
+                    ..second
+                },
+                "used more than once",
+            )
+            .with_secondary(first, "first use of 'x'")
+            .with_help("rename one of the parameters");
+
+        let rendered = diagnostic.render("add.pab", source, false);
+
+        assert_eq!(
+            rendered,
+            r#"error: duplicate parameter name 'x'
+ --> add.pab:1:20
+  |
+1 | let add = fn(x, y, x) {
+  |              - first use of 'x'
+  |                    ^ used more than once
+  |
+  = help: rename one of the parameters
+"#
+        );
+    }
+    #[test]
+    fn render_distant_lines() {
+        let source = "let f = fn() {\n\tlet x = 1;\n\t\tx\n";
+        let open = span(source, "{");
+        let eof = Span {
+            start: source.len(),
+            end: source.len(),
+            ..Default::default()
+        };
+        let diagnostic = Diagnostic::error("unexpected end of input, the block is never closed")
+            .with_label(eof, "expected '}'")
+            .with_secondary(open, "block opened here");
+
+        let rendered = diagnostic.render("f.pab", source, false);
+
+        assert_eq!(
+            rendered,
+            "error: unexpected end of input, the block is never closed
+ --> f.pab:4:1
+  |
+1 | let f = fn() {
+  |              - block opened here
+...
+4 |
+  | ^ expected '}'
+"
+        );
+    }
+    #[test]
+    fn render_colors() {
+        let source = "let = 1;";
+        let diagnostic = Diagnostic::warning("odd").with_label(span(source, "="), "here");
+
+        assert!(diagnostic.render("w.pab", source, true).contains(YELLOW));
+        assert!(!diagnostic.render("w.pab", source, false).contains('\x1b'));
+    }
+    #[test]
+    fn json_output() {
+        let source = "let é = 1\n";
+        let diagnostic = Diagnostic::error("expected ';' at the end of the statement")
+            .with_code("E0101")
+            .with_label(span(source, "1"), "add ';' here")
+            .with_suggestion(
+                Span {
+                    start: source.len() - 1,
+                    end: source.len() - 1,
+                    ..Default::default()
+                },
+                ";",
+                "add a semicolon",
+            );
+
+        let json = diagnostic.to_json("a.pab", source).to_string();
+
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"severity":"error","code":"E0101","message":"expected ';' at the end of the statement","file":"a.pab","#,
+                r#""span":{"byte_start":9,"byte_end":10,"line_start":1,"column_start":9,"line_end":1,"column_end":10},"#,
+                r#""labels":[{"primary":true,"message":"add ';' here","span":{"byte_start":9,"byte_end":10,"line_start":1,"column_start":9,"line_end":1,"column_end":10}}],"#,
+                r#""notes":[],"help":[],"#,
+                r#""suggestions":[{"message":"add a semicolon","replacement":";","span":{"byte_start":10,"byte_end":10,"line_start":1,"column_start":10,"line_end":1,"column_end":10}}]}"#
+            )
+        );
+    }
+}
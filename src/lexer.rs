@@ -1,6 +1,58 @@
-use crate::token::{Token, TokenType};
+use crate::diagnostic::Diagnostic;
+use crate::token::{Span, Token, TokenType};
+use thiserror::Error as ThisError;
 
-#[derive(Debug)]
+/// Why the lexer produced an illegal token
+#[derive(Debug, Clone, PartialEq, ThisError)]
+pub enum LexError {
+    #[error("unexpected character '{}'", character.escape_debug())]
+    UnexpectedCharacter { character: char, span: Span },
+    #[error("unterminated string")]
+    UnterminatedString { span: Span },
+}
+
+impl LexError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnexpectedCharacter { .. } => "E0001",
+            Self::UnterminatedString { .. } => "E0002",
+        }
+    }
+    pub fn span(&self) -> Span {
+        match self {
+            Self::UnexpectedCharacter { span, .. } | Self::UnterminatedString { span } => *span,
+        }
+    }
+}
+
+impl From<&LexError> for Diagnostic {
+    fn from(error: &LexError) -> Self {
+        let diagnostic = Diagnostic::error(error.to_string()).with_code(error.code());
+        match error {
+            LexError::UnexpectedCharacter { span, .. } => {
+                diagnostic.with_label(*span, "not part of the language")
+            }
+            LexError::UnterminatedString { span } => diagnostic
+                .with_label(
+                    Span {
+                        end: span.start + 1,
+                        ..*span
+                    },
+                    "string starts here",
+                )
+                .with_suggestion(
+                    Span {
+                        start: span.end,
+                        ..*span
+                    },
+                    "\"",
+                    "add the closing quote",
+                ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Lexer<'a> {
     input: &'a str,
     chars: std::str::Chars<'a>,
@@ -25,6 +77,21 @@ impl<'a> Lexer<'a> {
         l.read_char();
         l
     }
+    /// Lexer reading `input` from the byte offset `position`, the tokens
+    /// keep their location in the whole input
+    pub fn new_at(input: &'a str, position: usize) -> Self {
+        let mut l = Self {
+            chars: input[position..].chars(),
+            input,
+            position,
+            read_position: position,
+            ch: '\0',
+            line: 1 + input[..position].matches('\n').count(),
+            column: 1,
+        };
+        l.read_char();
+        l
+    }
 
     fn read_char(&mut self) {
         self.ch = self.chars.next().unwrap_or('\0');
@@ -35,7 +102,40 @@ impl<'a> Lexer<'a> {
         } else {
             self.column += 1;
         }
-        self.read_position += 1;
+        // Positions are byte offsets so tokens can be sliced out of the input
+        self.read_position += self.ch.len_utf8();
+    }
+
+    /// Whether the whole input was read, a `'\0'` before that is a character
+    /// of the input
+    fn at_end(&self) -> bool {
+        self.position >= self.input.len()
+    }
+
+    /// Line and column of the current character, both starting at 1
+    fn location(&self) -> (usize, usize) {
+        (self.line, self.column - 1)
+    }
+
+    pub fn input(&self) -> &'a str {
+        self.input
+    }
+
+    /// Return the source text a token was read from
+    pub fn literal(&self, token: &Token) -> &'a str {
+        &self.input[token.start..token.end]
+    }
+
+    /// Explain why a token is illegal
+    pub fn illegal_error(&self, token: &Token) -> LexError {
+        let literal = self.literal(token);
+        match literal.chars().next() {
+            Some('"') => LexError::UnterminatedString { span: token.span() },
+            character => LexError::UnexpectedCharacter {
+                character: character.unwrap_or('\0'),
+                span: token.span(),
+            },
+        }
     }
 
     /// Return the next character without consuming it
@@ -48,11 +148,13 @@ impl<'a> Lexer<'a> {
     /// This function will determine if the number is an integer or a float
     fn read_number(&mut self) -> Token {
         let position = self.position;
+        let (line, column) = self.location();
         let mut is_float = false;
 
         while self.ch.is_ascii_digit() || self.ch == '.' {
             if self.ch == '.' {
-                if is_float {
+                // `1..2` is a range
+                if is_float || self.peek_char() == '.' {
                     break;
                 }
                 is_float = true;
@@ -66,29 +168,51 @@ impl<'a> Lexer<'a> {
             TokenType::Int
         };
 
-        Token::new(token_type, position, self.position, self.line, self.column)
+        Token::new(token_type, position, self.position, line, column)
+    }
+
+    /// Read a string literal, the token spans both quotes
+    ///
+    /// A string missing its closing quote is returned as an illegal token
+    /// spanning the rest of the input
+    fn read_string(&mut self) -> Token {
+        let position = self.position;
+        let (line, column) = self.location();
+
+        loop {
+            self.read_char();
+            match self.ch {
+                '"' => break,
+                '\0' if self.at_end() => {
+                    return Token::new(TokenType::Illegal, position, self.position, line, column)
+                }
+                _ => {}
+            }
+        }
+        self.read_char();
+
+        Token::new(TokenType::String, position, self.position, line, column)
     }
 
     /// Consume the input and return the next token
     pub fn next_token(&mut self) -> Token {
-        self.consume_whitespace();
+        self.consume_trivia();
 
         let start_pos = self.position;
+        let (line, column) = self.location();
         let mut end_pos = self.read_position;
         let tok = match self.ch {
             '=' => {
                 if self.peek_char() == '=' {
                     self.read_char();
                     end_pos = self.read_position;
-                    Token::new(TokenType::EQ, start_pos, end_pos, self.line, self.column)
+                    Token::new(TokenType::EQ, start_pos, end_pos, line, column)
+                } else if self.peek_char() == '>' {
+                    self.read_char();
+                    end_pos = self.read_position;
+                    Token::new(TokenType::FatArrow, start_pos, end_pos, line, column)
                 } else {
-                    Token::new(
-                        TokenType::Assign,
-                        start_pos,
-                        end_pos,
-                        self.line,
-                        self.column,
-                    )
+                    Token::new(TokenType::Assign, start_pos, end_pos, line, column)
                 }
             }
             '!' => {
@@ -96,73 +220,79 @@ impl<'a> Lexer<'a> {
                     self.read_char();
                     end_pos = self.read_position;
 
-                    Token::new(TokenType::NotEQ, start_pos, end_pos, self.line, self.column)
+                    Token::new(TokenType::NotEQ, start_pos, end_pos, line, column)
                 } else {
-                    Token::new(TokenType::Bang, start_pos, end_pos, self.line, self.column)
+                    Token::new(TokenType::Bang, start_pos, end_pos, line, column)
                 }
             }
-            ';' => Token::new(
-                TokenType::Semicolon,
-                start_pos,
-                end_pos,
-                self.line,
-                self.column,
-            ),
-            '(' => Token::new(
-                TokenType::LParen,
-                start_pos,
-                end_pos,
-                self.line,
-                self.column,
-            ),
-            ')' => Token::new(
-                TokenType::RParen,
-                start_pos,
-                end_pos,
-                self.line,
-                self.column,
-            ),
-            ',' => Token::new(TokenType::Comma, start_pos, end_pos, self.line, self.column),
-            '+' => Token::new(TokenType::Plus, start_pos, end_pos, self.line, self.column),
-            '-' => Token::new(TokenType::Minus, start_pos, end_pos, self.line, self.column),
-            '/' => Token::new(TokenType::Slash, start_pos, end_pos, self.line, self.column),
-            '*' => Token::new(
-                TokenType::Asterisk,
-                start_pos,
-                end_pos,
-                self.line,
-                self.column,
-            ),
-            '<' => Token::new(TokenType::LT, start_pos, end_pos, self.line, self.column),
-            '>' => Token::new(TokenType::GT, start_pos, end_pos, self.line, self.column),
-            '{' => Token::new(
-                TokenType::LBrace,
-                start_pos,
-                end_pos,
-                self.line,
-                self.column,
-            ),
-            '}' => Token::new(
-                TokenType::RBrace,
-                start_pos,
-                end_pos,
-                self.line,
-                self.column,
-            ),
-            '\0' => Token::new(TokenType::EOF, start_pos, start_pos, self.line, self.column), // end pos is the same as start pos as there is no next char
+            ';' => Token::new(TokenType::Semicolon, start_pos, end_pos, line, column),
+            '(' => Token::new(TokenType::LParen, start_pos, end_pos, line, column),
+            ')' => Token::new(TokenType::RParen, start_pos, end_pos, line, column),
+            ',' => Token::new(TokenType::Comma, start_pos, end_pos, line, column),
+            ':' => {
+                if self.peek_char() == ':' {
+                    self.read_char();
+                    end_pos = self.read_position;
+                    Token::new(TokenType::ColonColon, start_pos, end_pos, line, column)
+                } else {
+                    Token::new(TokenType::Colon, start_pos, end_pos, line, column)
+                }
+            }
+            '.' if self.peek_char() == '.' => {
+                self.read_char();
+                if self.peek_char() == '=' {
+                    self.read_char();
+                    end_pos = self.read_position;
+                    Token::new(TokenType::DotDotEq, start_pos, end_pos, line, column)
+                } else if self.peek_char() == '.' {
+                    self.read_char();
+                    end_pos = self.read_position;
+                    Token::new(TokenType::DotDotDot, start_pos, end_pos, line, column)
+                } else {
+                    end_pos = self.read_position;
+                    Token::new(TokenType::DotDot, start_pos, end_pos, line, column)
+                }
+            }
+            // `.5` is a float
+            '.' if !self.peek_char().is_ascii_digit() => {
+                Token::new(TokenType::Dot, start_pos, end_pos, line, column)
+            }
+            '|' => {
+                if self.peek_char() == '>' {
+                    self.read_char();
+                    end_pos = self.read_position;
+                    Token::new(TokenType::Pipe, start_pos, end_pos, line, column)
+                } else {
+                    Token::new(TokenType::Bar, start_pos, end_pos, line, column)
+                }
+            }
+            '"' => return self.read_string(),
+            '+' => Token::new(TokenType::Plus, start_pos, end_pos, line, column),
+            '-' => {
+                if self.peek_char() == '>' {
+                    self.read_char();
+                    end_pos = self.read_position;
+                    Token::new(TokenType::Arrow, start_pos, end_pos, line, column)
+                } else {
+                    Token::new(TokenType::Minus, start_pos, end_pos, line, column)
+                }
+            }
+            '/' => Token::new(TokenType::Slash, start_pos, end_pos, line, column),
+            '*' => Token::new(TokenType::Asterisk, start_pos, end_pos, line, column),
+            '<' => Token::new(TokenType::LT, start_pos, end_pos, line, column),
+            '>' => Token::new(TokenType::GT, start_pos, end_pos, line, column),
+            '{' => Token::new(TokenType::LBrace, start_pos, end_pos, line, column),
+            '}' => Token::new(TokenType::RBrace, start_pos, end_pos, line, column),
+            '[' => Token::new(TokenType::LBracket, start_pos, end_pos, line, column),
+            ']' => Token::new(TokenType::RBracket, start_pos, end_pos, line, column),
+            '\0' if self.at_end() => Token::new(TokenType::EOF, start_pos, start_pos, line, column), // end pos is the same as start pos as there is no next char
             _ => {
                 if self.is_letter() {
                     return self.read_identifier();
                 } else if self.is_number() {
                     return self.read_number();
                 } else {
-                    return Token::new(
-                        TokenType::Illegal,
-                        start_pos,
-                        end_pos,
-                        self.line,
-                        self.column,
-                    );
+                    Token::new(TokenType::Illegal, start_pos, end_pos, line, column)
                 }
             }
         };
@@ -174,10 +304,18 @@ impl<'a> Lexer<'a> {
         self.ch.is_ascii_digit() || self.ch == '.'
     }
 
-    /// Consume all whitespace characters
-    fn consume_whitespace(&mut self) {
-        while self.ch.is_whitespace() {
-            self.read_char();
+    /// Consume whitespace and `//` comments, which run to the end of the line
+    fn consume_trivia(&mut self) {
+        loop {
+            if self.ch.is_whitespace() {
+                self.read_char();
+            } else if self.ch == '/' && self.peek_char() == '/' {
+                while self.ch != '\n' && !self.at_end() {
+                    self.read_char();
+                }
+            } else {
+                return;
+            }
         }
     }
     /// Checks if the current character is an alphabetic character or an underscore
@@ -186,11 +324,12 @@ impl<'a> Lexer<'a> {
     }
     pub fn read_identifier(&mut self) -> Token {
         let position = self.position;
+        let (line, column) = self.location();
         while self.is_letter() {
             self.read_char();
         }
         let literal = &self.input[position..self.position];
-        Token::lookup_ident(literal, position, self.position, self.line, self.column)
+        Token::lookup_ident(literal, position, self.position, line, column)
     }
 }
 
@@ -221,6 +360,70 @@ pub mod test {
         }
     }
     #[test]
+    fn test_brackets() {
+        let input = "[1, 2][0]";
+        let tokens = vec![
+            (TokenType::LBracket, "["),
+            (TokenType::Int, "1"),
+            (TokenType::Comma, ","),
+            (TokenType::Int, "2"),
+            (TokenType::RBracket, "]"),
+            (TokenType::LBracket, "["),
+            (TokenType::Int, "0"),
+            (TokenType::RBracket, "]"),
+            (TokenType::EOF, ""),
+        ];
+        let mut l = Lexer::new(input);
+        for (expected_type, expected_literal) in tokens {
+            let tok = l.next_token();
+            let literal = &input[tok.start..tok.end];
+            assert_eq!(tok.token_type, expected_type);
+            assert_eq!(literal, expected_literal);
+        }
+    }
+    #[test]
+    fn test_strings() {
+        let input = r#"{"pompe": "à bouette", 1: ""} "open"#;
+        let tokens = vec![
+            (TokenType::LBrace, "{"),
+            (TokenType::String, "\"pompe\""),
+            (TokenType::Colon, ":"),
+            (TokenType::String, "\"à bouette\""),
+            (TokenType::Comma, ","),
+            (TokenType::Int, "1"),
+            (TokenType::Colon, ":"),
+            (TokenType::String, "\"\""),
+            (TokenType::RBrace, "}"),
+            (TokenType::Illegal, "\"open"),
+            (TokenType::EOF, ""),
+        ];
+        let mut l = Lexer::new(input);
+        for (expected_type, expected_literal) in tokens {
+            let tok = l.next_token();
+            let literal = &input[tok.start..tok.end];
+            assert_eq!(tok.token_type, expected_type);
+            assert_eq!(literal, expected_literal);
+        }
+    }
+    #[test]
+    fn test_illegal_characters() {
+        let input = "a @€ b";
+        let tokens = vec![
+            (TokenType::Ident, "a"),
+            (TokenType::Illegal, "@"),
+            (TokenType::Illegal, "€"),
+            (TokenType::Ident, "b"),
+            (TokenType::EOF, ""),
+        ];
+        let mut l = Lexer::new(input);
+        for (expected_type, expected_literal) in tokens {
+            let tok = l.next_token();
+            let literal = &input[tok.start..tok.end];
+            assert_eq!(tok.token_type, expected_type);
+            assert_eq!(literal, expected_literal);
+        }
+    }
+    #[test]
     fn test_assignment() {
         let input = "let five = 5;";
         let tokens = vec![
@@ -362,4 +565,211 @@ pub mod test {
             assert_eq!(literal, expected_literal);
         }
     }
+    #[test]
+    fn test_comments() {
+        let input = "a // b / c\n/ d // e";
+        let tokens = vec![
+            (TokenType::Ident, "a"),
+            (TokenType::Slash, "/"),
+            (TokenType::Ident, "d"),
+            (TokenType::EOF, ""),
+        ];
+        let mut l = Lexer::new(input);
+        for (expected_type, expected_literal) in tokens {
+            let tok = l.next_token();
+            let literal = &input[tok.start..tok.end];
+            assert_eq!(tok.token_type, expected_type);
+            assert_eq!(literal, expected_literal);
+        }
+    }
+    #[test]
+    fn test_match_arms() {
+        let input = "match x { _ => a == b, _y => 1 }";
+        let tokens = vec![
+            (TokenType::Match, "match"),
+            (TokenType::Ident, "x"),
+            (TokenType::LBrace, "{"),
+            (TokenType::Underscore, "_"),
+            (TokenType::FatArrow, "=>"),
+            (TokenType::Ident, "a"),
+            (TokenType::EQ, "=="),
+            (TokenType::Ident, "b"),
+            (TokenType::Comma, ","),
+            (TokenType::Ident, "_y"),
+            (TokenType::FatArrow, "=>"),
+            (TokenType::Int, "1"),
+            (TokenType::RBrace, "}"),
+            (TokenType::EOF, ""),
+        ];
+        let mut l = Lexer::new(input);
+        for (expected_type, expected_literal) in tokens {
+            let tok = l.next_token();
+            let literal = &input[tok.start..tok.end];
+            assert_eq!(tok.token_type, expected_type);
+            assert_eq!(literal, expected_literal);
+        }
+    }
+    #[test]
+    fn test_field_access() {
+        let input = "p.x = .5 + a.b.c;";
+        let tokens = vec![
+            (TokenType::Ident, "p"),
+            (TokenType::Dot, "."),
+            (TokenType::Ident, "x"),
+            (TokenType::Assign, "="),
+            (TokenType::Float, ".5"),
+            (TokenType::Plus, "+"),
+            (TokenType::Ident, "a"),
+            (TokenType::Dot, "."),
+            (TokenType::Ident, "b"),
+            (TokenType::Dot, "."),
+            (TokenType::Ident, "c"),
+            (TokenType::Semicolon, ";"),
+            (TokenType::EOF, ""),
+        ];
+        let mut l = Lexer::new(input);
+        for (expected_type, expected_literal) in tokens {
+            let tok = l.next_token();
+            let literal = &input[tok.start..tok.end];
+            assert_eq!(tok.token_type, expected_type);
+            assert_eq!(literal, expected_literal);
+        }
+    }
+    #[test]
+    fn test_paths() {
+        let input = "Shape::Circle(1) {a: b}";
+        let tokens = vec![
+            (TokenType::Ident, "Shape"),
+            (TokenType::ColonColon, "::"),
+            (TokenType::Ident, "Circle"),
+            (TokenType::LParen, "("),
+            (TokenType::Int, "1"),
+            (TokenType::RParen, ")"),
+            (TokenType::LBrace, "{"),
+            (TokenType::Ident, "a"),
+            (TokenType::Colon, ":"),
+            (TokenType::Ident, "b"),
+            (TokenType::RBrace, "}"),
+            (TokenType::EOF, ""),
+        ];
+        let mut l = Lexer::new(input);
+        for (expected_type, expected_literal) in tokens {
+            let tok = l.next_token();
+            let literal = &input[tok.start..tok.end];
+            assert_eq!(tok.token_type, expected_type);
+            assert_eq!(literal, expected_literal);
+        }
+    }
+    #[test]
+    fn test_pipes() {
+        let input = "xs |> map(|x| x)||>";
+        let tokens = vec![
+            (TokenType::Ident, "xs"),
+            (TokenType::Pipe, "|>"),
+            (TokenType::Ident, "map"),
+            (TokenType::LParen, "("),
+            (TokenType::Bar, "|"),
+            (TokenType::Ident, "x"),
+            (TokenType::Bar, "|"),
+            (TokenType::Ident, "x"),
+            (TokenType::RParen, ")"),
+            (TokenType::Bar, "|"),
+            (TokenType::Pipe, "|>"),
+            (TokenType::EOF, ""),
+        ];
+        let mut l = Lexer::new(input);
+        for (expected_type, expected_literal) in tokens {
+            let tok = l.next_token();
+            let literal = &input[tok.start..tok.end];
+            assert_eq!(tok.token_type, expected_type);
+            assert_eq!(literal, expected_literal);
+        }
+    }
+    #[test]
+    fn test_ranges() {
+        let input = "0..10 1..=n[..2.5] 1. .5 [a, ...rest]";
+        let tokens = vec![
+            (TokenType::Int, "0"),
+            (TokenType::DotDot, ".."),
+            (TokenType::Int, "10"),
+            (TokenType::Int, "1"),
+            (TokenType::DotDotEq, "..="),
+            (TokenType::Ident, "n"),
+            (TokenType::LBracket, "["),
+            (TokenType::DotDot, ".."),
+            (TokenType::Float, "2.5"),
+            (TokenType::RBracket, "]"),
+            (TokenType::Float, "1."),
+            (TokenType::Float, ".5"),
+            (TokenType::LBracket, "["),
+            (TokenType::Ident, "a"),
+            (TokenType::Comma, ","),
+            (TokenType::DotDotDot, "..."),
+            (TokenType::Ident, "rest"),
+            (TokenType::RBracket, "]"),
+            (TokenType::EOF, ""),
+        ];
+        let mut l = Lexer::new(input);
+        for (expected_type, expected_literal) in tokens {
+            let tok = l.next_token();
+            let literal = &input[tok.start..tok.end];
+            assert_eq!(tok.token_type, expected_type);
+            assert_eq!(literal, expected_literal);
+        }
+    }
+    #[test]
+    fn test_annotations() {
+        let input = "fn(a: array<int>) -> int { a - 1 }";
+        let tokens = vec![
+            (TokenType::Fn, "fn"),
+            (TokenType::LParen, "("),
+            (TokenType::Ident, "a"),
+            (TokenType::Colon, ":"),
+            (TokenType::Ident, "array"),
+            (TokenType::LT, "<"),
+            (TokenType::Ident, "int"),
+            (TokenType::GT, ">"),
+            (TokenType::RParen, ")"),
+            (TokenType::Arrow, "->"),
+            (TokenType::Ident, "int"),
+            (TokenType::LBrace, "{"),
+            (TokenType::Ident, "a"),
+            (TokenType::Minus, "-"),
+            (TokenType::Int, "1"),
+            (TokenType::RBrace, "}"),
+            (TokenType::EOF, ""),
+        ];
+        let mut l = Lexer::new(input);
+        for (expected_type, expected_literal) in tokens {
+            let tok = l.next_token();
+            let literal = &input[tok.start..tok.end];
+            assert_eq!(tok.token_type, expected_type);
+            assert_eq!(literal, expected_literal);
+        }
+    }
+    #[test]
+    fn test_embedded_nul() {
+        let input = "a\0 \"b\0\" // c\0\nd";
+        let tokens = vec![
+            (TokenType::Ident, "a"),
+            (TokenType::Illegal, "\0"),
+            (TokenType::String, "\"b\0\""),
+            (TokenType::Ident, "d"),
+            (TokenType::EOF, ""),
+        ];
+        let mut l = Lexer::new(input);
+        for (expected_type, expected_literal) in tokens {
+            let tok = l.next_token();
+            let literal = &input[tok.start..tok.end];
+            assert_eq!(tok.token_type, expected_type);
+            assert_eq!(literal, expected_literal);
+        }
+        let mut l = Lexer::new(input);
+        l.next_token();
+        let nul = l.next_token();
+        assert_eq!(
+            l.illegal_error(&nul).to_string(),
+            "unexpected character '\\0'"
+        );
+    }
 }
@@ -1,11 +1,20 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use diagnostic::Diagnostic;
+use json::Json;
 use lexer::Lexer;
+use sexp::Sexp;
+use std::path::Path;
 use thiserror::Error as ThisError;
 
 pub mod ast;
+pub mod cst;
+pub mod diagnostic;
+pub mod json;
 pub mod lexer;
+pub mod module;
 pub mod parser;
 pub mod repl;
+pub mod sexp;
 pub mod token;
 
 /// Pompe a Bouette toy programming language
@@ -13,33 +22,102 @@ pub mod token;
 #[clap(author = "Louis-Philippe Turmel", version, about, long_about = None)]
 pub struct Cli {
     input_file: Option<String>,
+    /// How errors and warnings are reported
+    #[arg(long, value_enum, default_value_t = ErrorFormat::Human)]
+    error_format: ErrorFormat,
+    /// Print the tokens or the syntax tree of the input file to stdout
+    #[arg(long, value_enum, requires = "input_file")]
+    emit: Option<Emit>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum ErrorFormat {
+    /// Source snippets with underlined spans
+    Human,
+    /// One JSON object per line and diagnostic
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Emit {
+    /// One JSON object per line and token
+    Tokens,
+    /// The AST as JSON
+    #[value(name = "ast=json")]
+    AstJson,
+    /// The AST as an indented S-expression
+    #[value(name = "ast=sexp")]
+    AstSexp,
 }
 
 #[derive(Debug, ThisError)]
 enum Error {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
+    #[error("aborting due to {} previous error{}", .0, if *.0 == 1 { "" } else { "s" })]
+    Parse(usize),
 }
 
 fn main() {
-    if let Err(e) = run() {
-        eprintln!("{}", e);
+    let cli = Cli::parse();
+
+    if let Err(e) = run(&cli) {
+        match cli.error_format {
+            ErrorFormat::Human => eprintln!("{}", e),
+            ErrorFormat::Json => {
+                let file_path = cli.input_file.as_deref().unwrap_or_default();
+                eprintln!(
+                    "{}",
+                    Diagnostic::error(e.to_string()).to_json(file_path, "")
+                );
+            }
+        }
         std::process::exit(1);
     }
 }
-fn run() -> Result<(), Error> {
-    let cli = Cli::parse();
-
-    if let Some(input_file) = cli.input_file {
-        let input = std::fs::read_to_string(&input_file)?;
+fn run(cli: &Cli) -> Result<(), Error> {
+    if let Some(input_file) = &cli.input_file {
+        let input = std::fs::read_to_string(input_file)?;
 
-        let lexer = Lexer::new(&input);
-        let mut parser = parser::Parser::new(lexer, &input_file);
+        if cli.emit == Some(Emit::Tokens) {
+            let mut lexer = Lexer::new(&input);
+            loop {
+                let token = lexer.next_token();
+                println!("{}", Json::from(&token).with("text", lexer.literal(&token)));
+                if token.token_type == token::TokenType::EOF {
+                    return Ok(());
+                }
+            }
+        }
 
-        let _ = parser.parse();
+        // The imported files are loaded too, each one once
+        let mut loader = module::Loader::new();
+        let entry = loader.load(Path::new(input_file))?;
+        let program = loader.modules()[entry].program();
+        match cli.emit {
+            Some(Emit::AstJson) => println!("{}", Json::from(program)),
+            Some(Emit::AstSexp) => println!("{:#}", Sexp::from(program)),
+            Some(Emit::Tokens) | None => {}
+        }
 
-        for error in parser.errors() {
-            println!("parser errors:\n{}", error);
+        let color = diagnostic::use_color();
+        let mut errors = 0;
+        for module in loader.modules() {
+            let file_path = module.path().display().to_string();
+            for diagnostic in module.diagnostics() {
+                match cli.error_format {
+                    ErrorFormat::Human => {
+                        eprintln!("{}", diagnostic.render(&file_path, module.source(), color))
+                    }
+                    ErrorFormat::Json => {
+                        eprintln!("{}", diagnostic.to_json(&file_path, module.source()))
+                    }
+                }
+            }
+            errors += module.error_count();
+        }
+        if errors > 0 {
+            return Err(Error::Parse(errors));
         }
     } else {
         let mut repl = repl::Repl::new();
@@ -0,0 +1,361 @@
+//! Loading of the files a program imports
+//!
+//! The path of an import is relative to the importing file. Each file is
+//! parsed once, every module importing it shares the result. The macros of a
+//! file are expanded after it is parsed.
+
+use crate::ast::expand::{Expander, MacroError};
+use crate::ast::{ImportItems, ImportStatement, Program};
+use crate::diagnostic::Diagnostic;
+use crate::lexer::Lexer;
+use crate::parser::{ParseError, ParseWarning, Parser};
+use crate::token::Span;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error as ThisError;
+
+/// An import that can't be resolved, the span is in the importing file
+#[derive(Debug, Clone, PartialEq, ThisError)]
+pub enum ImportError {
+    #[error("can't read '{path}': {reason}")]
+    Unreadable {
+        path: String,
+        reason: String,
+        span: Span,
+    },
+    /// `chain` lists the files from the imported one back to itself
+    #[error("import cycle: {chain}")]
+    Cycle { chain: String, span: Span },
+    /// `exports` lists the names the module exports
+    #[error("'{path}' has no export '{name}'")]
+    MissingExport {
+        path: String,
+        name: String,
+        exports: Vec<String>,
+        span: Span,
+    },
+}
+
+impl ImportError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Unreadable { .. } => "E0201",
+            Self::Cycle { .. } => "E0202",
+            Self::MissingExport { .. } => "E0203",
+        }
+    }
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Unreadable { span, .. }
+            | Self::Cycle { span, .. }
+            | Self::MissingExport { span, .. } => *span,
+        }
+    }
+}
+
+impl From<&ImportError> for Diagnostic {
+    fn from(error: &ImportError) -> Self {
+        let diagnostic = Diagnostic::error(error.to_string()).with_code(error.code());
+        match error {
+            ImportError::Unreadable { span, .. } => diagnostic
+                .with_label(*span, "imported here")
+                .with_note("the path is relative to the importing file"),
+            ImportError::Cycle { span, .. } => diagnostic
+                .with_label(*span, "imports a file importing this one")
+                .with_help("move what both files need to a third one"),
+            ImportError::MissingExport { exports, span, .. } => {
+                let diagnostic = diagnostic.with_label(*span, "not exported");
+                if exports.is_empty() {
+                    diagnostic.with_note("the module exports nothing")
+                } else {
+                    diagnostic.with_note(format!("the exports are {}", exports.join(", ")))
+                }
+            }
+        }
+    }
+}
+
+/// A parsed file and the problems found in it
+#[derive(Debug)]
+pub struct Module {
+    path: PathBuf,
+    source: String,
+    program: Program,
+    errors: Vec<ParseError>,
+    warnings: Vec<ParseWarning>,
+    macro_errors: Vec<MacroError>,
+    import_errors: Vec<ImportError>,
+}
+
+impl Module {
+    /// The path the file was loaded from
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+    /// The warnings then the errors of the file, macro then imports ones
+    /// last
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.warnings
+            .iter()
+            .map(Diagnostic::from)
+            .chain(self.errors.iter().map(Diagnostic::from))
+            .chain(self.macro_errors.iter().map(Diagnostic::from))
+            .chain(self.import_errors.iter().map(Diagnostic::from))
+            .collect()
+    }
+    pub fn error_count(&self) -> usize {
+        self.errors.len() + self.macro_errors.len() + self.import_errors.len()
+    }
+}
+
+/// Parses a file and the files it imports
+#[derive(Debug, Default)]
+pub struct Loader {
+    /// In the order they finished loading, the imported files first
+    modules: Vec<Module>,
+    /// Index of the modules by canonical path
+    loaded: HashMap<PathBuf, usize>,
+    /// Canonical and shown path of the files being loaded, each one
+    /// importing the next
+    loading: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Loader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn modules(&self) -> &[Module] {
+        &self.modules
+    }
+    /// Load the file at `path` and the files it imports, returns the index
+    /// of its module
+    ///
+    /// Only the file itself being unreadable is an error, the problems of
+    /// the imports are reported in the module importing them.
+    pub fn load(&mut self, path: &Path) -> io::Result<usize> {
+        let canonical = path.canonicalize()?;
+        if let Some(&index) = self.loaded.get(&canonical) {
+            return Ok(index);
+        }
+        let source = std::fs::read_to_string(path)?;
+
+        let file_path = path.display().to_string();
+        let mut parser = Parser::new(Lexer::new(&source), &file_path);
+        let mut program = parser.parse();
+        let errors = parser.errors().to_vec();
+        let warnings = parser.warnings().to_vec();
+        let mut expander = Expander::new();
+        expander.expand(&mut program);
+        let macro_errors = expander.errors().to_vec();
+
+        self.loading.push((canonical.clone(), path.to_path_buf()));
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let mut import_errors = Vec::new();
+        for import in program.imports() {
+            self.load_import(dir, import, &mut import_errors);
+        }
+        self.loading.pop();
+
+        self.modules.push(Module {
+            path: path.to_path_buf(),
+            source,
+            program,
+            errors,
+            warnings,
+            macro_errors,
+            import_errors,
+        });
+        let index = self.modules.len() - 1;
+        self.loaded.insert(canonical, index);
+        Ok(index)
+    }
+    fn load_import(&mut self, dir: &Path, import: &ImportStatement, errors: &mut Vec<ImportError>) {
+        let path = normalize(&dir.join(import.path()));
+        let span = import.path_token().span();
+        let unreadable = |error: io::Error| ImportError::Unreadable {
+            path: path.display().to_string(),
+            reason: error.to_string(),
+            span,
+        };
+
+        let canonical = match path.canonicalize() {
+            Ok(canonical) => canonical,
+            Err(error) => return errors.push(unreadable(error)),
+        };
+        if let Some(start) = self.loading.iter().position(|(p, _)| *p == canonical) {
+            let chain: Vec<String> = self.loading[start..]
+                .iter()
+                .map(|(_, shown)| shown.display().to_string())
+                .chain(std::iter::once(path.display().to_string()))
+                .collect();
+            return errors.push(ImportError::Cycle {
+                chain: chain.join(" -> "),
+                span,
+            });
+        }
+        let index = match self.load(&path) {
+            Ok(index) => index,
+            Err(error) => return errors.push(unreadable(error)),
+        };
+
+        if let ImportItems::Names(names) = import.items() {
+            let exports: Vec<String> = self.modules[index]
+                .program
+                .exports()
+                .iter()
+                .map(|name| name.value().to_string())
+                .collect();
+            for name in names {
+                if !exports.iter().any(|export| export == name.value()) {
+                    errors.push(ImportError::MissingExport {
+                        path: path.display().to_string(),
+                        name: name.value().to_string(),
+                        exports: exports.clone(),
+                        span: name.token().span(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Remove the `.` of the path and the directories followed by `..`
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    /// Write the files to a new directory of the temporary directory
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pab-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, source) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        }
+        dir
+    }
+
+    fn messages(module: &Module) -> Vec<String> {
+        module
+            .diagnostics()
+            .iter()
+            .map(|d| d.message.clone())
+            .collect()
+    }
+
+    #[test]
+    fn load_imports_once() {
+        let dir = write_files(
+            "once",
+            &[
+                (
+                    "main.pab",
+                    r#"import "lib/math.pab" as math; import { add } from "./util.pab"; add(1, 2);"#,
+                ),
+                (
+                    "lib/math.pab",
+                    r#"import { add, Point } from "../util.pab"; export let double = fn(x) { add(x, x) };"#,
+                ),
+                (
+                    "util.pab",
+                    "let unless = macro(c, x) { quote(if (!(unquote(c))) { unquote(x) }) }; export let add = fn(a, b) { unless(false, a + b) }; export struct Point { x, y }",
+                ),
+            ],
+        );
+        let mut loader = Loader::new();
+
+        let entry = loader.load(&dir.join("main.pab")).unwrap();
+
+        let modules = loader.modules();
+        assert_eq!(entry, 2);
+        let paths: Vec<PathBuf> = modules.iter().map(|m| m.path().to_path_buf()).collect();
+        assert_eq!(
+            paths,
+            [
+                dir.join("util.pab"),
+                dir.join("lib/math.pab"),
+                dir.join("main.pab")
+            ]
+        );
+        for module in modules {
+            assert_eq!(module.error_count(), 0, "{:?}", messages(module));
+        }
+        assert_eq!(
+            modules[0].program().to_string(),
+            "export let add = fn(a, b) {\n    if (!false) {\n        a + b;\n    };\n};\nexport struct Point { x, y }"
+        );
+        assert_eq!(loader.load(&dir.join("./lib/../util.pab")).unwrap(), 0);
+        assert_eq!(loader.modules().len(), 3);
+    }
+
+    #[test]
+    fn import_errors() {
+        let dir = write_files(
+            "errors",
+            &[
+                (
+                    "a.pab",
+                    r#"import "b.pab" as b; import { x, y } from "c.pab"; import "missing.pab" as m;"#,
+                ),
+                ("b.pab", r#"import { f } from "./sub/../a.pab";"#),
+                ("c.pab", "export let x = 1; let y = 2;"),
+            ],
+        );
+        let mut loader = Loader::new();
+
+        let entry = loader.load(&dir.join("a.pab")).unwrap();
+
+        let shown = |name: &str| dir.join(name).display().to_string();
+        let b = &loader.modules()[0];
+        assert_eq!(b.path(), dir.join("b.pab"));
+        assert_eq!(
+            messages(b),
+            [format!(
+                "import cycle: {} -> {} -> {}",
+                shown("a.pab"),
+                shown("b.pab"),
+                shown("a.pab")
+            )]
+        );
+        let a = &loader.modules()[entry];
+        let messages = messages(a);
+        assert_eq!(messages.len(), 2, "{:?}", messages);
+        assert_eq!(
+            messages[0],
+            format!("'{}' has no export 'y'", shown("c.pab"))
+        );
+        assert!(messages[1].starts_with(&format!("can't read '{}': ", shown("missing.pab"))));
+        let codes: Vec<&str> = a.import_errors.iter().map(|e| e.code()).collect();
+        assert_eq!(codes, ["E0203", "E0201"]);
+        assert_eq!(a.import_errors[0].span().column, 34);
+
+        assert!(Loader::new().load(&dir.join("missing.pab")).is_err());
+    }
+}
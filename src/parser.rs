@@ -1,14 +1,80 @@
-use crate::ast::{self, LetStatement, Statement};
+use crate::ast::{
+    self, BlockStatement, BreakStatement, ConstStatement, ContinueStatement, EnumStatement,
+    ExportStatement, ExprId, Expression, ExpressionStatement, ImportItems, ImportStatement,
+    LetStatement, Literal, MatchArm, Parameter, Pattern, ReturnStatement, Statement, StmtId,
+    StructStatement, TypeAnnotation,
+};
+use crate::cst::{self, SyntaxKind};
 use crate::lexer::Lexer;
-use crate::token::{Token, TokenType};
+use crate::token::{Span, Token, TokenType};
+use std::collections::HashMap;
+
+mod arguments;
+mod constant;
+pub mod error;
+
+pub use error::{ParseError, ParseWarning};
+
+/// Binding power of the operators, from the loosest to the tightest
+#[derive(Debug, PartialEq, PartialOrd, Clone, Copy)]
+pub(crate) enum Precedence {
+    Lowest,
+    Assign,      // x = y
+    Range,       // 0..10
+    Equals,      // ==
+    LessGreater, // > or <
+    Pipe,        // x |> f
+    Sum,         // +
+    Product,     // *
+    Prefix,      // -x or !x
+    Call,        // add(x)
+    Index,       // arr[i] or p.x
+}
+
+impl From<&TokenType> for Precedence {
+    fn from(token_type: &TokenType) -> Self {
+        match token_type {
+            TokenType::Assign => Self::Assign,
+            TokenType::DotDot | TokenType::DotDotEq => Self::Range,
+            TokenType::EQ | TokenType::NotEQ => Self::Equals,
+            TokenType::LT | TokenType::GT => Self::LessGreater,
+            TokenType::Pipe => Self::Pipe,
+            TokenType::Plus | TokenType::Minus => Self::Sum,
+            TokenType::Asterisk | TokenType::Slash => Self::Product,
+            TokenType::LParen => Self::Call,
+            TokenType::LBracket | TokenType::Dot => Self::Index,
+            _ => Self::Lowest,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Parser<'a> {
     lexer: Lexer<'a>,
     cur_token: Token,
     peek_token: Token,
-    errors: Vec<String>,
+    errors: Vec<ParseError>,
+    warnings: Vec<ParseWarning>,
     token_count: usize,
+    /// Number of blocks the current token is nested in
+    block_depth: usize,
+    /// Keyword of the loops the current token is in, function bodies start
+    /// with none
+    loops: Vec<Token>,
+    /// Whether `Name {` begins a struct literal, see `with_struct_literals`
+    struct_literals: bool,
+    /// Variants and their fields of the enums declared so far, by enum name
+    enums: HashMap<String, Vec<(String, Vec<String>)>>,
+    /// The names bound in each scope, the innermost last, with the value and
+    /// name span of the ones bound by `const`
+    ///
+    /// A `let`, parameter or other binding of the name hides the constants
+    /// of the outer scopes.
+    consts: Vec<HashMap<String, Option<(Literal, Span)>>>,
+    /// Kind and byte range of the finished nodes, for the syntax tree
+    nodes: Vec<(SyntaxKind, usize, usize)>,
+    /// Arena the nodes are allocated in, handed over by `parse`
+    program: ast::Program,
     file_path: &'a str,
 }
 
@@ -19,7 +85,15 @@ impl<'a> Parser<'a> {
             cur_token: Token::new(TokenType::EOF, 0, 0, 1, 1),
             peek_token: Token::new(TokenType::EOF, 0, 0, 1, 1),
             errors: Vec::new(),
+            warnings: Vec::new(),
             token_count: 0,
+            block_depth: 0,
+            loops: Vec::new(),
+            struct_literals: true,
+            enums: HashMap::new(),
+            consts: vec![HashMap::new()],
+            nodes: Vec::new(),
+            program: ast::Program::new(),
             file_path,
         };
         // Read two tokens, so cur_token and peek_token are both set
@@ -27,24 +101,55 @@ impl<'a> Parser<'a> {
         p.next_token();
         p
     }
+    /// Allocate the nodes in `program`, which keeps the nodes parsed before
+    pub fn with_program(mut self, program: ast::Program) -> Self {
+        self.program = program;
+        self
+    }
 
     pub fn token_count(&self) -> usize {
         self.token_count
     }
-    pub fn errors(&self) -> &[String] {
+    pub fn errors(&self) -> &[ParseError] {
         &self.errors
     }
+    /// Problems that don't prevent the program from being parsed
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+    /// Lossless syntax tree of the input, call it after `parse`
+    pub fn syntax_tree(&self) -> cst::SyntaxNode {
+        cst::build(self.lexer.input(), &self.nodes)
+    }
+    /// Path of the parsed file, for rendering the errors
+    pub fn file_path(&self) -> &str {
+        self.file_path
+    }
 
-    fn peek_error(&mut self, token_type: &TokenType) {
-        let msg = format!(
-            "--> {}\n\t{}:{} expected next token to be '{}', got '{}' instead",
-            self.file_path,
-            self.cur_token.line,
-            self.cur_token.column - 1,
-            token_type,
-            self.peek_token.token_type
-        );
-        self.errors.push(msg);
+    /// Source of a token, for the error messages
+    fn literal(&self, token: &Token) -> String {
+        self.lexer.literal(token).to_string()
+    }
+    fn unexpected(&self, expected: Vec<TokenType>, found: &Token) -> ParseError {
+        ParseError::unexpected(expected, found, self.lexer.literal(found))
+    }
+    fn peek_error(&mut self, token_type: TokenType) {
+        let error = self.unexpected(vec![token_type], &self.peek_token);
+        self.errors.push(error);
+    }
+    fn no_prefix_parse_error(&mut self) {
+        let error = if self.cur_token_is(TokenType::EOF) {
+            ParseError::UnexpectedEof {
+                expected: String::from("an expression"),
+                span: self.cur_token.span(),
+            }
+        } else {
+            ParseError::ExpectedExpression {
+                found: self.literal(&self.cur_token),
+                span: self.cur_token.span(),
+            }
+        };
+        self.errors.push(error);
     }
     fn next_token(&mut self) {
         std::mem::swap(&mut self.cur_token, &mut self.peek_token);
@@ -52,16 +157,87 @@ impl<'a> Parser<'a> {
         self.token_count += 1;
     }
 
+    /// Record a node of the syntax tree from `start` to the current token
+    fn finish_node(&mut self, kind: SyntaxKind, start: usize) {
+        self.nodes.push((kind, start, self.cur_token.end));
+    }
+    /// Span from the beginning of `start` to the end of the current token
+    fn span_from(&self, start: Span) -> Span {
+        Span {
+            end: self.cur_token.end,
+            ..start
+        }
+    }
+
+    /// Value and name span of the constant `name` refers to, if it refers to
+    /// one
+    fn constant(&self, name: &str) -> Option<&(Literal, Span)> {
+        self.consts
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .and_then(Option::as_ref)
+    }
+    /// Bind the names in the innermost scope, hiding the constants of the
+    /// same name
+    fn bind<'i>(&mut self, names: impl IntoIterator<Item = &'i ast::Identifier>) {
+        if let Some(scope) = self.consts.last_mut() {
+            for name in names {
+                scope.insert(name.value().to_string(), None);
+            }
+        }
+    }
+    /// Run `parse` in a new scope with the names bound
+    fn scoped<'i, T>(
+        &mut self,
+        names: impl IntoIterator<Item = &'i ast::Identifier>,
+        parse: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        self.consts.push(HashMap::new());
+        self.bind(names);
+        let result = parse(self);
+        self.consts.pop();
+        result
+    }
+
+    /// Run `parse` with struct literals allowed or not
+    ///
+    /// They are forbidden in the expression before the braces of `for` and
+    /// `match`, where `xs {` begins the body. Delimiters lift the restriction,
+    /// `for p in (Point { x: 1 }) {` is fine.
+    fn with_struct_literals<T>(&mut self, allowed: bool, parse: impl FnOnce(&mut Self) -> T) -> T {
+        let outer = std::mem::replace(&mut self.struct_literals, allowed);
+        let result = parse(self);
+        self.struct_literals = outer;
+        result
+    }
+
     fn expect_peek(&mut self, token_type: TokenType) -> bool {
         if self.peek_token_is(&token_type) {
             self.next_token();
             true
         } else {
-            self.peek_error(&token_type);
+            self.peek_error(token_type);
             false
         }
     }
 
+    /// Statements end with a semicolon, which can be left out before the
+    /// closing brace of a block
+    fn expect_semicolon(&mut self) -> bool {
+        if self.peek_token_is(&TokenType::Semicolon) {
+            self.next_token();
+            return true;
+        }
+        if self.peek_token_is(&TokenType::RBrace) && self.block_depth > 0 {
+            return true;
+        }
+        self.errors.push(ParseError::MissingSemicolon {
+            span: self.cur_token.span(),
+        });
+        false
+    }
+
     fn cur_token_is(&self, token_type: TokenType) -> bool {
         self.cur_token.token_type == token_type
     }
@@ -70,148 +246,3759 @@ impl<'a> Parser<'a> {
         self.peek_token.token_type == *token_type
     }
 
+    fn peek_precedence(&self) -> Precedence {
+        Precedence::from(&self.peek_token.token_type)
+    }
+
+    fn cur_precedence(&self) -> Precedence {
+        Precedence::from(&self.cur_token.token_type)
+    }
+
     pub fn parse(&mut self) -> ast::Program {
-        let mut program = ast::Program::new();
+        let mut statements = Vec::new();
 
         while self.cur_token.token_type != TokenType::EOF {
-            let stmt = self.parse_stmt();
-            if let Some(stmt) = stmt {
-                program.statements.push(stmt);
+            let start = self.token_count;
+            match self.parse_stmt() {
+                Some(stmt) => {
+                    statements.push(stmt);
+                    self.next_token();
+                }
+                None => self.synchronize(start),
             }
-            self.next_token();
         }
 
-        program
+        self.program.statements = statements;
+        self.check_arguments();
+        std::mem::take(&mut self.program)
     }
-    fn parse_stmt(&mut self) -> Option<Statement> {
-        match self.cur_token.token_type {
-            TokenType::Let => self
-                .parse_let_stmt()
-                .map(|stmt| Statement::Let(Box::new(stmt))),
-            _ => None,
+    /// Skip the rest of a statement that failed to parse, leaving the parser
+    /// at the beginning of the next one
+    ///
+    /// Synchronization points are a `;` outside of nested braces, the closing
+    /// brace of the enclosing block and statement keywords. `start` is the
+    /// token count when the statement began, we never stop on its first token
+    /// so every call makes progress.
+    fn synchronize(&mut self, start: usize) {
+        let mut depth = 0;
+        loop {
+            match self.cur_token.token_type {
+                TokenType::EOF => return,
+                TokenType::LBrace => depth += 1,
+                TokenType::RBrace if depth > 0 => depth -= 1,
+                TokenType::RBrace if self.block_depth > 0 => return,
+                TokenType::Semicolon if depth == 0 => {
+                    self.next_token();
+                    return;
+                }
+                ref token_type
+                    if depth == 0
+                        && self.token_count > start
+                        && token_type.is_statement_keyword() =>
+                {
+                    return
+                }
+                _ => {}
+            }
+            self.next_token();
         }
     }
+    fn parse_stmt(&mut self) -> Option<StmtId> {
+        let start = self.cur_token.span();
+        let stmt = match self.cur_token.token_type {
+            TokenType::Let => Statement::Let(self.parse_let_stmt()?),
+            TokenType::Const => {
+                let (stmt, value) = self.parse_const_stmt()?;
+                let id = self.program.alloc_statement(Statement::Const(stmt));
+                self.program.set_const_value(id, value);
+                self.program.set_statement_span(id, self.span_from(start));
+                return Some(id);
+            }
+            TokenType::Return => Statement::Return(self.parse_return_stmt()?),
+            TokenType::Break => Statement::Break(self.parse_break_stmt()?),
+            TokenType::Continue => Statement::Continue(self.parse_continue_stmt()?),
+            TokenType::Struct => Statement::Struct(self.parse_struct_stmt()?),
+            TokenType::Enum => Statement::Enum(self.parse_enum_stmt()?),
+            TokenType::Import => Statement::Import(self.parse_import_stmt()?),
+            TokenType::Export => Statement::Export(self.parse_export_stmt()?),
+            _ => Statement::Expression(self.parse_expression_stmt()?),
+        };
+        let id = self.program.alloc_statement(stmt);
+        self.program.set_statement_span(id, self.span_from(start));
+        Some(id)
+    }
     fn parse_let_stmt(&mut self) -> Option<LetStatement> {
         let token = self.cur_token.clone();
 
-        let is_mut = self.peek_token_is(&TokenType::Mut);
+        // Most statements bind a name, it is what's expected of the others
+        if !matches!(
+            self.peek_token.token_type,
+            TokenType::Ident
+                | TokenType::Mut
+                | TokenType::Underscore
+                | TokenType::LBracket
+                | TokenType::LBrace
+                | TokenType::DotDotDot
+        ) {
+            self.peek_error(TokenType::Ident);
+            return None;
+        }
+        self.next_token();
 
-        if is_mut {
-            self.next_token();
+        let pattern = self.parse_pattern()?;
+        if !self.is_irrefutable_let(&pattern) || !self.has_unique_bindings(&pattern) {
+            return None;
+        }
+        let annotation = self.parse_annotation()?;
+
+        if !self.expect_peek(TokenType::Assign) {
+            return None;
+        }
+        self.next_token();
+
+        let value = self.parse_expression(Precedence::Lowest)?;
+        if !self.can_destructure(&pattern, value) {
+            return None;
+        }
+
+        if !self.expect_semicolon() {
+            return None;
         }
+        self.finish_node(SyntaxKind::LetStmt, token.start);
+        self.bind(pattern.bindings());
+
+        Some(LetStatement::new(token, pattern, annotation, value))
+    }
+    /// Parse `const NAME = value;` and evaluate the value, which must be a
+    /// constant expression
+    fn parse_const_stmt(&mut self) -> Option<(ConstStatement, Literal)> {
+        let token = self.cur_token.clone();
 
         if !self.expect_peek(TokenType::Ident) {
             return None;
         }
+        let name = self.parse_identifier();
+        let annotation = self.parse_annotation()?;
 
-        let name = ast::Identifier::new(
-            self.cur_token.clone(),
-            self.cur_token.start,
-            self.cur_token.end,
+        if !self.expect_peek(TokenType::Assign) {
+            return None;
+        }
+        self.next_token();
+
+        let value = self.parse_expression(Precedence::Lowest)?;
+        let literal = match self.evaluate_const(value) {
+            Ok(literal) => literal,
+            Err(error) => {
+                self.errors.push(error);
+                return None;
+            }
+        };
+        let scope = self.consts.last_mut()?;
+        if let Some(Some((_, first))) = scope.get(name.value()) {
+            self.errors.push(ParseError::DuplicateConst {
+                name: name.value().to_string(),
+                span: name.token().span(),
+                first: *first,
+            });
+            return None;
+        }
+        let declaration = name.token().span();
+        scope.insert(
+            name.value().to_string(),
+            Some((literal.clone(), declaration)),
         );
 
-        if !self.expect_peek(TokenType::Assign) {
+        if !self.expect_semicolon() {
+            return None;
+        }
+        self.finish_node(SyntaxKind::ConstStmt, token.start);
+
+        Some((ConstStatement::new(token, name, annotation, value), literal))
+    }
+    /// Report the part of a `let` pattern that can fail to match, `let` has
+    /// no other branch to take
+    fn is_irrefutable_let(&mut self, pattern: &Pattern) -> bool {
+        fn refutable(pattern: &Pattern) -> Option<&Pattern> {
+            match pattern {
+                Pattern::Literal { .. } | Pattern::Variant { .. } => Some(pattern),
+                Pattern::Array { elements, .. } => elements.iter().find_map(refutable),
+                Pattern::Struct { fields, .. } => {
+                    fields.iter().find_map(|(_, pattern)| refutable(pattern))
+                }
+                Pattern::Wildcard(_) | Pattern::Binding { .. } | Pattern::Rest { .. } => None,
+            }
+        }
+        match refutable(pattern) {
+            Some(pattern) => {
+                self.errors.push(ParseError::RefutablePattern {
+                    span: pattern.token().span(),
+                });
+                false
+            }
+            None => true,
+        }
+    }
+    /// Report a name bound twice by `pattern`
+    fn has_unique_bindings(&mut self, pattern: &Pattern) -> bool {
+        let bindings = pattern.bindings();
+        for (i, binding) in bindings.iter().enumerate() {
+            if let Some(first) = bindings[..i].iter().find(|b| b.value() == binding.value()) {
+                self.errors.push(ParseError::DuplicateBinding {
+                    name: binding.value().to_string(),
+                    span: binding.token().span(),
+                    first: first.token().span(),
+                });
+                return false;
+            }
+        }
+        true
+    }
+    /// Report a pattern that can't match the literal it destructures, the
+    /// other values are only known when the program runs
+    fn can_destructure(&mut self, pattern: &Pattern, value: ExprId) -> bool {
+        let reason = match (pattern, &self.program[value]) {
+            (
+                Pattern::Array { elements, .. },
+                Expression::Array {
+                    elements: values, ..
+                },
+            ) => {
+                let values = values.clone();
+                let (before, after) = Self::split_rest(elements);
+                let fixed = before.len() + after.map_or(0, <[Pattern]>::len);
+                match after {
+                    None if values.len() != fixed => format!(
+                        "the array has {} element(s), the pattern expects {}",
+                        values.len(),
+                        fixed
+                    ),
+                    Some(_) if values.len() < fixed => format!(
+                        "the array has {} element(s), the pattern expects at least {}",
+                        values.len(),
+                        fixed
+                    ),
+                    _ => {
+                        let after = after.unwrap_or_default();
+                        return before
+                            .iter()
+                            .zip(&values)
+                            .chain(after.iter().rev().zip(values.iter().rev()))
+                            .all(|(pattern, value)| self.can_destructure(pattern, *value));
+                    }
+                }
+            }
+            (
+                Pattern::Struct { name, fields, .. },
+                Expression::Struct {
+                    name: value_name,
+                    fields: values,
+                    ..
+                },
+            ) => {
+                if let Some(name) = name.as_ref().filter(|n| n.value() != value_name.value()) {
+                    format!(
+                        "the value is a '{}', the pattern expects a '{}'",
+                        value_name.value(),
+                        name.value()
+                    )
+                } else {
+                    let given = |field: &ast::Identifier| {
+                        values
+                            .iter()
+                            .find(|(f, _)| f.value() == field.value())
+                            .map(|(_, value)| *value)
+                    };
+                    match fields.iter().find(|(field, _)| given(field).is_none()) {
+                        Some((field, _)) => format!(
+                            "the '{}' has no field '{}'",
+                            value_name.value(),
+                            field.value()
+                        ),
+                        None => {
+                            let fields: Vec<(&Pattern, ExprId)> = fields
+                                .iter()
+                                .filter_map(|(field, pattern)| Some((pattern, given(field)?)))
+                                .collect();
+                            return fields
+                                .into_iter()
+                                .all(|(pattern, value)| self.can_destructure(pattern, value));
+                        }
+                    }
+                }
+            }
+            (Pattern::Struct { name: None, .. }, Expression::Hash { .. }) => return true,
+            (Pattern::Array { .. } | Pattern::Struct { .. }, value) => {
+                let found = match value {
+                    Expression::Integer { .. } => "an integer",
+                    Expression::Float { .. } => "a float",
+                    Expression::Boolean { .. } => "a boolean",
+                    Expression::String { .. } => "a string",
+                    Expression::Array { .. } => "an array",
+                    Expression::Hash { .. } => "a hash",
+                    Expression::Function { .. } => "a function",
+                    Expression::Macro { .. } => "a macro",
+                    Expression::Range { .. } => "a range",
+                    _ => return true,
+                };
+                let expected = match pattern {
+                    Pattern::Struct {
+                        name: Some(name), ..
+                    } => format!("a '{}'", name.value()),
+                    Pattern::Struct { .. } => String::from("a struct or a hash"),
+                    _ => String::from("an array"),
+                };
+                format!("the value is {}, the pattern expects {}", found, expected)
+            }
+            _ => return true,
+        };
+        self.errors.push(ParseError::PatternMismatch {
+            reason,
+            span: pattern.token().span(),
+            value: self.program[value].token().span(),
+        });
+        false
+    }
+    fn parse_return_stmt(&mut self) -> Option<ReturnStatement> {
+        let token = self.cur_token.clone();
+        self.next_token();
+
+        let value = self.parse_expression(Precedence::Lowest)?;
+
+        if !self.expect_semicolon() {
             return None;
         }
+        self.finish_node(SyntaxKind::ReturnStmt, token.start);
+
+        Some(ReturnStatement::new(token, value))
+    }
+    /// `break` can only carry a value out of a `loop`, the other loops have
+    /// no value
+    fn parse_break_stmt(&mut self) -> Option<BreakStatement> {
+        let token = self.cur_token.clone();
+        let Some(enclosing) = self.loops.last().cloned() else {
+            self.errors.push(ParseError::OutsideLoop {
+                keyword: token.token_type.clone(),
+                span: token.span(),
+            });
+            return None;
+        };
 
-        // TODO: We're skipping the expressions until we
-        // encounter a semicolon
-        while !self.cur_token_is(TokenType::Semicolon) {
+        let value = if self.peek_token_is(&TokenType::Semicolon)
+            || self.peek_token_is(&TokenType::RBrace)
+        {
+            None
+        } else if enclosing.token_type != TokenType::Loop {
+            self.errors.push(ParseError::BreakWithValue {
+                span: self.peek_token.span(),
+                enclosing: enclosing.span(),
+            });
+            return None;
+        } else {
             self.next_token();
+            Some(self.parse_expression(Precedence::Lowest)?)
+        };
+
+        if !self.expect_semicolon() {
+            return None;
         }
+        self.finish_node(SyntaxKind::BreakStmt, token.start);
 
-        Some(LetStatement::new(
-            token, name, is_mut,
-            // Box::new(ast::Identifier::default()),
-        ))
+        Some(BreakStatement::new(token, value))
     }
-}
+    fn parse_continue_stmt(&mut self) -> Option<ContinueStatement> {
+        let token = self.cur_token.clone();
+        if self.loops.is_empty() {
+            self.errors.push(ParseError::OutsideLoop {
+                keyword: token.token_type.clone(),
+                span: token.span(),
+            });
+            return None;
+        }
 
-#[cfg(test)]
-pub mod test {
+        if !self.expect_semicolon() {
+            return None;
+        }
+        self.finish_node(SyntaxKind::ContinueStmt, token.start);
 
-    #[test]
-    fn parse_let_stmt() {
-        let input = r#" let x = 5; let y = 10; let a = 838383; "#;
-        let lexer = crate::lexer::Lexer::new(input);
-        let mut parser = super::Parser::new(lexer, "test");
-        let p = parser.parse();
-        if !parser.errors.is_empty() {
-            println!("parser has {} errors", parser.errors.len());
-            for error in parser.errors {
-                println!("parser error: {}", error);
+        Some(ContinueStatement::new(token))
+    }
+    /// Parse `import "path" as name;` or `import { name, ... } from "path";`
+    fn parse_import_stmt(&mut self) -> Option<ImportStatement> {
+        let token = self.cur_token.clone();
+        if !self.is_top_level(&token) {
+            return None;
+        }
+
+        let items = match self.peek_token.token_type {
+            TokenType::String => None,
+            TokenType::LBrace => {
+                self.next_token();
+                let mut names = Vec::new();
+                while !self.peek_token_is(&TokenType::RBrace) {
+                    if !self.expect_peek(TokenType::Ident) {
+                        return None;
+                    }
+                    names.push(self.parse_identifier());
+
+                    if !self.peek_token_is(&TokenType::RBrace)
+                        && !self.expect_peek(TokenType::Comma)
+                    {
+                        return None;
+                    }
+                }
+                self.next_token();
+                if !self.expect_peek(TokenType::From) {
+                    return None;
+                }
+                Some(ImportItems::Names(names))
             }
-            panic!();
+            _ => {
+                let error =
+                    self.unexpected(vec![TokenType::String, TokenType::LBrace], &self.peek_token);
+                self.errors.push(error);
+                return None;
+            }
+        };
+
+        if !self.expect_peek(TokenType::String) {
+            return None;
         }
-        assert_eq!(p.statements.len(), 3);
+        let path_token = self.cur_token.clone();
+        let path = self.string_value();
+
+        let items = match items {
+            Some(items) => items,
+            None => {
+                if !self.expect_peek(TokenType::As) || !self.expect_peek(TokenType::Ident) {
+                    return None;
+                }
+                ImportItems::Module(self.parse_identifier())
+            }
+        };
+
+        if !self.expect_semicolon() {
+            return None;
+        }
+        self.finish_node(SyntaxKind::ImportStmt, token.start);
+
+        Some(ImportStatement::new(token, path_token, path, items))
     }
-    #[test]
-    fn parse_mut_let_stmt() {
-        let input = r#"
-        let x = 3;
-        let mut y = 5;
-        y = 10;
-        "#;
-        let lexer = crate::lexer::Lexer::new(input);
-        let mut parser = super::Parser::new(lexer, "test");
+    /// Parse `export` followed by a `let`, `const`, `struct` or `enum`
+    /// declaration
+    fn parse_export_stmt(&mut self) -> Option<ExportStatement> {
+        let token = self.cur_token.clone();
+        if !self.is_top_level(&token) {
+            return None;
+        }
 
-        let p = parser.parse();
+        if !matches!(
+            self.peek_token.token_type,
+            TokenType::Let | TokenType::Const | TokenType::Struct | TokenType::Enum
+        ) {
+            let error = if self.peek_token_is(&TokenType::EOF) {
+                ParseError::UnexpectedEof {
+                    expected: String::from("a declaration"),
+                    span: self.peek_token.span(),
+                }
+            } else {
+                ParseError::ExpectedDeclaration {
+                    found: self.literal(&self.peek_token),
+                    span: self.peek_token.span(),
+                }
+            };
+            self.errors.push(error);
+            return None;
+        }
+        self.next_token();
 
-        if !parser.errors.is_empty() {
-            println!("parser has {} errors", parser.errors.len());
-            for error in parser.errors {
-                println!("parser error: {}", error);
+        let declaration = self.parse_stmt()?;
+        self.finish_node(SyntaxKind::ExportStmt, token.start);
+
+        Some(ExportStatement::new(token, declaration))
+    }
+    /// Report the `import` or `export` keyword of a statement inside a block
+    fn is_top_level(&mut self, keyword: &Token) -> bool {
+        if self.block_depth == 0 {
+            return true;
+        }
+        self.errors.push(ParseError::NotTopLevel {
+            keyword: keyword.token_type.clone(),
+            span: keyword.span(),
+        });
+        false
+    }
+    /// Parse `struct Name { field, ... }`, the semicolon is optional
+    fn parse_struct_stmt(&mut self) -> Option<StructStatement> {
+        let token = self.cur_token.clone();
+
+        if !self.expect_peek(TokenType::Ident) {
+            return None;
+        }
+        let name = self.parse_identifier();
+
+        if !self.expect_peek(TokenType::LBrace) {
+            return None;
+        }
+
+        let mut fields: Vec<ast::Identifier> = Vec::new();
+        while !self.peek_token_is(&TokenType::RBrace) {
+            if !self.expect_peek(TokenType::Ident) {
+                return None;
+            }
+            let field = self.parse_identifier();
+            if !self.is_new_field(fields.iter(), &field) {
+                return None;
+            }
+            fields.push(field);
+
+            if !self.peek_token_is(&TokenType::RBrace) && !self.expect_peek(TokenType::Comma) {
+                return None;
             }
-            panic!();
         }
+        self.next_token();
 
-        let mut stmts = p.statements.iter();
-        let first_stmt = stmts.next().unwrap();
-        let sec_stmt = stmts.next().unwrap();
+        if self.peek_token_is(&TokenType::Semicolon) {
+            self.next_token();
+        }
+        self.finish_node(SyntaxKind::StructStmt, token.start);
 
-        assert!(!first_stmt.is_mut());
-        assert!(sec_stmt.is_mut());
+        Some(StructStatement::new(token, name, fields))
     }
-    #[test]
-    fn parse_from_file() {
-        let input = std::fs::read_to_string("input/large.pab").unwrap();
+    /// Parse `enum Name { Variant(field, ...), ... }`, the semicolon is
+    /// optional
+    ///
+    /// The enum is remembered to check the variants used after it.
+    fn parse_enum_stmt(&mut self) -> Option<EnumStatement> {
+        let token = self.cur_token.clone();
 
-        let lexer = crate::lexer::Lexer::new(&input);
-        let mut parser = super::Parser::new(lexer, "test");
+        if !self.expect_peek(TokenType::Ident) {
+            return None;
+        }
+        let name = self.parse_identifier();
 
-        let p = parser.parse();
+        if !self.expect_peek(TokenType::LBrace) {
+            return None;
+        }
 
-        if !parser.errors.is_empty() {
-            println!("parser has {} errors", parser.errors.len());
-            for error in parser.errors {
-                println!("parser error: {}", error);
+        let mut variants: Vec<ast::Variant> = Vec::new();
+        while !self.peek_token_is(&TokenType::RBrace) {
+            if !self.expect_peek(TokenType::Ident) {
+                return None;
+            }
+            let start = self.cur_token.start;
+            let variant = self.parse_identifier();
+            if let Some(first) = variants
+                .iter()
+                .find(|v| v.name().value() == variant.value())
+            {
+                self.errors.push(ParseError::DuplicateVariant {
+                    name: variant.value().to_string(),
+                    span: variant.token().span(),
+                    first: first.name().token().span(),
+                });
+                return None;
+            }
+
+            let mut fields: Vec<ast::Identifier> = Vec::new();
+            if self.peek_token_is(&TokenType::LParen) {
+                self.next_token();
+                while !self.peek_token_is(&TokenType::RParen) {
+                    if !self.expect_peek(TokenType::Ident) {
+                        return None;
+                    }
+                    let field = self.parse_identifier();
+                    if !self.is_new_field(fields.iter(), &field) {
+                        return None;
+                    }
+                    fields.push(field);
+
+                    if !self.peek_token_is(&TokenType::RParen)
+                        && !self.expect_peek(TokenType::Comma)
+                    {
+                        return None;
+                    }
+                }
+                self.next_token();
+            }
+            self.finish_node(SyntaxKind::EnumVariant, start);
+            variants.push(ast::Variant::new(variant, fields));
+
+            if !self.peek_token_is(&TokenType::RBrace) && !self.expect_peek(TokenType::Comma) {
+                return None;
             }
-            panic!();
         }
+        self.next_token();
 
-        assert_eq!(p.statements.len(), 518400);
+        if self.peek_token_is(&TokenType::Semicolon) {
+            self.next_token();
+        }
+        self.finish_node(SyntaxKind::EnumStmt, token.start);
+
+        let declared = variants
+            .iter()
+            .map(|v| {
+                let fields = v.fields().iter().map(|f| f.value().to_string());
+                (v.name().value().to_string(), fields.collect())
+            })
+            .collect();
+        self.enums.insert(name.value().to_string(), declared);
+
+        Some(EnumStatement::new(token, name, variants))
     }
-    #[test]
-    fn test_line_nb_error() {
-        let input = r#"let x = 5;
-let = 10;"#;
+    /// Report `variant` if its enum was declared without it or with another
+    /// number of fields than `found`
+    ///
+    /// Enums not declared yet are not checked.
+    fn is_valid_variant(
+        &mut self,
+        enum_name: &ast::Identifier,
+        variant: &ast::Identifier,
+        found: usize,
+    ) -> bool {
+        let Some(variants) = self.enums.get(enum_name.value()) else {
+            return true;
+        };
+        let error = match variants.iter().find(|(v, _)| v == variant.value()) {
+            None => ParseError::UnknownVariant {
+                enum_name: enum_name.value().to_string(),
+                variant: variant.value().to_string(),
+                variants: variants.iter().map(|(v, _)| v.clone()).collect(),
+                span: variant.token().span(),
+            },
+            Some((_, fields)) if fields.len() != found => ParseError::VariantArity {
+                path: format!("{}::{}", enum_name.value(), variant.value()),
+                expected: fields.len(),
+                found,
+                declaration: if fields.is_empty() {
+                    variant.value().to_string()
+                } else {
+                    format!("{}({})", variant.value(), fields.join(", "))
+                },
+                span: variant.token().span(),
+            },
+            Some(_) => return true,
+        };
+        self.errors.push(error);
+        false
+    }
+    /// Report `field` if it is one of the `given` fields
+    fn is_new_field<'f>(
+        &mut self,
+        mut given: impl Iterator<Item = &'f ast::Identifier>,
+        field: &ast::Identifier,
+    ) -> bool {
+        match given.find(|f| f.value() == field.value()) {
+            Some(first) => {
+                self.errors.push(ParseError::DuplicateField {
+                    name: field.value().to_string(),
+                    span: field.token().span(),
+                    first: first.token().span(),
+                });
+                false
+            }
+            None => true,
+        }
+    }
+    fn parse_expression_stmt(&mut self) -> Option<ExpressionStatement> {
+        let token = self.cur_token.clone();
+        let expression = self.parse_expression(Precedence::Lowest)?;
 
-        let lexer = crate::lexer::Lexer::new(input);
-        let mut parser = super::Parser::new(lexer, "test");
+        if self.peek_token_is(&TokenType::Semicolon) {
+            self.next_token();
+        }
+        self.finish_node(SyntaxKind::ExprStmt, token.start);
 
-        let p = parser.parse();
+        Some(ExpressionStatement::new(token, expression))
+    }
+    fn parse_block_stmt(&mut self) -> Option<BlockStatement> {
+        self.with_struct_literals(true, |p| p.scoped([], Self::parse_block))
+    }
+    fn parse_block(&mut self) -> Option<BlockStatement> {
+        let token = self.cur_token.clone();
+        let mut statements = Vec::new();
+        self.next_token();
 
-        assert_eq!(parser.errors.len(), 1);
+        self.block_depth += 1;
+        while !self.cur_token_is(TokenType::RBrace) && !self.cur_token_is(TokenType::EOF) {
+            let start = self.token_count;
+            match self.parse_stmt() {
+                Some(stmt) => {
+                    statements.push(stmt);
+                    self.next_token();
+                }
+                None => self.synchronize(start),
+            }
+        }
+        self.block_depth -= 1;
+
+        if self.cur_token_is(TokenType::EOF) {
+            self.errors.push(ParseError::UnclosedBlock {
+                open: token.span(),
+                span: self.cur_token.span(),
+            });
+            return None;
+        }
+        self.finish_node(SyntaxKind::Block, token.start);
+
+        Some(BlockStatement::new(token, statements, self.cur_token.end))
     }
-    #[test]
-    fn test_line_nb_error_file() {
-        let input = std::fs::read_to_string("input/line_nb_error.pab").unwrap();
 
-        let lexer = crate::lexer::Lexer::new(&input);
-        let mut parser = super::Parser::new(lexer, "test");
+    /// Parse an expression using Pratt parsing
+    ///
+    /// Infix operators are folded into the left-hand side for as long as
+    /// they bind tighter than `precedence`
+    fn parse_expression(&mut self, precedence: Precedence) -> Option<ExprId> {
+        let start = self.cur_token.span();
+        // Parentheses leave no trace in the AST, they get their own node
+        let grouped = self.cur_token_is(TokenType::LParen);
+        let mut left = self.parse_prefix()?;
+        if !grouped {
+            self.finish_node(SyntaxKind::from(&self.program[left]), start.start);
+            self.program
+                .set_expression_span(left, self.span_from(start));
+        }
 
-        let p = parser.parse();
+        while !self.peek_token_is(&TokenType::Semicolon) && precedence < self.peek_precedence() {
+            self.next_token();
+            // A pipe is a call in the AST, its node keeps the operands in
+            // source order
+            let kind = if self.cur_token_is(TokenType::Pipe) {
+                Some(SyntaxKind::PipeExpr)
+            } else {
+                None
+            };
+            left = self.parse_infix(left)?;
+            let kind = kind.unwrap_or_else(|| SyntaxKind::from(&self.program[left]));
+            self.finish_node(kind, start.start);
+            self.program
+                .set_expression_span(left, self.span_from(start));
+        }
 
-        assert_eq!(parser.errors.len(), 1);
+        Some(left)
+    }
+    fn parse_prefix(&mut self) -> Option<ExprId> {
+        let expr = match self.cur_token.token_type {
+            TokenType::Ident if self.peek_token_is(&TokenType::ColonColon) => {
+                self.parse_variant_expression()?
+            }
+            TokenType::Ident if self.struct_literals && self.peek_token_is(&TokenType::LBrace) => {
+                self.parse_struct_literal()?
+            }
+            TokenType::Ident => Expression::Identifier(self.parse_identifier()),
+            TokenType::Int => self.parse_integer_literal()?,
+            TokenType::Float => self.parse_float_literal()?,
+            TokenType::String => self.parse_string_literal(),
+            TokenType::True | TokenType::False => Expression::Boolean {
+                token: self.cur_token.clone(),
+                value: self.cur_token_is(TokenType::True),
+            },
+            TokenType::Bang | TokenType::Minus => self.parse_prefix_expression()?,
+            TokenType::LParen => {
+                return self.with_struct_literals(true, Self::parse_grouped_expression)
+            }
+            TokenType::If => self.parse_if_expression()?,
+            TokenType::While => self.parse_while_expression()?,
+            TokenType::For => self.parse_for_expression()?,
+            TokenType::Loop => self.parse_loop_expression()?,
+            TokenType::Match => self.parse_match_expression()?,
+            TokenType::Fn => self.parse_function_literal()?,
+            TokenType::Macro => self.parse_macro_literal()?,
+            TokenType::Bar => self.parse_lambda()?,
+            TokenType::DotDot | TokenType::DotDotEq => self.parse_range_expression(None)?,
+            TokenType::LBracket => self.with_struct_literals(true, Self::parse_array_literal)?,
+            // Blocks are only parsed where the grammar expects one (after
+            // `fn(...)`, `if (...)` and `else`), so a brace beginning an
+            // expression is always a hash literal
+            TokenType::LBrace => self.with_struct_literals(true, Self::parse_hash_literal)?,
+            TokenType::Illegal => {
+                let error = self.lexer.illegal_error(&self.cur_token);
+                self.errors.push(error.into());
+                return None;
+            }
+            _ => {
+                self.no_prefix_parse_error();
+                return None;
+            }
+        };
+        Some(self.program.alloc_expression(expr))
+    }
+    /// Only called for tokens with a precedence higher than `Precedence::Lowest`
+    fn parse_infix(&mut self, left: ExprId) -> Option<ExprId> {
+        let expr = match self.cur_token.token_type {
+            TokenType::LParen => {
+                self.with_struct_literals(true, |p| p.parse_call_expression(left))?
+            }
+            TokenType::LBracket => {
+                self.with_struct_literals(true, |p| p.parse_index_expression(left))?
+            }
+            TokenType::Dot => self.parse_field_expression(left)?,
+            TokenType::Pipe => return self.parse_pipe_expression(left),
+            TokenType::DotDot | TokenType::DotDotEq => self.parse_range_expression(Some(left))?,
+            TokenType::Assign => self.parse_assign_expression(left)?,
+            _ => self.parse_infix_expression(left)?,
+        };
+        Some(self.program.alloc_expression(expr))
+    }
+    fn parse_identifier(&self) -> ast::Identifier {
+        ast::Identifier::new(
+            self.cur_token.clone(),
+            self.lexer.literal(&self.cur_token).to_string(),
+            self.cur_token.start,
+            self.cur_token.end,
+        )
+    }
+    fn parse_integer_literal(&mut self) -> Option<Expression> {
+        Some(Expression::Integer {
+            token: self.cur_token.clone(),
+            value: self.parse_integer()?,
+        })
+    }
+    fn parse_integer(&mut self) -> Option<i64> {
+        let literal = self.lexer.literal(&self.cur_token);
+        match literal.parse::<i64>() {
+            Ok(value) => Some(value),
+            Err(_) => {
+                self.errors.push(ParseError::InvalidInteger {
+                    literal: literal.to_string(),
+                    span: self.cur_token.span(),
+                });
+                None
+            }
+        }
+    }
+    fn parse_float_literal(&mut self) -> Option<Expression> {
+        Some(Expression::Float {
+            token: self.cur_token.clone(),
+            value: self.parse_float()?,
+        })
+    }
+    fn parse_float(&mut self) -> Option<f64> {
+        let literal = self.lexer.literal(&self.cur_token);
+        match literal.parse::<f64>() {
+            Ok(value) => Some(value),
+            Err(_) => {
+                self.errors.push(ParseError::InvalidFloat {
+                    literal: literal.to_string(),
+                    span: self.cur_token.span(),
+                });
+                None
+            }
+        }
+    }
+    fn parse_string_literal(&self) -> Expression {
+        Expression::String {
+            token: self.cur_token.clone(),
+            value: self.string_value(),
+        }
+    }
+    /// The content of the current string token, without the quotes
+    fn string_value(&self) -> String {
+        let literal = self.lexer.literal(&self.cur_token);
+        literal[1..literal.len() - 1].to_string()
+    }
+    fn parse_prefix_expression(&mut self) -> Option<Expression> {
+        let token = self.cur_token.clone();
+        self.next_token();
+
+        let right = self.parse_expression(Precedence::Prefix)?;
+
+        Some(Expression::Prefix { token, right })
+    }
+    fn parse_infix_expression(&mut self, left: ExprId) -> Option<Expression> {
+        let token = self.cur_token.clone();
+        let precedence = self.cur_precedence();
+        self.next_token();
+
+        let right = self.parse_expression(precedence)?;
+
+        Some(Expression::Infix { token, left, right })
+    }
+    /// Desugar `value |> f` to `f(value)` and `value |> g(2)` to `g(value, 2)`
+    ///
+    /// A call in parentheses is called with the value, `value |> (g(2))` is
+    /// `g(2)(value)`.
+    fn parse_pipe_expression(&mut self, value: ExprId) -> Option<ExprId> {
+        let token = self.cur_token.clone();
+        self.next_token();
+
+        let start = self.cur_token.start;
+        let function = self.parse_expression(Precedence::Pipe)?;
+        let grouped = matches!(
+            self.nodes.last(),
+            Some((SyntaxKind::ParenExpr, node_start, _)) if *node_start == start
+        );
+
+        match &mut self.program[function] {
+            Expression::Call { args, .. } if !grouped => {
+                args.insert(0, value);
+                Some(function)
+            }
+            _ => Some(self.program.alloc_expression(Expression::Call {
+                token,
+                callee: function,
+                args: vec![value],
+                named: vec![],
+            })),
+        }
+    }
+    /// Parse the end of `start..end` or `start..=end`, the current token
+    /// being the operator
+    ///
+    /// The end of `..` can be left out where no expression can follow, as in
+    /// `arr[1..]` or `for i in 0.. {`.
+    fn parse_range_expression(&mut self, start: Option<ExprId>) -> Option<Expression> {
+        let token = self.cur_token.clone();
+
+        let end = if self.range_ends_here() {
+            if token.token_type == TokenType::DotDotEq {
+                let error = if self.peek_token_is(&TokenType::EOF) {
+                    ParseError::UnexpectedEof {
+                        expected: String::from("the end of the range"),
+                        span: self.peek_token.span(),
+                    }
+                } else {
+                    ParseError::InclusiveRangeWithoutEnd { span: token.span() }
+                };
+                self.errors.push(error);
+                return None;
+            }
+            None
+        } else {
+            self.next_token();
+            Some(self.parse_expression(Precedence::Range)?)
+        };
+
+        if let (Some(start), Some(end)) = (start, end) {
+            if let (
+                Expression::Integer { value: first, .. },
+                Expression::Integer { value: last, .. },
+            ) = (&self.program[start], &self.program[end])
+            {
+                // `..` excludes its end, `5..5` is as empty as `5..1`
+                let reason = if first > last {
+                    Some("it starts after its end")
+                } else if first == last && token.token_type == TokenType::DotDot {
+                    Some("it ends where it starts")
+                } else {
+                    None
+                };
+                if let Some(reason) = reason {
+                    self.warnings.push(ParseWarning::EmptyRange {
+                        range: format!("{}{}{}", first, token.token_type, last),
+                        reason,
+                        span: token.span(),
+                    });
+                }
+            }
+        }
+
+        Some(Expression::Range { token, start, end })
+    }
+    fn range_ends_here(&self) -> bool {
+        match self.peek_token.token_type {
+            TokenType::RBracket
+            | TokenType::RParen
+            | TokenType::RBrace
+            | TokenType::Comma
+            | TokenType::Semicolon
+            | TokenType::Colon
+            | TokenType::EOF => true,
+            TokenType::LBrace => !self.struct_literals,
+            _ => false,
+        }
+    }
+    /// Assignments are right associative, `x = y = 1` assigns `1` to both
+    fn parse_assign_expression(&mut self, target: ExprId) -> Option<Expression> {
+        let token = self.cur_token.clone();
+
+        let target_expr = &self.program[target];
+        if !matches!(
+            target_expr,
+            Expression::Identifier(_) | Expression::Index { .. } | Expression::Field { .. }
+        ) {
+            self.errors.push(ParseError::InvalidAssignTarget {
+                found: self.program.display_expression(target).to_string(),
+                span: target_expr.token().span(),
+            });
+            return None;
+        }
+        if let Expression::Identifier(ident) = target_expr {
+            if let Some((_, declaration)) = self.constant(ident.value()) {
+                self.errors.push(ParseError::AssignToConst {
+                    name: ident.value().to_string(),
+                    span: ident.token().span(),
+                    declaration: *declaration,
+                });
+                return None;
+            }
+        }
+        self.next_token();
+
+        let value = self.parse_expression(Precedence::Lowest)?;
+
+        Some(Expression::Assign {
+            token,
+            target,
+            value,
+        })
+    }
+    fn parse_grouped_expression(&mut self) -> Option<ExprId> {
+        let start = self.cur_token.start;
+        self.next_token();
+
+        let expression = self.parse_expression(Precedence::Lowest)?;
+
+        if !self.expect_peek(TokenType::RParen) {
+            return None;
+        }
+        self.finish_node(SyntaxKind::ParenExpr, start);
+        Some(expression)
+    }
+    fn parse_if_expression(&mut self) -> Option<Expression> {
+        let token = self.cur_token.clone();
+
+        if !self.expect_peek(TokenType::LParen) {
+            return None;
+        }
+        self.next_token();
+
+        let condition = self.parse_expression(Precedence::Lowest)?;
+
+        if !self.expect_peek(TokenType::RParen) || !self.expect_peek(TokenType::LBrace) {
+            return None;
+        }
+
+        let consequence = self.parse_block_stmt()?;
+
+        let alternative = if self.peek_token_is(&TokenType::Else) {
+            self.next_token();
+            if !self.expect_peek(TokenType::LBrace) {
+                return None;
+            }
+            Some(self.parse_block_stmt()?)
+        } else {
+            None
+        };
+
+        Some(Expression::If {
+            token,
+            condition,
+            consequence,
+            alternative,
+        })
+    }
+    fn parse_while_expression(&mut self) -> Option<Expression> {
+        let token = self.cur_token.clone();
+
+        if !self.expect_peek(TokenType::LParen) {
+            return None;
+        }
+        self.next_token();
+
+        let condition = self.parse_expression(Precedence::Lowest)?;
+
+        if !self.expect_peek(TokenType::RParen) || !self.expect_peek(TokenType::LBrace) {
+            return None;
+        }
+
+        let body = self.parse_loop_body(&token)?;
+
+        Some(Expression::While {
+            token,
+            condition,
+            body,
+        })
+    }
+    fn parse_for_expression(&mut self) -> Option<Expression> {
+        let token = self.cur_token.clone();
+
+        if !self.expect_peek(TokenType::Ident) {
+            return None;
+        }
+        let variable = self.parse_identifier();
+
+        if !self.expect_peek(TokenType::In) {
+            return None;
+        }
+        self.next_token();
+
+        let iterable =
+            self.with_struct_literals(false, |p| p.parse_expression(Precedence::Lowest))?;
+
+        if !self.expect_peek(TokenType::LBrace) {
+            return None;
+        }
+
+        let body = self.scoped([&variable], |p| p.parse_loop_body(&token))?;
+
+        Some(Expression::For {
+            token,
+            variable,
+            iterable,
+            body,
+        })
+    }
+    fn parse_loop_expression(&mut self) -> Option<Expression> {
+        let token = self.cur_token.clone();
+
+        if !self.expect_peek(TokenType::LBrace) {
+            return None;
+        }
+
+        let body = self.parse_loop_body(&token)?;
+
+        Some(Expression::Loop { token, body })
+    }
+    /// Parse the block of the loop beginning with `keyword`, in which
+    /// `break` and `continue` are allowed
+    fn parse_loop_body(&mut self, keyword: &Token) -> Option<BlockStatement> {
+        self.loops.push(keyword.clone());
+        let body = self.parse_block_stmt();
+        self.loops.pop();
+        body
+    }
+    /// Parse `match subject { pattern if guard => body, ... }`, warning
+    /// about the arms that can never be taken
+    fn parse_match_expression(&mut self) -> Option<Expression> {
+        let token = self.cur_token.clone();
+        self.next_token();
+
+        let subject =
+            self.with_struct_literals(false, |p| p.parse_expression(Precedence::Lowest))?;
+
+        if !self.expect_peek(TokenType::LBrace) {
+            return None;
+        }
+
+        let mut arms = Vec::new();
+        while !self.peek_token_is(&TokenType::RBrace) {
+            self.next_token();
+            let start = self.cur_token.start;
+            let pattern = self.parse_pattern()?;
+            if !self.has_unique_bindings(&pattern) {
+                return None;
+            }
+
+            let (guard, body) = self.scoped(pattern.bindings(), |p| {
+                let guard = if p.peek_token_is(&TokenType::If) {
+                    p.next_token();
+                    p.next_token();
+                    Some(p.parse_expression(Precedence::Lowest)?)
+                } else {
+                    None
+                };
+
+                if !p.expect_peek(TokenType::FatArrow) {
+                    return None;
+                }
+                p.next_token();
+                Some((guard, p.parse_expression(Precedence::Lowest)?))
+            })?;
+            self.finish_node(SyntaxKind::MatchArm, start);
+            arms.push(MatchArm::new(pattern, guard, body));
+
+            if !self.peek_token_is(&TokenType::RBrace) && !self.expect_peek(TokenType::Comma) {
+                return None;
+            }
+        }
+        self.next_token();
+
+        self.check_match(&token, subject, &arms);
+
+        Some(Expression::Match {
+            token,
+            subject,
+            arms,
+        })
+    }
+    /// Warn about the arms covered by the ones before them, and about the
+    /// matches on a boolean or a declared enum missing one of the values
+    ///
+    /// Arms with a guard can be unreachable but never cover the next ones.
+    /// `true` and `false` only cover everything when the subject is known to
+    /// be a boolean.
+    fn check_match(&mut self, token: &Token, subject: ExprId, arms: &[MatchArm]) {
+        let boolean_subject = match &self.program[subject] {
+            Expression::Boolean { .. } => true,
+            Expression::Prefix { token, .. } => token.token_type == TokenType::Bang,
+            Expression::Infix { token, .. } => matches!(
+                token.token_type,
+                TokenType::EQ | TokenType::NotEQ | TokenType::LT | TokenType::GT
+            ),
+            _ => false,
+        };
+
+        let mut earlier: Vec<&Pattern> = Vec::new();
+        for arm in arms {
+            let covered = (0..earlier.len())
+                .find(|&i| Self::is_covered(arm.pattern(), &earlier[..=i], boolean_subject));
+            if let Some(i) = covered {
+                self.warnings.push(ParseWarning::UnreachableArm {
+                    span: arm.pattern().token().span(),
+                    covered: earlier[i].token().span(),
+                });
+            }
+            if arm.guard().is_none() {
+                earlier.push(arm.pattern());
+            }
+        }
+
+        let is_boolean = boolean_subject
+            || arms.iter().any(|arm| {
+                matches!(
+                    arm.pattern(),
+                    Pattern::Literal {
+                        value: Literal::Boolean(_),
+                        ..
+                    }
+                )
+            });
+        if earlier.iter().any(|pattern| pattern.is_irrefutable()) {
+            return;
+        }
+        let matched_enum = arms.iter().find_map(|arm| match arm.pattern() {
+            Pattern::Variant { enum_name, .. } => self.enums.get_key_value(enum_name.value()),
+            _ => None,
+        });
+        let missing: Vec<String> = if is_boolean {
+            [true, false]
+                .into_iter()
+                .filter(|value| !Self::is_covered_literal(&Literal::Boolean(*value), &earlier))
+                .map(|value| value.to_string())
+                .collect()
+        } else if let Some((enum_name, variants)) = matched_enum {
+            variants
+                .iter()
+                .filter(|(variant, _)| !Self::is_covered_variant(enum_name, variant, &earlier))
+                .map(|(variant, _)| format!("{}::{}", enum_name, variant))
+                .collect()
+        } else {
+            return;
+        };
+        if !missing.is_empty() {
+            self.warnings.push(ParseWarning::NonExhaustiveMatch {
+                missing: missing.join(" and "),
+                span: token.span(),
+            });
+        }
+    }
+    /// Whether every value matched by `pattern` is matched by one of `earlier`
+    fn is_covered(pattern: &Pattern, earlier: &[&Pattern], boolean: bool) -> bool {
+        if earlier.iter().any(|pattern| pattern.is_irrefutable()) {
+            return true;
+        }
+        match pattern {
+            Pattern::Wildcard(_) | Pattern::Binding { .. } => {
+                boolean
+                    && [true, false]
+                        .iter()
+                        .all(|value| Self::is_covered_literal(&Literal::Boolean(*value), earlier))
+            }
+            // Only covered as part of the array it is in
+            Pattern::Rest { .. } => false,
+            Pattern::Literal { value, .. } => Self::is_covered_literal(value, earlier),
+            Pattern::Array { elements, .. } => earlier.iter().any(|pattern| match pattern {
+                Pattern::Array {
+                    elements: other, ..
+                } => Self::covers_array(elements, other),
+                _ => false,
+            }),
+            // Every field matched by the earlier pattern is matched by this
+            // one, a field left out matches anything. An earlier pattern
+            // without a name matches any struct with the fields
+            Pattern::Struct { name, fields, .. } => earlier.iter().any(|pattern| match pattern {
+                Pattern::Struct {
+                    name: other_name,
+                    fields: other,
+                    ..
+                } => {
+                    other_name.as_ref().is_none_or(|other| {
+                        name.as_ref().map(ast::Identifier::value) == Some(other.value())
+                    }) && other.iter().all(|(field, other)| {
+                        match fields.iter().find(|(f, _)| f.value() == field.value()) {
+                            Some((_, pattern)) => Self::is_covered(pattern, &[other], false),
+                            None => other.is_irrefutable(),
+                        }
+                    })
+                }
+                _ => false,
+            }),
+            Pattern::Variant {
+                enum_name,
+                variant,
+                fields,
+            } => earlier.iter().any(|pattern| match pattern {
+                Pattern::Variant {
+                    enum_name: other_enum,
+                    variant: other_variant,
+                    fields: other,
+                } => {
+                    other_enum.value() == enum_name.value()
+                        && other_variant.value() == variant.value()
+                        && other.len() == fields.len()
+                        && fields
+                            .iter()
+                            .zip(other)
+                            .all(|(field, other)| Self::is_covered(field, &[other], false))
+                }
+                _ => false,
+            }),
+        }
+    }
+    /// Whether every array matched by `elements` is matched by `other`
+    fn covers_array(elements: &[Pattern], other: &[Pattern]) -> bool {
+        let covers = |patterns: &mut dyn Iterator<Item = &Pattern>,
+                      others: &mut dyn Iterator<Item = &Pattern>| {
+            patterns
+                .zip(others)
+                .all(|(pattern, other)| Self::is_covered(pattern, &[other], false))
+        };
+        let (before, after) = Self::split_rest(elements);
+        match (Self::split_rest(other), after) {
+            ((other, None), None) => {
+                other.len() == before.len() && covers(&mut before.iter(), &mut other.iter())
+            }
+            ((_, None), Some(_)) => false,
+            ((other_before, Some(other_after)), None) => {
+                before.len() >= other_before.len() + other_after.len()
+                    && covers(&mut before.iter(), &mut other_before.iter())
+                    && covers(&mut before.iter().rev(), &mut other_after.iter().rev())
+            }
+            ((other_before, Some(other_after)), Some(after)) => {
+                before.len() >= other_before.len()
+                    && after.len() >= other_after.len()
+                    && covers(&mut before.iter(), &mut other_before.iter())
+                    && covers(&mut after.iter().rev(), &mut other_after.iter().rev())
+            }
+        }
+    }
+    /// The patterns before the rest of an array pattern, and those after it
+    /// if there is one
+    fn split_rest(elements: &[Pattern]) -> (&[Pattern], Option<&[Pattern]>) {
+        match elements
+            .iter()
+            .position(|element| matches!(element, Pattern::Rest { .. }))
+        {
+            Some(i) => (&elements[..i], Some(&elements[i + 1..])),
+            None => (elements, None),
+        }
+    }
+    fn is_covered_literal(literal: &Literal, earlier: &[&Pattern]) -> bool {
+        earlier
+            .iter()
+            .any(|pattern| matches!(pattern, Pattern::Literal { value, .. } if value == literal))
+    }
+    /// Whether one of `earlier` matches every value built with the variant
+    fn is_covered_variant(enum_name: &str, variant: &str, earlier: &[&Pattern]) -> bool {
+        earlier.iter().any(|pattern| match pattern {
+            Pattern::Variant {
+                enum_name: e,
+                variant: v,
+                fields,
+            } => {
+                e.value() == enum_name
+                    && v.value() == variant
+                    && fields.iter().all(Pattern::is_irrefutable)
+            }
+            _ => false,
+        })
+    }
+    /// Parse the pattern beginning at the current token
+    fn parse_pattern(&mut self) -> Option<Pattern> {
+        let start = self.cur_token.start;
+        let (pattern, kind) = match self.cur_token.token_type {
+            TokenType::Underscore => (
+                Pattern::Wildcard(self.cur_token.clone()),
+                SyntaxKind::WildcardPat,
+            ),
+            TokenType::Ident if self.peek_token_is(&TokenType::LBrace) => {
+                let name = self.parse_identifier();
+                self.next_token();
+                (
+                    self.parse_struct_pattern(Some(name))?,
+                    SyntaxKind::StructPat,
+                )
+            }
+            TokenType::Ident if self.peek_token_is(&TokenType::ColonColon) => {
+                (self.parse_variant_pattern()?, SyntaxKind::VariantPat)
+            }
+            TokenType::Ident => (
+                Pattern::Binding {
+                    name: self.parse_identifier(),
+                    is_mut: false,
+                },
+                SyntaxKind::BindingPat,
+            ),
+            TokenType::Mut => {
+                if !self.expect_peek(TokenType::Ident) {
+                    return None;
+                }
+                let pattern = Pattern::Binding {
+                    name: self.parse_identifier(),
+                    is_mut: true,
+                };
+                (pattern, SyntaxKind::BindingPat)
+            }
+            TokenType::LBrace => (self.parse_struct_pattern(None)?, SyntaxKind::StructPat),
+            TokenType::Int
+            | TokenType::Float
+            | TokenType::String
+            | TokenType::True
+            | TokenType::False
+            | TokenType::Minus => (self.parse_literal_pattern()?, SyntaxKind::LiteralPat),
+            TokenType::LBracket => (self.parse_array_pattern()?, SyntaxKind::ArrayPat),
+            TokenType::DotDotDot => {
+                self.errors.push(ParseError::MisplacedRest {
+                    span: self.cur_token.span(),
+                });
+                return None;
+            }
+            _ => {
+                let error = if self.cur_token_is(TokenType::EOF) {
+                    ParseError::UnexpectedEof {
+                        expected: String::from("a pattern"),
+                        span: self.cur_token.span(),
+                    }
+                } else {
+                    ParseError::ExpectedPattern {
+                        found: self.literal(&self.cur_token),
+                        span: self.cur_token.span(),
+                    }
+                };
+                self.errors.push(error);
+                return None;
+            }
+        };
+        self.finish_node(kind, start);
+        Some(pattern)
+    }
+    fn parse_literal_pattern(&mut self) -> Option<Pattern> {
+        let token = self.cur_token.clone();
+        let negative = self.cur_token_is(TokenType::Minus);
+        if negative {
+            if !self.peek_token_is(&TokenType::Int) && !self.peek_token_is(&TokenType::Float) {
+                let error =
+                    self.unexpected(vec![TokenType::Int, TokenType::Float], &self.peek_token);
+                self.errors.push(error);
+                return None;
+            }
+            self.next_token();
+        }
+
+        let value = match self.cur_token.token_type {
+            TokenType::Int if negative => Literal::Integer(-self.parse_integer()?),
+            TokenType::Int => Literal::Integer(self.parse_integer()?),
+            TokenType::Float if negative => Literal::Float(-self.parse_float()?),
+            TokenType::Float => Literal::Float(self.parse_float()?),
+            TokenType::String => Literal::String(self.string_value()),
+            _ => Literal::Boolean(self.cur_token_is(TokenType::True)),
+        };
+
+        Some(Pattern::Literal { token, value })
+    }
+    fn parse_array_pattern(&mut self) -> Option<Pattern> {
+        let token = self.cur_token.clone();
+        let mut elements = Vec::new();
+
+        let mut rest: Option<Span> = None;
+        while !self.peek_token_is(&TokenType::RBracket) {
+            self.next_token();
+            let element = if self.cur_token_is(TokenType::DotDotDot) {
+                if let Some(first) = rest {
+                    self.errors.push(ParseError::MultipleRests {
+                        span: self.cur_token.span(),
+                        first,
+                    });
+                    return None;
+                }
+                rest = Some(self.cur_token.span());
+                self.parse_rest_pattern()?
+            } else {
+                self.parse_pattern()?
+            };
+            elements.push(element);
+
+            if !self.peek_token_is(&TokenType::RBracket) && !self.expect_peek(TokenType::Comma) {
+                return None;
+            }
+        }
+        self.next_token();
+
+        Some(Pattern::Array { token, elements })
+    }
+    /// Parse `...`, `...rest` or `...mut rest`
+    fn parse_rest_pattern(&mut self) -> Option<Pattern> {
+        let token = self.cur_token.clone();
+        let is_mut = self.peek_token_is(&TokenType::Mut);
+        if is_mut {
+            self.next_token();
+        }
+
+        let name = if is_mut || self.peek_token_is(&TokenType::Ident) {
+            if !self.expect_peek(TokenType::Ident) {
+                return None;
+            }
+            Some(self.parse_identifier())
+        } else {
+            None
+        };
+        self.finish_node(SyntaxKind::RestPat, token.start);
+
+        Some(Pattern::Rest {
+            token,
+            name,
+            is_mut,
+        })
+    }
+    /// Parse `Name { field: pattern, ... }` from the brace, where `field`
+    /// alone stands for `field: field` and `mut field` for a mutable one
+    fn parse_struct_pattern(&mut self, name: Option<ast::Identifier>) -> Option<Pattern> {
+        let token = self.cur_token.clone();
+
+        let mut fields: Vec<(ast::Identifier, Pattern)> = Vec::new();
+        while !self.peek_token_is(&TokenType::RBrace) {
+            let is_mut = self.peek_token_is(&TokenType::Mut);
+            if is_mut {
+                self.next_token();
+            }
+            let start = self.cur_token.start;
+            if !self.expect_peek(TokenType::Ident) {
+                return None;
+            }
+            let field = self.parse_identifier();
+            if !self.is_new_field(fields.iter().map(|(f, _)| f), &field) {
+                return None;
+            }
+
+            let pattern = if !is_mut && self.peek_token_is(&TokenType::Colon) {
+                self.next_token();
+                self.next_token();
+                self.parse_pattern()?
+            } else {
+                if is_mut {
+                    self.finish_node(SyntaxKind::BindingPat, start);
+                }
+                Pattern::Binding {
+                    name: self.parse_identifier(),
+                    is_mut,
+                }
+            };
+            fields.push((field, pattern));
+
+            if !self.peek_token_is(&TokenType::RBrace) && !self.expect_peek(TokenType::Comma) {
+                return None;
+            }
+        }
+        self.next_token();
+
+        Some(Pattern::Struct {
+            token,
+            name,
+            fields,
+        })
+    }
+    /// Parse `Enum::Variant(pattern, ...)`, or `Enum::Variant` for the
+    /// variants without fields
+    fn parse_variant_pattern(&mut self) -> Option<Pattern> {
+        let enum_name = self.parse_identifier();
+        self.next_token();
+        if !self.expect_peek(TokenType::Ident) {
+            return None;
+        }
+        let variant = self.parse_identifier();
+
+        let mut fields = Vec::new();
+        if self.peek_token_is(&TokenType::LParen) {
+            self.next_token();
+            while !self.peek_token_is(&TokenType::RParen) {
+                self.next_token();
+                fields.push(self.parse_pattern()?);
+
+                if !self.peek_token_is(&TokenType::RParen) && !self.expect_peek(TokenType::Comma) {
+                    return None;
+                }
+            }
+            self.next_token();
+        }
+
+        if !self.is_valid_variant(&enum_name, &variant, fields.len()) {
+            return None;
+        }
+
+        Some(Pattern::Variant {
+            enum_name,
+            variant,
+            fields,
+        })
+    }
+    fn parse_function_literal(&mut self) -> Option<Expression> {
+        let token = self.cur_token.clone();
+
+        if !self.expect_peek(TokenType::LParen) {
+            return None;
+        }
+
+        let params = self.parse_function_params(TokenType::RParen)?;
+        let return_type = if self.peek_token_is(&TokenType::Arrow) {
+            self.next_token();
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        if !self.expect_peek(TokenType::LBrace) {
+            return None;
+        }
+        let body = self.parse_function_body(&params, Self::parse_block_stmt)?;
+
+        Some(Expression::Function {
+            token,
+            params,
+            return_type,
+            body,
+        })
+    }
+    /// Parse `macro(params) { body }`, the body builds the code the calls
+    /// expand to
+    fn parse_macro_literal(&mut self) -> Option<Expression> {
+        let token = self.cur_token.clone();
+
+        if !self.expect_peek(TokenType::LParen) {
+            return None;
+        }
+        let params = self.parse_function_params(TokenType::RParen)?;
+
+        if !self.expect_peek(TokenType::LBrace) {
+            return None;
+        }
+        let body = self.parse_function_body(&params, Self::parse_block_stmt)?;
+
+        Some(Expression::Macro {
+            token,
+            params,
+            body,
+        })
+    }
+    /// Parse `|params| body`, the short form of `fn(params) { body }`
+    ///
+    /// A body beginning with a brace is a block unless the brace opens a hash
+    /// literal, any other body is an expression standing for a block of one
+    /// statement.
+    fn parse_lambda(&mut self) -> Option<Expression> {
+        let token = self.cur_token.clone();
+
+        let params = self.parse_function_params(TokenType::Bar)?;
+        self.next_token();
+
+        let body = self.parse_function_body(&params, |p| {
+            if p.cur_token_is(TokenType::LBrace) && !p.brace_opens_hash() {
+                p.parse_block_stmt()
+            } else {
+                p.parse_expression_body()
+            }
+        })?;
+
+        Some(Expression::Function {
+            token,
+            params,
+            return_type: None,
+            body,
+        })
+    }
+    /// Whether the current `{` opens a hash literal rather than a block, that
+    /// is whether `}` or an expression and a `:` follow it
+    fn brace_opens_hash(&self) -> bool {
+        let mut lexer = self.lexer.clone();
+        let mut token = self.peek_token.clone();
+        if token.token_type == TokenType::RBrace {
+            return true;
+        }
+        let mut depth = 0;
+        // Inside the parameters of a lambda, where `:` begins a type
+        let mut params = false;
+        loop {
+            match token.token_type {
+                TokenType::LParen | TokenType::LBracket | TokenType::LBrace => depth += 1,
+                TokenType::RParen | TokenType::RBracket | TokenType::RBrace if depth > 0 => {
+                    depth -= 1
+                }
+                TokenType::Bar if depth == 0 => params = !params,
+                TokenType::Colon if depth == 0 && !params => return true,
+                TokenType::EOF => return false,
+                TokenType::Semicolon
+                | TokenType::RParen
+                | TokenType::RBracket
+                | TokenType::RBrace
+                    if depth == 0 =>
+                {
+                    return false
+                }
+                ref token_type if depth == 0 && token_type.is_statement_keyword() => return false,
+                _ => {}
+            }
+            token = lexer.next_token();
+        }
+    }
+    /// Run `parse` on the body of a function with the parameters bound
+    ///
+    /// A loop around the function literal can't be left from its body.
+    fn parse_function_body(
+        &mut self,
+        params: &[Parameter],
+        parse: impl FnOnce(&mut Self) -> Option<BlockStatement>,
+    ) -> Option<BlockStatement> {
+        let loops = std::mem::take(&mut self.loops);
+        let body = self.scoped(params.iter().map(Parameter::name), parse);
+        self.loops = loops;
+        body
+    }
+    fn parse_expression_body(&mut self) -> Option<BlockStatement> {
+        let token = self.cur_token.clone();
+        let expression = self.parse_expression(Precedence::Lowest)?;
+        let stmt = self
+            .program
+            .alloc_statement(Statement::Expression(ExpressionStatement::new(
+                token.clone(),
+                expression,
+            )));
+        let span = self.span_from(token.span());
+        self.program.set_statement_span(stmt, span);
+        Some(BlockStatement::new(token, vec![stmt], span.end))
+    }
+    /// Parse `: type` after the current token if there is one
+    fn parse_annotation(&mut self) -> Option<Option<TypeAnnotation>> {
+        if !self.peek_token_is(&TokenType::Colon) {
+            return Some(None);
+        }
+        self.next_token();
+        Some(Some(self.parse_type()?))
+    }
+    /// Parse the type after the current token, `int` or `array<int>`
+    fn parse_type(&mut self) -> Option<TypeAnnotation> {
+        self.next_token();
+        if !self.cur_token_is(TokenType::Ident) {
+            let error = if self.cur_token_is(TokenType::EOF) {
+                ParseError::UnexpectedEof {
+                    expected: String::from("a type"),
+                    span: self.cur_token.span(),
+                }
+            } else {
+                ParseError::ExpectedType {
+                    found: self.literal(&self.cur_token),
+                    span: self.cur_token.span(),
+                }
+            };
+            self.errors.push(error);
+            return None;
+        }
+        let start = self.cur_token.start;
+        let name = self.parse_identifier();
+
+        let mut args = Vec::new();
+        if self.peek_token_is(&TokenType::LT) {
+            self.next_token();
+            loop {
+                args.push(self.parse_type()?);
+                if !self.peek_token_is(&TokenType::Comma) {
+                    break;
+                }
+                self.next_token();
+            }
+            if !self.expect_peek(TokenType::GT) {
+                return None;
+            }
+        }
+        self.finish_node(SyntaxKind::TypeExpr, start);
+
+        Some(TypeAnnotation::new(name, args))
+    }
+    /// Parse a comma separated list of parameters up to the `end` token, each
+    /// a name with an optional type and default, the last one can be a rest
+    /// `...name`
+    ///
+    /// Errors point at the offending parameter rather than at the function literal
+    fn parse_function_params(&mut self, end: TokenType) -> Option<Vec<Parameter>> {
+        let mut params: Vec<Parameter> = Vec::new();
+
+        if self.peek_token_is(&end) {
+            self.next_token();
+            return Some(params);
+        }
+
+        loop {
+            self.next_token();
+
+            if let Some(rest) = params.last().filter(|p| p.is_rest()) {
+                self.errors.push(ParseError::RestNotLast {
+                    span: rest.name().token().span(),
+                });
+                return None;
+            }
+            let is_rest = self.cur_token_is(TokenType::DotDotDot);
+            if is_rest {
+                self.next_token();
+            }
+            if !self.cur_token_is(TokenType::Ident) {
+                let error = self.unexpected(vec![TokenType::Ident], &self.cur_token);
+                self.errors.push(error);
+                return None;
+            }
+
+            let param = self.parse_identifier();
+            if let Some(first) = params.iter().find(|p| p.name().value() == param.value()) {
+                self.errors.push(ParseError::DuplicateParameter {
+                    name: param.value().to_string(),
+                    span: param.token().span(),
+                    first: first.name().token().span(),
+                });
+                return None;
+            }
+            let annotation = self.parse_annotation()?;
+            let default = if !is_rest && self.peek_token_is(&TokenType::Assign) {
+                self.next_token();
+                self.next_token();
+                Some(self.parse_expression(Precedence::Lowest)?)
+            } else {
+                None
+            };
+            if default.is_none() && !is_rest {
+                if let Some(first) = params.iter().find(|p| p.default().is_some()) {
+                    self.errors.push(ParseError::RequiredAfterDefault {
+                        name: param.value().to_string(),
+                        span: param.token().span(),
+                        default: first.name().token().span(),
+                    });
+                    return None;
+                }
+            }
+            params.push(Parameter::new(param, annotation, default, is_rest));
+
+            match &self.peek_token.token_type {
+                TokenType::Comma => self.next_token(),
+                token_type if *token_type == end => {
+                    self.next_token();
+                    return Some(params);
+                }
+                _ => {
+                    let error = self.unexpected(vec![TokenType::Comma, end], &self.peek_token);
+                    self.errors.push(error);
+                    return None;
+                }
+            }
+        }
+    }
+    /// Parse the arguments of a call, the positional ones first then the
+    /// named ones `name: value`
+    ///
+    /// A positional argument can be a spread `...xs` passing the elements
+    /// of an array. Whether the arguments fit the parameters is checked once
+    /// the program is parsed, see `check_arguments`.
+    fn parse_call_expression(&mut self, callee: ExprId) -> Option<Expression> {
+        let token = self.cur_token.clone();
+        let mut args = Vec::new();
+        let mut named: Vec<(ast::Identifier, ExprId)> = Vec::new();
+
+        if self.peek_token_is(&TokenType::RParen) {
+            self.next_token();
+            return Some(Expression::Call {
+                token,
+                callee,
+                args,
+                named,
+            });
+        }
+
+        loop {
+            self.next_token();
+            let start = self.cur_token.start;
+
+            if self.cur_token_is(TokenType::Ident) && self.peek_token_is(&TokenType::Colon) {
+                let name = self.parse_identifier();
+                self.next_token();
+                self.next_token();
+                let value = self.parse_expression(Precedence::Lowest)?;
+                if let Some((first, _)) = named.iter().find(|(n, _)| n.value() == name.value()) {
+                    self.errors.push(ParseError::ArgumentGivenTwice {
+                        name: name.value().to_string(),
+                        span: name.token().span(),
+                        first: first.token().span(),
+                    });
+                    return None;
+                }
+                self.finish_node(SyntaxKind::NamedArg, start);
+                named.push((name, value));
+            } else {
+                if let Some((first, _)) = named.first() {
+                    self.errors.push(ParseError::PositionalAfterNamed {
+                        span: self.cur_token.span(),
+                        named: first.token().span(),
+                    });
+                    return None;
+                }
+                let value = if self.cur_token_is(TokenType::DotDotDot) {
+                    let spread = self.cur_token.clone();
+                    self.next_token();
+                    let value = self.parse_expression(Precedence::Lowest)?;
+                    self.finish_node(SyntaxKind::SpreadExpr, start);
+                    let span = self.span_from(spread.span());
+                    let spread = self.program.alloc_expression(Expression::Spread {
+                        token: spread,
+                        value,
+                    });
+                    self.program.set_expression_span(spread, span);
+                    spread
+                } else {
+                    self.parse_expression(Precedence::Lowest)?
+                };
+                args.push(value);
+            }
+
+            match self.peek_token.token_type {
+                TokenType::Comma => self.next_token(),
+                TokenType::RParen => {
+                    self.next_token();
+                    break;
+                }
+                _ => {
+                    let expected = vec![TokenType::Comma, TokenType::RParen];
+                    self.errors
+                        .push(self.unexpected(expected, &self.peek_token));
+                    return None;
+                }
+            }
+        }
+
+        Some(Expression::Call {
+            token,
+            callee,
+            args,
+            named,
+        })
+    }
+    fn parse_array_literal(&mut self) -> Option<Expression> {
+        let token = self.cur_token.clone();
+        let elements = self.parse_expression_list(TokenType::RBracket)?;
+
+        Some(Expression::Array { token, elements })
+    }
+    /// Parse `{ key: value, ... }`, warning about literal keys given more than once
+    fn parse_hash_literal(&mut self) -> Option<Expression> {
+        let token = self.cur_token.clone();
+        let mut pairs: Vec<(ExprId, ExprId)> = Vec::new();
+
+        while !self.peek_token_is(&TokenType::RBrace) {
+            self.next_token();
+            let key = self.parse_expression(Precedence::Lowest)?;
+
+            if !self.expect_peek(TokenType::Colon) {
+                return None;
+            }
+            self.next_token();
+            let value = self.parse_expression(Precedence::Lowest)?;
+
+            if let Some(literal) = Self::hash_key_literal(&self.program[key]) {
+                let first = pairs.iter().find(|(k, _)| {
+                    Self::hash_key_literal(&self.program[*k]).as_ref() == Some(&literal)
+                });
+                if let Some((first, _)) = first {
+                    self.warnings.push(ParseWarning::DuplicateKey {
+                        key: literal,
+                        span: self.program[key].token().span(),
+                        first: self.program[*first].token().span(),
+                    });
+                }
+            }
+            pairs.push((key, value));
+
+            if !self.peek_token_is(&TokenType::RBrace) && !self.expect_peek(TokenType::Comma) {
+                return None;
+            }
+        }
+        self.next_token();
+
+        Some(Expression::Hash { token, pairs })
+    }
+    /// Canonical form of a literal hash key, `None` for keys only known at runtime
+    fn hash_key_literal(key: &Expression) -> Option<String> {
+        match key {
+            Expression::Integer { value, .. } => Some(value.to_string()),
+            Expression::Boolean { value, .. } => Some(value.to_string()),
+            Expression::String { value, .. } => Some(format!("\"{}\"", value)),
+            _ => None,
+        }
+    }
+    /// Parse `Name { field: value, ... }`, the current token being the name
+    fn parse_struct_literal(&mut self) -> Option<Expression> {
+        let name = self.parse_identifier();
+        self.next_token();
+        let token = self.cur_token.clone();
+
+        let mut fields: Vec<(ast::Identifier, ExprId)> = Vec::new();
+        while !self.peek_token_is(&TokenType::RBrace) {
+            if !self.expect_peek(TokenType::Ident) {
+                return None;
+            }
+            let field = self.parse_identifier();
+            if !self.is_new_field(fields.iter().map(|(f, _)| f), &field) {
+                return None;
+            }
+
+            if !self.expect_peek(TokenType::Colon) {
+                return None;
+            }
+            self.next_token();
+            let value = self.parse_expression(Precedence::Lowest)?;
+            fields.push((field, value));
+
+            if !self.peek_token_is(&TokenType::RBrace) && !self.expect_peek(TokenType::Comma) {
+                return None;
+            }
+        }
+        self.next_token();
+
+        Some(Expression::Struct {
+            token,
+            name,
+            fields,
+        })
+    }
+    /// Parse `Enum::Variant(value, ...)`, or `Enum::Variant` for the
+    /// variants without fields
+    fn parse_variant_expression(&mut self) -> Option<Expression> {
+        let enum_name = self.parse_identifier();
+        self.next_token();
+        let token = self.cur_token.clone();
+        if !self.expect_peek(TokenType::Ident) {
+            return None;
+        }
+        let variant = self.parse_identifier();
+
+        let args = if self.peek_token_is(&TokenType::LParen) {
+            self.next_token();
+            self.with_struct_literals(true, |p| p.parse_expression_list(TokenType::RParen))?
+        } else {
+            Vec::new()
+        };
+
+        if !self.is_valid_variant(&enum_name, &variant, args.len()) {
+            return None;
+        }
+
+        Some(Expression::Variant {
+            token,
+            enum_name,
+            variant,
+            args,
+        })
+    }
+    fn parse_field_expression(&mut self, object: ExprId) -> Option<Expression> {
+        let token = self.cur_token.clone();
+
+        if !self.expect_peek(TokenType::Ident) {
+            return None;
+        }
+
+        Some(Expression::Field {
+            token,
+            object,
+            field: self.parse_identifier(),
+        })
+    }
+    fn parse_index_expression(&mut self, left: ExprId) -> Option<Expression> {
+        let token = self.cur_token.clone();
+        self.next_token();
+
+        let index = self.parse_expression(Precedence::Lowest)?;
+
+        if !self.expect_peek(TokenType::RBracket) {
+            return None;
+        }
+        self.check_slice(left, index);
+
+        Some(Expression::Index { token, left, index })
+    }
+    /// Warn about the slices of an array or string literal starting or
+    /// ending past it
+    fn check_slice(&mut self, left: ExprId, index: ExprId) {
+        let Expression::Range { token, start, end } = &self.program[index] else {
+            return;
+        };
+        let length = match &self.program[left] {
+            Expression::Array { elements, .. } => elements.len() as i64,
+            Expression::String { value, .. } => value.chars().count() as i64,
+            _ => return,
+        };
+        let constant = |bound: &Option<ExprId>| match bound.map(|id| &self.program[id]) {
+            Some(Expression::Integer { value, .. }) => Some(*value),
+            _ => None,
+        };
+        let inclusive = token.token_type == TokenType::DotDotEq;
+        // A slice starting past the end also ends past it, only the start is
+        // reported
+        let warning = match (constant(start), constant(end)) {
+            (Some(start), _) if start > length => Some(("start", start)),
+            (_, Some(end)) if end > length || inclusive && end == length => Some(("end", end)),
+            _ => None,
+        };
+        if let Some((bound, index)) = warning {
+            self.warnings.push(ParseWarning::SliceOutOfBounds {
+                bound,
+                index,
+                length: length as usize,
+                span: token.span(),
+            });
+        }
+    }
+    /// Parse a comma separated list of expressions up to the `end` token
+    fn parse_expression_list(&mut self, end: TokenType) -> Option<Vec<ExprId>> {
+        let mut list = Vec::new();
+
+        if self.peek_token_is(&end) {
+            self.next_token();
+            return Some(list);
+        }
+
+        self.next_token();
+        list.push(self.parse_expression(Precedence::Lowest)?);
+
+        while self.peek_token_is(&TokenType::Comma) {
+            self.next_token();
+            self.next_token();
+            list.push(self.parse_expression(Precedence::Lowest)?);
+        }
+
+        if !self.expect_peek(end) {
+            return None;
+        }
+        Some(list)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn parse_let_stmt() {
+        let input = r#" let x = 5; let y = 10; let a = 838383; "#;
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+        let p = parser.parse();
+        if !parser.errors.is_empty() {
+            println!("parser has {} errors", parser.errors.len());
+            for error in parser.errors {
+                println!("parser error: {}", error);
+            }
+            panic!();
+        }
+        assert_eq!(p.statements.len(), 3);
+    }
+    #[test]
+    fn parse_mut_let_stmt() {
+        let input = r#"
+        let x = 3;
+        let mut y = 5;
+        y = 10;
+        "#;
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        if !parser.errors.is_empty() {
+            println!("parser has {} errors", parser.errors.len());
+            for error in parser.errors {
+                println!("parser error: {}", error);
+            }
+            panic!();
+        }
+
+        let mut stmts = p.statements.iter();
+        let first_stmt = stmts.next().unwrap();
+        let sec_stmt = stmts.next().unwrap();
+
+        assert!(!p[*first_stmt].is_mut());
+        assert!(p[*sec_stmt].is_mut());
+    }
+    #[test]
+    fn parse_from_file() {
+        let input = std::fs::read_to_string("input/large.pab").unwrap();
+
+        let lexer = crate::lexer::Lexer::new(&input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        if !parser.errors.is_empty() {
+            println!("parser has {} errors", parser.errors.len());
+            for error in parser.errors {
+                println!("parser error: {}", error);
+            }
+            panic!();
+        }
+
+        assert_eq!(p.statements.len(), 518400);
+    }
+    #[test]
+    fn test_line_nb_error() {
+        let input = r#"let x = 5;
+let = 10;"#;
+
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let _p = parser.parse();
+
+        assert_eq!(parser.errors.len(), 1);
+    }
+    #[test]
+    fn test_line_nb_error_file() {
+        let input = std::fs::read_to_string("input/line_nb_error.pab").unwrap();
+
+        let lexer = crate::lexer::Lexer::new(&input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let _p = parser.parse();
+
+        // Each `!-/*5;` line has a '/' with nothing on its left, the
+        // misplaced literal of line 223 is the error the file is about
+        assert_eq!(parser.errors.len(), 19, "{:?}", parser.errors);
+        let slashes = parser.errors.iter().filter(|e| {
+            matches!(
+                e,
+                ParseError::ExpectedExpression { found, .. } if found == "/"
+            )
+        });
+        assert_eq!(slashes.count(), 18);
+        let misplaced = parser.errors.iter().find(|e| e.code() == "E0100").unwrap();
+        assert_eq!((misplaced.span().line, misplaced.span().column), (223, 5));
+    }
+    #[test]
+    fn parse_function_literal() {
+        let input = "fn(x, y) { x + y; }";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        assert_eq!(p.statements.len(), 1);
+        let Statement::Expression(stmt) = &p[p.statements[0]] else {
+            panic!("expected an expression statement");
+        };
+        let Expression::Function { params, body, .. } = &p[stmt.expression()] else {
+            panic!("expected a function literal");
+        };
+        let names: Vec<&str> = params.iter().map(|p| p.name().value()).collect();
+        assert_eq!(names, ["x", "y"]);
+        assert_eq!(body.statements().len(), 1);
+    }
+    #[test]
+    fn parse_lambdas() {
+        let input = "map(xs, |x| x + 1, 2); let f = || { loop { break 1 } }; |a, b| a = b";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        assert_eq!(p.statements.len(), 3);
+        let Statement::Expression(stmt) = &p[p.statements[0]] else {
+            panic!("expected an expression statement");
+        };
+        let Expression::Call { args, .. } = &p[stmt.expression()] else {
+            panic!("expected a call expression");
+        };
+        // The body ends at the comma
+        assert_eq!(args.len(), 3);
+        let Expression::Function { params, body, .. } = &p[args[1]] else {
+            panic!("expected a function literal");
+        };
+        assert_eq!(params[0].name().value(), "x");
+        let Statement::Expression(stmt) = &p[body.statements()[0]] else {
+            panic!("expected an expression statement");
+        };
+        assert!(matches!(p[stmt.expression()], Expression::Infix { .. }));
+
+        let Statement::Let(stmt) = &p[p.statements[1]] else {
+            panic!("expected a let statement");
+        };
+        assert!(
+            matches!(&p[stmt.value()], Expression::Function { params, .. } if params.is_empty())
+        );
+        assert_eq!(
+            p.to_string(),
+            "map(xs, fn(x) {
+    x + 1;
+}, 2);
+let f = fn() {
+    loop {
+        break 1;
+    };
+};
+fn(a, b) {
+    a = b;
+};"
+        );
+    }
+    #[test]
+    fn parse_lambda_hash_bodies() {
+        let input = r#"let a = |x| { "k": x }; let b = || {}; let c = |x| { x };
+let d = |f| { |y: int| y }; let e = |x| { f(x): [x] }["k"];"#;
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        assert_eq!(
+            p.to_string(),
+            r#"let a = fn(x) {
+    {"k": x};
+};
+let b = fn() {
+    {};
+};
+let c = fn(x) {
+    x;
+};
+let d = fn(f) {
+    fn(y: int) {
+        y;
+    };
+};
+let e = fn(x) {
+    {f(x): [x]}["k"];
+};"#
+        );
+    }
+    #[test]
+    fn parse_pipes() {
+        let inputs = [
+            ("value |> f |> g(2)", "g(f(value), 2)"),
+            ("a + 1 |> f == b", "f(a + 1) == b"),
+            (
+                "xs |> map(|x| x * 2) |> sum()",
+                "sum(map(xs, fn(x) {\n    x * 2;\n}))",
+            ),
+            ("x |> (g(2)) |> (h)(3)", "h(g(2)(x), 3)"),
+            ("x |> a.f |> y[0]", "y[0](a.f(x))"),
+        ];
+        for (input, expected) in inputs {
+            let lexer = crate::lexer::Lexer::new(input);
+            let mut parser = super::Parser::new(lexer, "test");
+
+            let p = parser.parse();
+
+            assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+            let Statement::Expression(stmt) = &p[p.statements[0]] else {
+                panic!("expected an expression statement");
+            };
+            assert_eq!(
+                p.display_expression(stmt.expression()).to_string(),
+                expected
+            );
+        }
+    }
+    #[test]
+    fn malformed_lambdas() {
+        let input = "let a = |x, x| x;
+let b = |x y| x;
+while (true) { each(xs, |x| { continue; }) }";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let _p = parser.parse();
+
+        let lines: Vec<usize> = parser.errors.iter().map(|e| e.span().line).collect();
+        assert_eq!(lines, [1, 2, 3], "{:#?}", parser.errors);
+        assert!(matches!(
+            parser.errors[0],
+            ParseError::DuplicateParameter { .. }
+        ));
+        assert_eq!(
+            parser.errors[1].to_string(),
+            "expected ',' or '|', got 'y' instead"
+        );
+        assert!(matches!(
+            parser.errors[2],
+            ParseError::OutsideLoop {
+                keyword: TokenType::Continue,
+                ..
+            }
+        ));
+    }
+    #[test]
+    fn parse_call_precedence() {
+        let input = "a + add(b * c, d)(e) * f;";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        let Statement::Expression(stmt) = &p[p.statements[0]] else {
+            panic!("expected an expression statement");
+        };
+        // a + ((add(b * c, d)(e)) * f)
+        let Expression::Infix { token, right, .. } = &p[stmt.expression()] else {
+            panic!("expected an infix expression");
+        };
+        assert_eq!(token.token_type, TokenType::Plus);
+        let Expression::Infix { token, left, .. } = &p[*right] else {
+            panic!("expected an infix expression");
+        };
+        assert_eq!(token.token_type, TokenType::Asterisk);
+        let Expression::Call { callee, args, .. } = &p[*left] else {
+            panic!("expected a call expression");
+        };
+        assert_eq!(args.len(), 1);
+        let Expression::Call { args, .. } = &p[*callee] else {
+            panic!("expected a call expression");
+        };
+        assert_eq!(args.len(), 2);
+        assert!(matches!(p[args[0]], Expression::Infix { .. }));
+    }
+    #[test]
+    fn malformed_function_params() {
+        let input = r#"let a = fn(x, y, x) { x };
+let b = fn(x y) { x };
+let c = fn(x, 5) { x };"#;
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let _p = parser.parse();
+
+        assert_eq!(parser.errors.len(), 3, "{:?}", parser.errors);
+        let ParseError::DuplicateParameter { name, span, first } = &parser.errors[0] else {
+            panic!("expected a duplicate parameter error");
+        };
+        assert_eq!(name, "x");
+        assert_eq!((span.line, span.column), (1, 18));
+        assert_eq!((first.line, first.column), (1, 12));
+        assert_eq!(
+            parser.errors[1],
+            ParseError::UnexpectedToken {
+                expected: vec![TokenType::Comma, TokenType::RParen],
+                found: "y".to_string(),
+                span: Span {
+                    start: 40,
+                    end: 41,
+                    line: 2,
+                    column: 14
+                },
+            }
+        );
+        assert_eq!(
+            parser.errors[2].to_string(),
+            "expected an identifier, got '5' instead"
+        );
+        assert_eq!(parser.errors[2].span().column, 15);
+    }
+    #[test]
+    fn node_ids() {
+        let input = "let a = 1 + 2; f(a);";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        assert_eq!(p.statement_count(), 2);
+        // 1, 2, 1 + 2, f, a, f(a)
+        assert_eq!(p.expression_count(), 6);
+        let Statement::Let(stmt) = &p[p.statements[0]] else {
+            panic!("expected a let statement");
+        };
+        let Expression::Infix { left, right, .. } = p[stmt.value()] else {
+            panic!("expected an infix expression");
+        };
+        // Children are allocated before their parent
+        assert!(left < right && right < stmt.value());
+        let mut types = vec![None; p.expression_count()];
+        types[stmt.value().index()] = Some("int");
+        assert_eq!(types.iter().flatten().count(), 1);
+    }
+    #[test]
+    fn parse_array_literal() {
+        let input = "[1, 2 * 2, fn(x) { x }];";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        let Statement::Expression(stmt) = &p[p.statements[0]] else {
+            panic!("expected an expression statement");
+        };
+        let Expression::Array { elements, .. } = &p[stmt.expression()] else {
+            panic!("expected an array literal");
+        };
+        assert_eq!(elements.len(), 3);
+        assert!(matches!(p[elements[1]], Expression::Infix { .. }));
+        assert!(matches!(p[elements[2]], Expression::Function { .. }));
+    }
+    #[test]
+    fn parse_index_precedence() {
+        let input = "a * [1, 2][b * c]; matrix[0][1] = get()[0];";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        // a * ([1, 2][b * c])
+        let Statement::Expression(stmt) = &p[p.statements[0]] else {
+            panic!("expected an expression statement");
+        };
+        let Expression::Infix { right, .. } = &p[stmt.expression()] else {
+            panic!("expected an infix expression");
+        };
+        let Expression::Index { left, index, .. } = &p[*right] else {
+            panic!("expected an index expression");
+        };
+        assert!(matches!(p[*left], Expression::Array { .. }));
+        assert!(matches!(p[*index], Expression::Infix { .. }));
+
+        // (matrix[0])[1] = ((get())[0])
+        let Statement::Expression(stmt) = &p[p.statements[1]] else {
+            panic!("expected an expression statement");
+        };
+        let Expression::Assign { target, value, .. } = &p[stmt.expression()] else {
+            panic!("expected an assignment");
+        };
+        let Expression::Index { left, .. } = &p[*target] else {
+            panic!("expected an index expression");
+        };
+        assert!(matches!(p[*left], Expression::Index { .. }));
+        let Expression::Index { left, .. } = &p[*value] else {
+            panic!("expected an index expression");
+        };
+        assert!(matches!(p[*left], Expression::Call { .. }));
+    }
+    #[test]
+    fn parse_hash_literal() {
+        let input = r#"let h = { "one": 1, 2: fn(x) { x }, true: [] };
+if (h) { {} }"#;
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        assert!(parser.warnings.is_empty(), "{:?}", parser.warnings);
+        let Statement::Let(stmt) = &p[p.statements[0]] else {
+            panic!("expected a let statement");
+        };
+        let Expression::Hash { pairs, .. } = &p[stmt.value()] else {
+            panic!("expected a hash literal");
+        };
+        assert_eq!(pairs.len(), 3);
+        assert!(matches!(&p[pairs[0].0], Expression::String { value, .. } if value == "one"));
+        assert!(matches!(p[pairs[1].1], Expression::Function { .. }));
+
+        let Statement::Expression(stmt) = &p[p.statements[1]] else {
+            panic!("expected an expression statement");
+        };
+        let Expression::If { consequence, .. } = &p[stmt.expression()] else {
+            panic!("expected an if expression");
+        };
+        let Statement::Expression(stmt) = &p[consequence.statements()[0]] else {
+            panic!("expected an expression statement");
+        };
+        assert!(
+            matches!(&p[stmt.expression()], Expression::Hash { pairs, .. } if pairs.is_empty())
+        );
+    }
+    #[test]
+    fn hash_literal_duplicate_keys() {
+        let input = r#"let h = { "a": 1, x: 2, "a": 3, x: 4, 1: 5, 01: 6 };"#;
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let _p = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        assert_eq!(parser.warnings.len(), 2, "{:?}", parser.warnings);
+        let ParseWarning::DuplicateKey { key, span, first } = &parser.warnings[0] else {
+            panic!("expected a duplicate key warning");
+        };
+        assert_eq!(key, r#""a""#);
+        assert_eq!((span.column, first.column), (25, 11));
+        assert_eq!(
+            parser.warnings[1].to_string(),
+            "duplicate key 1 in hash literal"
+        );
+        assert_eq!(parser.warnings[1].span().column, 45);
+    }
+    #[test]
+    fn missing_semicolon_terminates() {
+        let lexer = crate::lexer::Lexer::new("let x = 5");
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert!(p.statements.is_empty());
+        assert_eq!(parser.errors.len(), 1, "{:?}", parser.errors);
+        assert!(matches!(
+            parser.errors[0],
+            ParseError::MissingSemicolon { span } if span.column == 9
+        ));
+    }
+    #[test]
+    fn reports_every_independent_error() {
+        let input = r#"let = 1;
+let y = 2;
+let z 3;
+fn(x { x };
+let w = 4
+let v = 5;
+} let u = 6;"#;
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        let lines: Vec<usize> = parser.errors.iter().map(|e| e.span().line).collect();
+        assert_eq!(lines, [1, 3, 4, 5, 7], "{:#?}", parser.errors);
+        let names: Vec<&str> = p
+            .statements
+            .iter()
+            .map(|s| match &p[*s] {
+                Statement::Let(stmt) => match stmt.pattern() {
+                    Pattern::Binding { name, .. } => name.value(),
+                    _ => panic!("expected a binding"),
+                },
+                _ => panic!("expected a let statement"),
+            })
+            .collect();
+        assert_eq!(names, ["y", "v", "u"]);
+    }
+    #[test]
+    fn errors_in_blocks_do_not_cascade() {
+        let input = r#"let f = fn(x) {
+    let = 1;
+    x +
+};
+let g = fn() { return 1 };"#;
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert_eq!(parser.errors.len(), 2, "{:#?}", parser.errors);
+        assert!(matches!(
+            parser.errors[0],
+            ParseError::UnexpectedToken { ref expected, span, .. }
+                if expected == &[TokenType::Ident] && (span.line, span.column) == (2, 9)
+        ));
+        assert!(matches!(
+            parser.errors[1],
+            ParseError::ExpectedExpression { ref found, span }
+                if found == "}" && (span.line, span.column) == (4, 1)
+        ));
+        assert_eq!(p.statements.len(), 2);
+    }
+    #[test]
+    fn truncated_input_terminates() {
+        let inputs = [
+            "let",
+            "let mut",
+            "let x",
+            "let x =",
+            "return",
+            "fn(",
+            "fn(x,",
+            "fn(x) {",
+            "fn(x) { x + ",
+            "if (x",
+            "if (x) { 1 } else {",
+            "[1, 2",
+            r#"{"a": "#,
+            "add(1,",
+            "arr[",
+            "(1 + 2",
+            "while (x) {",
+            "for x in",
+            "loop { break",
+            "match x { [1, ",
+            "match x { y if",
+            "|x",
+            "|x| ",
+            "0..=",
+            "let x:",
+            "let x: array<int",
+            "fn(a: int) ->",
+            "import",
+            "import { a",
+            "import \"m.pab\" as",
+            "export",
+            "const",
+            "const X =",
+            "macro(x",
+            "macro(x) {",
+            "fn(x = ",
+            "fn(...",
+            "f(x:",
+            "f(...",
+            "a[1..",
+        ];
+        for input in inputs {
+            let lexer = crate::lexer::Lexer::new(input);
+            let mut parser = super::Parser::new(lexer, "test");
+
+            let _p = parser.parse();
+
+            assert!(
+                matches!(
+                    parser.errors.last(),
+                    Some(ParseError::UnexpectedEof { .. } | ParseError::UnclosedBlock { .. })
+                ),
+                "{:?} gave {:?}",
+                input,
+                parser.errors
+            );
+        }
+    }
+    #[test]
+    fn illegal_tokens_are_lexer_errors() {
+        let input = "let a = 1 @ 2;\nlet s = \"open;";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let _p = parser.parse();
+
+        assert_eq!(parser.errors.len(), 2, "{:#?}", parser.errors);
+        assert_eq!(
+            parser.errors[0].to_string(),
+            "expected ';' at the end of the statement"
+        );
+        assert!(matches!(
+            &parser.errors[1],
+            ParseError::Lex(crate::lexer::LexError::UnterminatedString { span }) if span.line == 2
+        ));
+    }
+    #[test]
+    fn parse_loops() {
+        let input = "while (i < 3) { i = i + 1; continue; }
+for x in xs { if (x) { break } };
+let v = loop { loop { break; }; break 2 * v; };";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        let Statement::Expression(stmt) = &p[p.statements[0]] else {
+            panic!("expected an expression statement");
+        };
+        let Expression::While {
+            condition, body, ..
+        } = &p[stmt.expression()]
+        else {
+            panic!("expected a while loop");
+        };
+        assert!(matches!(p[*condition], Expression::Infix { .. }));
+        assert!(matches!(p[body.statements()[1]], Statement::Continue(_)));
+
+        let Statement::Expression(stmt) = &p[p.statements[1]] else {
+            panic!("expected an expression statement");
+        };
+        let Expression::For {
+            variable, iterable, ..
+        } = &p[stmt.expression()]
+        else {
+            panic!("expected a for loop");
+        };
+        assert_eq!(variable.value(), "x");
+        assert!(matches!(&p[*iterable], Expression::Identifier(i) if i.value() == "xs"));
+
+        let Statement::Let(stmt) = &p[p.statements[2]] else {
+            panic!("expected a let statement");
+        };
+        let Expression::Loop { body, .. } = &p[stmt.value()] else {
+            panic!("expected a loop");
+        };
+        let Statement::Break(inner) = &p[body.statements()[1]] else {
+            panic!("expected a break statement");
+        };
+        assert!(matches!(
+            p[inner.value().unwrap()],
+            Expression::Infix { .. }
+        ));
+    }
+    #[test]
+    fn break_outside_loop() {
+        let input = "break;
+while (x) { let f = fn() { continue; }; }
+for x in xs { break x; }
+loop { let y = 1; } continue";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert_eq!(parser.errors.len(), 4, "{:?}", parser.errors);
+        assert!(matches!(
+            parser.errors[0],
+            ParseError::OutsideLoop { keyword: TokenType::Break, span } if span.line == 1
+        ));
+        assert!(matches!(
+            parser.errors[1],
+            ParseError::OutsideLoop { keyword: TokenType::Continue, span }
+                if (span.line, span.column) == (2, 28)
+        ));
+        let ParseError::BreakWithValue { span, enclosing } = parser.errors[2] else {
+            panic!("expected a break with a value");
+        };
+        assert_eq!((span.line, span.column), (3, 21));
+        assert_eq!((enclosing.line, enclosing.column), (3, 1));
+        assert!(matches!(
+            parser.errors[3],
+            ParseError::OutsideLoop { keyword: TokenType::Continue, span } if span.line == 4
+        ));
+        assert_eq!(p.statements.len(), 3);
+    }
+    #[test]
+    fn parse_match_expression() {
+        let input = r#"match value { 0 => "zero", -1.5 => "neg", [a, [_, "s"]] if a => a + 1, _ => "other" }"#;
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        assert!(parser.warnings.is_empty(), "{:?}", parser.warnings);
+        let Statement::Expression(stmt) = &p[p.statements[0]] else {
+            panic!("expected an expression statement");
+        };
+        let Expression::Match { subject, arms, .. } = &p[stmt.expression()] else {
+            panic!("expected a match expression");
+        };
+        assert!(matches!(&p[*subject], Expression::Identifier(i) if i.value() == "value"));
+        assert_eq!(arms.len(), 4);
+        assert!(matches!(
+            arms[0].pattern(),
+            Pattern::Literal {
+                value: Literal::Integer(0),
+                ..
+            }
+        ));
+        assert!(matches!(
+            arms[1].pattern(),
+            Pattern::Literal { value: Literal::Float(v), .. } if *v == -1.5
+        ));
+        let Pattern::Array { elements, .. } = arms[2].pattern() else {
+            panic!("expected an array pattern");
+        };
+        assert!(matches!(&elements[0], Pattern::Binding { name, .. } if name.value() == "a"));
+        assert!(matches!(&elements[1], Pattern::Array { elements, .. } if elements.len() == 2));
+        assert!(matches!(
+            p[arms[2].guard().unwrap()],
+            Expression::Identifier(_)
+        ));
+        assert!(matches!(p[arms[2].body()], Expression::Infix { .. }));
+        assert!(arms[3].pattern().is_irrefutable());
+        assert!(arms[3].guard().is_none());
+    }
+    #[test]
+    fn match_warnings() {
+        let input = r#"match x { y => 1, 2 => 2 };
+match x { [1, _] if x => 1, [1, a] => 2, [1, 3] => 3, _ => 4 };
+match a == b { true => 1, false => 2, _ => 3 };
+match x { true => 1, false => 2, _ => 3 };
+match a < b { true => 1 };
+match x { false => 1, _ if y => 2 };
+match x { true => 1, false => 2 };"#;
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let _p = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        assert_eq!(parser.warnings.len(), 5, "{:#?}", parser.warnings);
+        let ParseWarning::UnreachableArm { span, covered } = parser.warnings[0] else {
+            panic!("expected an unreachable arm");
+        };
+        assert_eq!((span.line, span.column), (1, 19));
+        assert_eq!((covered.line, covered.column), (1, 11));
+        let ParseWarning::UnreachableArm { span, covered } = parser.warnings[1] else {
+            panic!("expected an unreachable arm");
+        };
+        assert_eq!((span.line, span.column), (2, 42));
+        assert_eq!((covered.line, covered.column), (2, 29));
+        let ParseWarning::UnreachableArm { span, covered } = parser.warnings[2] else {
+            panic!("expected an unreachable arm");
+        };
+        assert_eq!((span.line, span.column), (3, 39));
+        assert_eq!((covered.line, covered.column), (3, 27));
+        assert_eq!(
+            parser.warnings[3],
+            ParseWarning::NonExhaustiveMatch {
+                missing: String::from("false"),
+                span: Span {
+                    start: 183,
+                    end: 188,
+                    line: 5,
+                    column: 1
+                },
+            }
+        );
+        assert_eq!(
+            parser.warnings[4].to_string(),
+            "non-exhaustive match, true not covered"
+        );
+        assert_eq!(parser.warnings[4].span().line, 6);
+    }
+    #[test]
+    fn malformed_patterns() {
+        let input = "match x { a + 1 => 2 }; match x { (a) => 1 }; match x { -a => 1 };";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert_eq!(parser.errors.len(), 3, "{:?}", parser.errors);
+        assert!(matches!(
+            parser.errors[0],
+            ParseError::UnexpectedToken { ref found, .. } if found == "+"
+        ));
+        assert!(matches!(
+            parser.errors[1],
+            ParseError::ExpectedPattern { ref found, span } if found == "(" && span.column == 35
+        ));
+        assert_eq!(
+            parser.errors[2].to_string(),
+            "expected an integer or a float, got 'a' instead"
+        );
+        assert!(p.statements.is_empty());
+    }
+    #[test]
+    fn parse_structs() {
+        let input = "struct Point { x, y, }
+let p = Point { x: 1, y: Point { x: 2, y: 3 } };
+p.y.x = -p.x;
+for q in (Point { x: 1, y: 2 }).x { q }
+match p { Point { x: 0, y } => y, Point {} => p }";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        assert_eq!(p.statements.len(), 5);
+        let Statement::Struct(stmt) = &p[p.statements[0]] else {
+            panic!("expected a struct declaration");
+        };
+        assert_eq!(stmt.name().value(), "Point");
+        let fields: Vec<&str> = stmt.fields().iter().map(|f| f.value()).collect();
+        assert_eq!(fields, ["x", "y"]);
+
+        let Statement::Let(stmt) = &p[p.statements[1]] else {
+            panic!("expected a let statement");
+        };
+        let Expression::Struct { name, fields, .. } = &p[stmt.value()] else {
+            panic!("expected a struct literal");
+        };
+        assert_eq!(name.value(), "Point");
+        assert_eq!(fields[0].0.value(), "x");
+        assert!(matches!(p[fields[1].1], Expression::Struct { .. }));
+
+        // ((p.y).x) = (-(p.x))
+        let Statement::Expression(stmt) = &p[p.statements[2]] else {
+            panic!("expected an expression statement");
+        };
+        let Expression::Assign { target, value, .. } = &p[stmt.expression()] else {
+            panic!("expected an assignment");
+        };
+        let Expression::Field { object, field, .. } = &p[*target] else {
+            panic!("expected a field access");
+        };
+        assert_eq!(field.value(), "x");
+        assert!(matches!(&p[*object], Expression::Field { field, .. } if field.value() == "y"));
+        assert!(matches!(p[*value], Expression::Prefix { .. }));
+
+        let Statement::Expression(stmt) = &p[p.statements[4]] else {
+            panic!("expected an expression statement");
+        };
+        let Expression::Match { subject, arms, .. } = &p[stmt.expression()] else {
+            panic!("expected a match expression");
+        };
+        assert!(matches!(p[*subject], Expression::Identifier(_)));
+        let Pattern::Struct {
+            name: Some(name),
+            fields,
+            ..
+        } = arms[0].pattern()
+        else {
+            panic!("expected a struct pattern");
+        };
+        assert_eq!(name.value(), "Point");
+        assert!(matches!(fields[0].1, Pattern::Literal { .. }));
+        assert!(matches!(&fields[1].1, Pattern::Binding { name, .. } if name.value() == "y"));
+    }
+    #[test]
+    fn struct_errors_and_warnings() {
+        let input = "struct P { x, y, x }
+let p = P { y: 1, y: 2 };
+match p { P { x: a } => 1, P { x: 1, y: 2 } => 2, Q { y: 1 } => 3 };
+for q in P { x: 1 } { q };
+1 + 2 = p.x;";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let _p = parser.parse();
+
+        let lines: Vec<usize> = parser.errors.iter().map(|e| e.span().line).collect();
+        assert_eq!(lines, [1, 2, 4, 4, 5], "{:#?}", parser.errors);
+        let ParseError::DuplicateField { name, span, first } = &parser.errors[0] else {
+            panic!("expected a duplicate field");
+        };
+        assert_eq!(name, "x");
+        assert_eq!((span.column, first.column), (18, 12));
+        assert_eq!(parser.errors[1].to_string(), "duplicate field 'y'");
+        // `P` is the iterable, the body `{ x: 1 }` is not a statement and
+        // `{ q }` is then taken for a hash literal
+        assert!(matches!(
+            parser.errors[2],
+            ParseError::ExpectedExpression { ref found, .. } if found == ":"
+        ));
+        assert!(matches!(
+            parser.errors[4],
+            ParseError::InvalidAssignTarget { ref found, .. } if found == "1 + 2"
+        ));
+        assert_eq!(parser.warnings.len(), 1, "{:?}", parser.warnings);
+        let ParseWarning::UnreachableArm { span, covered } = parser.warnings[0] else {
+            panic!("expected an unreachable arm");
+        };
+        assert_eq!((span.line, span.column), (3, 28));
+        assert_eq!(covered.column, 11);
+    }
+    #[test]
+    fn parse_enums() {
+        let input = "enum Shape { Circle(r), Rect(w, h), Empty }
+let s = Shape::Rect(1, 2);
+match s { Shape::Circle(r) => r, Shape::Rect(w, _) => w, Shape::Empty => 0 }";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        assert!(parser.warnings.is_empty(), "{:?}", parser.warnings);
+        let Statement::Enum(stmt) = &p[p.statements[0]] else {
+            panic!("expected an enum declaration");
+        };
+        assert_eq!(stmt.name().value(), "Shape");
+        let variants: Vec<(&str, usize)> = stmt
+            .variants()
+            .iter()
+            .map(|v| (v.name().value(), v.fields().len()))
+            .collect();
+        assert_eq!(variants, [("Circle", 1), ("Rect", 2), ("Empty", 0)]);
+
+        let Statement::Let(stmt) = &p[p.statements[1]] else {
+            panic!("expected a let statement");
+        };
+        let Expression::Variant {
+            enum_name,
+            variant,
+            args,
+            ..
+        } = &p[stmt.value()]
+        else {
+            panic!("expected a variant");
+        };
+        assert_eq!((enum_name.value(), variant.value()), ("Shape", "Rect"));
+        assert_eq!(args.len(), 2);
+
+        let Statement::Expression(stmt) = &p[p.statements[2]] else {
+            panic!("expected an expression statement");
+        };
+        let Expression::Match { arms, .. } = &p[stmt.expression()] else {
+            panic!("expected a match expression");
+        };
+        let Pattern::Variant {
+            variant, fields, ..
+        } = arms[1].pattern()
+        else {
+            panic!("expected a variant pattern");
+        };
+        assert_eq!(variant.value(), "Rect");
+        assert!(matches!(
+            fields[..],
+            [Pattern::Binding { .. }, Pattern::Wildcard(_)]
+        ));
+        assert!(matches!(arms[2].pattern(), Pattern::Variant { fields, .. } if fields.is_empty()));
+    }
+    #[test]
+    fn enum_errors_and_warnings() {
+        let input = "enum Shape { Circle(r), Rect(w, h), Circle }
+enum Shape { Circle(r), Rect(w, h), Empty }
+let a = Shape::Square(1);
+let b = Shape::Rect(1);
+match b { Shape::Circle(r, s) => 0 };
+match b { Shape::Circle(r) => r, Shape::Rect(0, h) => h, Shape::Circle(1) => 1 };
+let c = Other::Any(1, 2);";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let _p = parser.parse();
+
+        let lines: Vec<usize> = parser.errors.iter().map(|e| e.span().line).collect();
+        assert_eq!(lines, [1, 3, 4, 5], "{:#?}", parser.errors);
+        let ParseError::DuplicateVariant { name, span, first } = &parser.errors[0] else {
+            panic!("expected a duplicate variant");
+        };
+        assert_eq!(name, "Circle");
+        assert_eq!((span.column, first.column), (37, 14));
+        assert_eq!(
+            parser.errors[1].to_string(),
+            "enum 'Shape' has no variant 'Square'"
+        );
+        assert_eq!(
+            parser.errors[2].to_string(),
+            "'Shape::Rect' takes 2 field(s) but 1 were given"
+        );
+        let diagnostic = crate::diagnostic::Diagnostic::from(&parser.errors[2]);
+        assert!(format!("{:?}", diagnostic).contains("Rect(w, h)"));
+        assert!(matches!(
+            parser.errors[3],
+            ParseError::VariantArity {
+                expected: 1,
+                found: 2,
+                ..
+            }
+        ));
+
+        let messages: Vec<String> = parser.warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "unreachable match arm",
+                "non-exhaustive match, Shape::Rect and Shape::Empty not covered",
+            ]
+        );
+        assert_eq!(parser.warnings[0].span().column, 58);
+    }
+    #[test]
+    fn parse_ranges() {
+        let inputs = [
+            ("0..10", "(0..10)"),
+            ("0..=n + 1", "(0..=(n + 1))"),
+            ("a..b == c", "(a..(b == c))"),
+            ("-1..", "((-1)..)"),
+            ("s[..5]", "(s[(..5)])"),
+            ("arr[1..3][..]", "((arr[(1..3)])[(..)])"),
+            ("x = 1..2", "(x = (1..2))"),
+        ];
+        for (input, expected) in inputs {
+            let lexer = crate::lexer::Lexer::new(input);
+            let mut parser = super::Parser::new(lexer, "test");
+
+            let p = parser.parse();
+
+            assert!(parser.errors.is_empty(), "{}: {:?}", input, parser.errors);
+            assert_eq!(format!("{:#}", p), format!("{};", expected));
+        }
+
+        let input = "for i in 0.. { i }";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+        let p = parser.parse();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        let Statement::Expression(stmt) = &p[p.statements[0]] else {
+            panic!("expected an expression statement");
+        };
+        let Expression::For { iterable, body, .. } = &p[stmt.expression()] else {
+            panic!("expected a for loop");
+        };
+        assert!(matches!(
+            p[*iterable],
+            Expression::Range {
+                start: Some(_),
+                end: None,
+                ..
+            }
+        ));
+        assert_eq!(body.statements().len(), 1);
+    }
+    #[test]
+    fn range_errors_and_warnings() {
+        let input = "let a = 0..=;
+let b = 5..1;
+let c = [1, 2, 3][1..4];
+let d = \"abc\"[..=3];
+let e = [1, 2, 3][1..3] + \"abc\"[3..=2] + \"abc\"[..=2];
+let f = 5..5;
+let g = 5..=5;
+let h = [1, 2][5..] + \"ab\"[3..4] + \"ab\"[2..];";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let _p = parser.parse();
+
+        assert_eq!(parser.errors.len(), 1, "{:?}", parser.errors);
+        assert_eq!(
+            parser.errors[0].to_string(),
+            "inclusive range without an end"
+        );
+        assert_eq!(parser.errors[0].span().column, 10);
+
+        let messages: Vec<String> = parser.warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "range 5..1 is empty, it starts after its end",
+                "slice end 4 is out of bounds for a length of 3",
+                "slice end 3 is out of bounds for a length of 3",
+                "range 3..=2 is empty, it starts after its end",
+                "range 5..5 is empty, it ends where it starts",
+                "slice start 5 is out of bounds for a length of 2",
+                "slice start 3 is out of bounds for a length of 2",
+            ]
+        );
+        let lines: Vec<usize> = parser.warnings.iter().map(|w| w.span().line).collect();
+        assert_eq!(lines, [2, 3, 4, 5, 6, 8, 8]);
+    }
+    #[test]
+    fn parse_destructuring() {
+        let input = "let [a, mut b, ...rest] = xs;
+let { x, y: [_, mut z] } = point;
+let Point { x: px, mut y } = p;
+let [first, ..., [last]] = xs;
+let [a, ...b] = [1, 2, 3];
+let [...mut tail] = xs;
+match xs { [head, ...tail] => head, [] => 0 }";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        let lets: Vec<&LetStatement> = p
+            .statements
+            .iter()
+            .filter_map(|s| match &p[*s] {
+                Statement::Let(stmt) => Some(stmt),
+                _ => None,
+            })
+            .collect();
+        let bindings: Vec<Vec<&str>> = lets
+            .iter()
+            .map(|stmt| {
+                let bindings = stmt.pattern().bindings();
+                bindings.into_iter().map(|b| b.value()).collect()
+            })
+            .collect();
+        assert_eq!(
+            bindings,
+            [
+                vec!["a", "b", "rest"],
+                vec!["x", "z"],
+                vec!["px", "y"],
+                vec!["first", "last"],
+                vec!["a", "b"],
+                vec!["tail"],
+            ]
+        );
+
+        let Pattern::Array { elements, .. } = lets[0].pattern() else {
+            panic!("expected an array pattern");
+        };
+        assert!(matches!(elements[1], Pattern::Binding { is_mut: true, .. }));
+        assert!(matches!(
+            &elements[2],
+            Pattern::Rest { name: Some(name), is_mut: false, .. } if name.value() == "rest"
+        ));
+        let Pattern::Struct {
+            name: None, fields, ..
+        } = lets[1].pattern()
+        else {
+            panic!("expected a struct pattern without a name");
+        };
+        assert!(matches!(fields[1].1, Pattern::Array { .. }));
+        let Pattern::Struct {
+            name: Some(_),
+            fields,
+            ..
+        } = lets[2].pattern()
+        else {
+            panic!("expected a struct pattern");
+        };
+        assert!(matches!(
+            &fields[1].1,
+            Pattern::Binding { name, is_mut: true } if name.value() == "y"
+        ));
+        assert!(matches!(
+            lets[5].pattern(),
+            Pattern::Array { elements, .. } if matches!(elements[0], Pattern::Rest { is_mut: true, .. })
+        ));
+        assert!(matches!(p[p.statements[6]], Statement::Expression(_)));
+        assert!(parser.warnings.is_empty(), "{:?}", parser.warnings);
+    }
+    #[test]
+    fn destructuring_errors() {
+        let input = "let [a, 1] = xs;
+let [a, ...b, ...c] = xs;
+let [a, { b, a }] = xs;
+let [a, b] = [1, 2, 3];
+let [a, b, ...c] = [1];
+let P { x } = Q { x: 1 };
+let { z } = P { x: 1 };
+let [a] = 5;
+let [[a, b], c] = [[1], 2];
+let ...a = xs;
+match xs { [a, ...a] => 1 }
+let Shape::Empty = s;";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let _p = parser.parse();
+
+        let messages: Vec<String> = parser.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "refutable pattern in let statement",
+                "more than one rest in an array pattern",
+                "'a' is bound more than once in the same pattern",
+                "pattern can't match the value, the array has 3 element(s), the pattern expects 2",
+                "pattern can't match the value, the array has 1 element(s), the pattern expects at least 2",
+                "pattern can't match the value, the value is a 'Q', the pattern expects a 'P'",
+                "pattern can't match the value, the 'P' has no field 'z'",
+                "pattern can't match the value, the value is an integer, the pattern expects an array",
+                "pattern can't match the value, the array has 1 element(s), the pattern expects 2",
+                "'...' outside of an array pattern",
+                "'a' is bound more than once in the same pattern",
+                "refutable pattern in let statement",
+            ]
+        );
+        let lines: Vec<usize> = parser.errors.iter().map(|e| e.span().line).collect();
+        assert_eq!(lines, (1..=12).collect::<Vec<_>>());
+        let codes: Vec<&str> = parser.errors.iter().map(|e| e.code()).collect();
+        assert_eq!(codes[..4], ["E0117", "E0119", "E0120", "E0121"]);
+        assert_eq!(parser.errors[9].code(), "E0118");
+        assert_eq!(parser.errors[8].span().column, 6);
+    }
+    #[test]
+    fn parse_annotations() {
+        let input = "let x: int = 5;
+let [a, b]: array<float> = xs;
+let f = fn(a: float, b, c: map<string, array<int>>) -> float { a };
+let g = |n: int| n;
+let h = fn() -> array<array<int>> { [] };";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        let stmts: Vec<&LetStatement> = p
+            .statements
+            .iter()
+            .filter_map(|s| match &p[*s] {
+                Statement::Let(stmt) => Some(stmt),
+                _ => None,
+            })
+            .collect();
+        let annotations: Vec<Option<String>> = stmts
+            .iter()
+            .map(|stmt| stmt.annotation().map(|a| a.to_string()))
+            .collect();
+        assert_eq!(
+            annotations,
+            [
+                Some(String::from("int")),
+                Some(String::from("array<float>")),
+                None,
+                None,
+                None
+            ]
+        );
+
+        let Expression::Function {
+            params,
+            return_type,
+            ..
+        } = &p[stmts[2].value()]
+        else {
+            panic!("expected a function literal");
+        };
+        let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+        assert_eq!(params, ["a: float", "b", "c: map<string, array<int>>"]);
+        let return_type = return_type.as_ref().unwrap();
+        assert_eq!(return_type.name().value(), "float");
+        assert!(return_type.args().is_empty());
+
+        let Expression::Function { params, .. } = &p[stmts[3].value()] else {
+            panic!("expected a function literal");
+        };
+        assert_eq!(params[0].annotation().unwrap().name().value(), "int");
+        let Expression::Function { return_type, .. } = &p[stmts[4].value()] else {
+            panic!("expected a function literal");
+        };
+        let args = return_type.as_ref().unwrap().args();
+        assert_eq!(args[0].args()[0].name().value(), "int");
+    }
+    #[test]
+    fn annotation_errors() {
+        let input = "let x: = 5;
+let y: array<int = 5;
+let f = fn(a: 1) { a };
+let g = fn(a) -> { a };
+let h = fn(a) int { a };
+let z: array<> = [];";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let _p = parser.parse();
+
+        let messages: Vec<String> = parser.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "expected a type, got '=' instead",
+                "expected '>', got '=' instead",
+                "expected a type, got '1' instead",
+                "expected a type, got '{' instead",
+                "expected '{', got 'int' instead",
+                "expected a type, got '>' instead",
+            ]
+        );
+        let lines: Vec<usize> = parser.errors.iter().map(|e| e.span().line).collect();
+        assert_eq!(lines, [1, 2, 3, 4, 5, 6]);
+        assert_eq!(parser.errors[0].code(), "E0122");
+    }
+    #[test]
+    fn parse_imports() {
+        let input = r#"import "lib/math.pab" as math;
+import { add, sub } from "./util.pab";
+import {} from "empty.pab";
+export let [x, y] = [1, 2];
+export struct Point { x, y }
+export enum Shape { Empty }
+let z = 3;"#;
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        let imports: Vec<(&str, Vec<&str>)> = p
+            .imports()
+            .map(|import| {
+                let names = match import.items() {
+                    ImportItems::Module(name) => vec![name.value()],
+                    ImportItems::Names(names) => names.iter().map(|n| n.value()).collect(),
+                };
+                (import.path(), names)
+            })
+            .collect();
+        assert_eq!(
+            imports,
+            [
+                ("lib/math.pab", vec!["math"]),
+                ("./util.pab", vec!["add", "sub"]),
+                ("empty.pab", vec![]),
+            ]
+        );
+        assert!(matches!(
+            p.imports().next().unwrap().items(),
+            ImportItems::Module(_)
+        ));
+        let exports: Vec<&str> = p.exports().iter().map(|e| e.value()).collect();
+        assert_eq!(exports, ["x", "y", "Point", "Shape"]);
+    }
+    #[test]
+    fn import_errors() {
+        let input = r#"import "a.pab";
+import { a } "b.pab";
+import math;
+export 1 + 2;
+fn() { import "c.pab" as c; };
+if (true) { export let x = 1; };
+export"#;
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let _p = parser.parse();
+
+        let messages: Vec<String> = parser.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "expected 'as', got ';' instead",
+                "expected 'from', got '\"b.pab\"' instead",
+                "expected a string or '{', got 'math' instead",
+                "expected a declaration after 'export', got '1' instead",
+                "'import' is only allowed at the top level",
+                "'export' is only allowed at the top level",
+                "unexpected end of input, expected a declaration",
+            ]
+        );
+        let lines: Vec<usize> = parser.errors.iter().map(|e| e.span().line).collect();
+        assert_eq!(lines, [1, 2, 3, 4, 5, 6, 7]);
+        let codes: Vec<&str> = parser.errors[3..6].iter().map(|e| e.code()).collect();
+        assert_eq!(codes, ["E0124", "E0123", "E0123"]);
+    }
+    #[test]
+    fn parse_consts() {
+        let input = r#"const DAY = 60 * 60 * 24;
+const WEEK: int = DAY * 7;
+const HALF = -1.0 / 2.0;
+const NAME = "pa" + "b";
+const SHORT = !(WEEK > 1000000) == (NAME != "x");
+export const LIMIT = WEEK - DAY;"#;
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        let names: Vec<&str> = p
+            .statements
+            .iter()
+            .filter_map(|&id| match &p[id] {
+                Statement::Const(stmt) => Some(stmt.name().value()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, ["DAY", "WEEK", "HALF", "NAME", "SHORT"]);
+        let values: Vec<(&str, &Literal)> = p
+            .statements
+            .iter()
+            .filter_map(|&id| match &p[id] {
+                Statement::Const(stmt) => Some((stmt.name().value(), p.const_value(id)?)),
+                Statement::Export(stmt) => match &p[stmt.declaration()] {
+                    Statement::Const(c) => {
+                        Some((c.name().value(), p.const_value(stmt.declaration())?))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            values,
+            [
+                ("DAY", &Literal::Integer(86400)),
+                ("WEEK", &Literal::Integer(604800)),
+                ("HALF", &Literal::Float(-0.5)),
+                ("NAME", &Literal::String(String::from("pab"))),
+                ("SHORT", &Literal::Boolean(true)),
+                ("LIMIT", &Literal::Integer(518400)),
+            ]
+        );
+        let exports: Vec<&str> = p.exports().iter().map(|e| e.value()).collect();
+        assert_eq!(exports, ["LIMIT"]);
+    }
+    #[test]
+    fn const_errors() {
+        let input = r#"let n = 2;
+const A = n + 1;
+const B = f(1);
+const C = 1 / (2 - 2);
+const D = 9223372036854775807 + 1;
+const E = 1 + "s";
+const F = 10;
+F = 11;
+let mut g = F;
+g = 12;
+const G: int = F +;"#;
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let _p = parser.parse();
+
+        let messages: Vec<String> = parser.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "const initializer is not a constant expression",
+                "const initializer is not a constant expression",
+                "can't evaluate the constant, division by zero",
+                "can't evaluate the constant, the result overflows",
+                "can't evaluate the constant, '+' can't be applied to int and string",
+                "cannot assign to constant 'F'",
+                "expected an expression, got ';' instead",
+            ]
+        );
+        let lines: Vec<usize> = parser.errors.iter().map(|e| e.span().line).collect();
+        assert_eq!(lines, [2, 3, 4, 5, 6, 8, 11]);
+        let codes: Vec<&str> = parser.errors[..6].iter().map(|e| e.code()).collect();
+        assert_eq!(
+            codes,
+            ["E0125", "E0125", "E0126", "E0126", "E0126", "E0127"]
+        );
+        assert_eq!(parser.errors[0].span().column, 11);
+    }
+    #[test]
+    fn parse_macro_literal() {
+        let input = "let m = macro(x, y) { quote(unquote(x) + y) };";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        let Statement::Let(stmt) = &p[p.statements[0]] else {
+            panic!("expected a let statement")
+        };
+        let Expression::Macro { params, body, .. } = &p[stmt.value()] else {
+            panic!("expected a macro literal, got {:?}", p[stmt.value()])
+        };
+        let names: Vec<&str> = params.iter().map(|p| p.name().value()).collect();
+        assert_eq!(names, ["x", "y"]);
+        assert_eq!(body.statements().len(), 1);
+    }
+    #[test]
+    fn parse_default_named_and_rest() {
+        let input = "let f = fn(x, y = 10, ...rest) { x };
+f(1, ...xs);
+f(y: 3, x: 1);
+1 |> f(y: 2);";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        let Statement::Let(stmt) = &p[p.statements[0]] else {
+            panic!("expected a let statement")
+        };
+        let Expression::Function { params, .. } = &p[stmt.value()] else {
+            panic!("expected a function literal, got {:?}", p[stmt.value()])
+        };
+        let names: Vec<&str> = params.iter().map(|p| p.name().value()).collect();
+        assert_eq!(names, ["x", "y", "rest"]);
+        assert!(params[0].default().is_none());
+        assert!(matches!(
+            p[params[1].default().unwrap()],
+            Expression::Integer { value: 10, .. }
+        ));
+        let rests: Vec<bool> = params.iter().map(|p| p.is_rest()).collect();
+        assert_eq!(rests, [false, false, true]);
+
+        let calls: Vec<(usize, Vec<&str>)> = p.statements[1..]
+            .iter()
+            .map(|id| {
+                let Statement::Expression(stmt) = &p[*id] else {
+                    panic!("expected an expression statement")
+                };
+                let Expression::Call { args, named, .. } = &p[stmt.expression()] else {
+                    panic!("expected a call")
+                };
+                (args.len(), named.iter().map(|(n, _)| n.value()).collect())
+            })
+            .collect();
+        assert_eq!(calls, [(2, vec![]), (0, vec!["y", "x"]), (1, vec!["y"])]);
+        let Statement::Expression(stmt) = &p[p.statements[1]] else {
+            panic!("expected an expression statement")
+        };
+        let Expression::Call { args, .. } = &p[stmt.expression()] else {
+            panic!("expected a call")
+        };
+        assert!(matches!(p[args[1]], Expression::Spread { .. }));
+    }
+    #[test]
+    fn argument_errors() {
+        let input = "let f = fn(x, y = 10, ...rest) { x };
+let g = fn(a, b) { a };
+let h = fn(a = 1, b) { a };
+let k = fn(...r, a) { a };
+f(x: 1, 2);
+f(x: 1, x: 2);
+f(y: 3);
+g(1, 2, 3);
+g(1, a: 2);
+g(c: 1);
+2 |> g(1, 3);
+g(...xs);
+let inner = fn(g) { g(1) };
+if (true) { let g = fn() { 1 }; g(); };
+g(1);";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let _p = parser.parse();
+
+        let messages: Vec<String> = parser.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "parameter 'b' has no default but follows one that has",
+                "the rest parameter must be the last one",
+                "positional argument after a named one",
+                "argument 'x' is given more than once",
+                "missing argument(s) 'x' in the call to 'f'",
+                "'g' takes 2 argument(s) but 3 were given",
+                "argument 'a' is given more than once",
+                "'g' has no parameter named 'c'",
+                "'g' takes 2 argument(s) but 3 were given",
+                "missing argument(s) 'b' in the call to 'g'",
+            ]
+        );
+        let lines: Vec<usize> = parser.errors.iter().map(|e| e.span().line).collect();
+        assert_eq!(lines, [3, 4, 5, 6, 7, 8, 9, 10, 11, 15]);
+        let codes: Vec<&str> = parser.errors.iter().map(|e| e.code()).collect();
+        assert_eq!(
+            codes,
+            [
+                "E0129", "E0128", "E0130", "E0131", "E0132", "E0133", "E0131", "E0134", "E0133",
+                "E0132"
+            ]
+        );
+        assert_eq!(parser.errors[5].span().column, 9);
+    }
+    #[test]
+    fn consts_are_scoped() {
+        let input = "const MAX = 3;
+let f = fn(MAX) { MAX = 2; };
+let g = fn() { let mut MAX = 1; MAX = 4; };
+let h = fn() { const L = MAX + 1; const MAX = L * 2; let M = MAX; L };
+for MAX in xs { MAX = 1; };
+match x { MAX => MAX = 1 };
+L = 5;
+const L = MAX * 10;
+MAX = 6;
+if (true) { const MAX = 1; const MAX = 2; };
+const L = 1;";
+        let lexer = crate::lexer::Lexer::new(input);
+        let mut parser = super::Parser::new(lexer, "test");
+
+        let p = parser.parse();
+
+        let messages: Vec<String> = parser.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "cannot assign to constant 'MAX'",
+                "constant 'MAX' is declared more than once in the same scope",
+                "constant 'L' is declared more than once in the same scope",
+            ]
+        );
+        let lines: Vec<usize> = parser.errors.iter().map(|e| e.span().line).collect();
+        assert_eq!(lines, [9, 10, 11]);
+        assert_eq!(parser.errors[1].code(), "E0135");
+
+        let values: Vec<&Literal> = p
+            .statements
+            .iter()
+            .filter_map(|&id| p.const_value(id))
+            .collect();
+        assert_eq!(values, [&Literal::Integer(3), &Literal::Integer(30)]);
+        let Statement::Let(stmt) = &p[p.statements[3]] else {
+            panic!("expected a let statement")
+        };
+        let Expression::Function { body, .. } = &p[stmt.value()] else {
+            panic!("expected a function literal")
+        };
+        let inner: Vec<&Literal> = body
+            .statements()
+            .iter()
+            .filter_map(|&id| p.const_value(id))
+            .collect();
+        assert_eq!(inner, [&Literal::Integer(4), &Literal::Integer(8)]);
     }
 }
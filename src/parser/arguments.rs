@@ -0,0 +1,241 @@
+//! Checking the arguments of the calls of named function literals
+//!
+//! A call is checked when its callee is a name bound by `let name = fn...`
+//! in an enclosing scope, the other callees are only known when the program
+//! runs. The arguments are checked once the whole program is parsed, so the
+//! calls written with `|>` get all of theirs.
+
+use super::{ParseError, Parser};
+use crate::ast::visit::{self, Visitor};
+use crate::ast::{
+    BlockStatement, ConstStatement, ExprId, Expression, Identifier, ImportItems, ImportStatement,
+    LetStatement, MatchArm, Parameter, Pattern, Program, TypeAnnotation,
+};
+use crate::token::{Span, Token};
+use std::collections::HashMap;
+
+impl Parser<'_> {
+    /// Report the calls whose arguments don't fit the parameters of the
+    /// function called
+    pub(super) fn check_arguments(&mut self) {
+        let mut calls = Calls::default();
+        calls.visit_program(&self.program);
+        self.errors.extend(calls.errors);
+    }
+}
+
+/// The function literal and name span a name is bound to, if it is bound
+/// to one
+type Binding = Option<(ExprId, Span)>;
+
+#[derive(Default)]
+struct Calls {
+    /// The names bound in each scope, the innermost last
+    scopes: Vec<HashMap<String, Binding>>,
+    errors: Vec<ParseError>,
+}
+
+impl Calls {
+    fn bind(&mut self, name: &Identifier, binding: Binding) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.value().to_string(), binding);
+        }
+    }
+    fn lookup(&self, name: &str) -> Binding {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .copied()
+            .flatten()
+    }
+    /// Visit `body` in a new scope with the names bound
+    fn scoped<'i>(
+        &mut self,
+        names: impl IntoIterator<Item = &'i Identifier>,
+        body: impl FnOnce(&mut Self),
+    ) {
+        self.scopes.push(HashMap::new());
+        for name in names {
+            self.bind(name, None);
+        }
+        body(self);
+        self.scopes.pop();
+    }
+    fn check(
+        &mut self,
+        program: &Program,
+        callee: ExprId,
+        args: &[ExprId],
+        named: &[(Identifier, ExprId)],
+    ) {
+        let Expression::Identifier(name) = &program[callee] else {
+            return;
+        };
+        let Some((function, declaration)) = self.lookup(name.value()) else {
+            return;
+        };
+        let Expression::Function { params, .. } = &program[function] else {
+            return;
+        };
+        let callee = name.value().to_string();
+        let shown: Vec<String> = params.iter().map(Parameter::to_string).collect();
+        let fixed: Vec<&Parameter> = params.iter().filter(|p| !p.is_rest()).collect();
+        let spread = args
+            .iter()
+            .any(|arg| matches!(program[*arg], Expression::Spread { .. }));
+
+        for (name, _) in named {
+            let span = name.token().span();
+            match fixed.iter().position(|p| p.name().value() == name.value()) {
+                None => {
+                    return self.errors.push(ParseError::UnknownParameter {
+                        callee,
+                        name: name.value().to_string(),
+                        params: shown,
+                        span,
+                    })
+                }
+                Some(i) if i < args.len() && !spread => {
+                    return self.errors.push(ParseError::ArgumentGivenTwice {
+                        name: name.value().to_string(),
+                        span,
+                        first: program[args[i]].token().span(),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        // The number of arguments a spread passes is only known when the
+        // program runs
+        if spread {
+            return;
+        }
+
+        if args.len() > fixed.len() && fixed.len() == params.len() {
+            return self.errors.push(ParseError::TooManyArguments {
+                callee,
+                params: shown,
+                found: args.len() + named.len(),
+                span: program[args[fixed.len()]].token().span(),
+                declaration,
+            });
+        }
+        let missing: Vec<String> = fixed
+            .iter()
+            .skip(args.len())
+            .filter(|p| p.default().is_none())
+            .filter(|p| !named.iter().any(|(n, _)| n.value() == p.name().value()))
+            .map(|p| p.name().value().to_string())
+            .collect();
+        if !missing.is_empty() {
+            self.errors.push(ParseError::MissingArguments {
+                callee,
+                missing,
+                span: name.token().span(),
+                declaration,
+            });
+        }
+    }
+}
+
+impl Visitor for Calls {
+    fn visit_program(&mut self, program: &Program) {
+        self.scoped([], |calls| visit::walk_program(calls, program));
+    }
+    fn visit_block(&mut self, program: &Program, block: &BlockStatement) {
+        self.scoped([], |calls| visit::walk_block(calls, program, block));
+    }
+    fn visit_let(&mut self, program: &Program, stmt: &LetStatement) {
+        self.visit_expression(program, stmt.value());
+        match stmt.pattern() {
+            Pattern::Binding {
+                name,
+                is_mut: false,
+            } if matches!(program[stmt.value()], Expression::Function { .. }) => {
+                self.bind(name, Some((stmt.value(), name.token().span())))
+            }
+            pattern => {
+                for name in pattern.bindings() {
+                    self.bind(name, None);
+                }
+            }
+        }
+    }
+    fn visit_const(&mut self, _program: &Program, stmt: &ConstStatement) {
+        self.bind(stmt.name(), None);
+    }
+    fn visit_import(&mut self, stmt: &ImportStatement) {
+        match stmt.items() {
+            ImportItems::Module(name) => self.bind(name, None),
+            ImportItems::Names(names) => {
+                for name in names {
+                    self.bind(name, None);
+                }
+            }
+        }
+    }
+    fn visit_for(
+        &mut self,
+        program: &Program,
+        _token: &Token,
+        variable: &Identifier,
+        iterable: ExprId,
+        body: &BlockStatement,
+    ) {
+        self.visit_expression(program, iterable);
+        self.scoped([variable], |calls| calls.visit_block(program, body));
+    }
+    fn visit_match(
+        &mut self,
+        program: &Program,
+        _token: &Token,
+        subject: ExprId,
+        arms: &[MatchArm],
+    ) {
+        self.visit_expression(program, subject);
+        for arm in arms {
+            self.scoped(arm.pattern().bindings(), |calls| {
+                if let Some(guard) = arm.guard() {
+                    calls.visit_expression(program, guard);
+                }
+                calls.visit_expression(program, arm.body());
+            });
+        }
+    }
+    fn visit_function(
+        &mut self,
+        program: &Program,
+        _token: &Token,
+        params: &[Parameter],
+        _return_type: Option<&TypeAnnotation>,
+        body: &BlockStatement,
+    ) {
+        for default in params.iter().filter_map(Parameter::default) {
+            self.visit_expression(program, default);
+        }
+        self.scoped(params.iter().map(Parameter::name), |calls| {
+            calls.visit_block(program, body)
+        });
+    }
+    fn visit_macro(
+        &mut self,
+        program: &Program,
+        token: &Token,
+        params: &[Parameter],
+        body: &BlockStatement,
+    ) {
+        self.visit_function(program, token, params, None, body);
+    }
+    fn visit_call(
+        &mut self,
+        program: &Program,
+        token: &Token,
+        callee: ExprId,
+        args: &[ExprId],
+        named: &[(Identifier, ExprId)],
+    ) {
+        visit::walk_call(self, program, token, callee, args, named);
+        self.check(program, callee, args, named);
+    }
+}
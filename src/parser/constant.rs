@@ -0,0 +1,47 @@
+//! Evaluation of the initializers of `const` declarations
+
+use super::{ParseError, Parser};
+use crate::ast::{ExprId, Expression, Literal};
+
+impl Parser<'_> {
+    /// Compute the value of a constant expression
+    ///
+    /// Constant expressions are literals, the constants in scope and
+    /// the arithmetic, comparison and negation operators applied to them.
+    pub(super) fn evaluate_const(&self, id: ExprId) -> Result<Literal, ParseError> {
+        match &self.program[id] {
+            Expression::Integer { value, .. } => Ok(Literal::Integer(*value)),
+            Expression::Float { value, .. } => Ok(Literal::Float(*value)),
+            Expression::Boolean { value, .. } => Ok(Literal::Boolean(*value)),
+            Expression::String { value, .. } => Ok(Literal::String(value.clone())),
+            Expression::Identifier(ident) => match self.constant(ident.value()) {
+                Some((value, _)) => Ok(value.clone()),
+                None => Err(ParseError::NonConstant {
+                    span: ident.token().span(),
+                }),
+            },
+            Expression::Prefix { token, right } => {
+                let right = self.evaluate_const(*right)?;
+                Literal::prefix(&token.token_type, right).map_err(|reason| {
+                    ParseError::ConstEvaluation {
+                        reason,
+                        span: token.span(),
+                    }
+                })
+            }
+            Expression::Infix { token, left, right } => {
+                let left = self.evaluate_const(*left)?;
+                let right = self.evaluate_const(*right)?;
+                Literal::infix(&token.token_type, left, right).map_err(|reason| {
+                    ParseError::ConstEvaluation {
+                        reason,
+                        span: token.span(),
+                    }
+                })
+            }
+            expression => Err(ParseError::NonConstant {
+                span: expression.token().span(),
+            }),
+        }
+    }
+}
@@ -0,0 +1,557 @@
+use crate::diagnostic::Diagnostic;
+use crate::lexer::LexError;
+use crate::token::{Span, Token, TokenType};
+use thiserror::Error as ThisError;
+
+/// An error preventing a statement from being parsed
+///
+/// The messages don't include the location, see [`ParseError::span`]
+#[derive(Debug, Clone, PartialEq, ThisError)]
+pub enum ParseError {
+    #[error(transparent)]
+    Lex(#[from] LexError),
+    /// `found` is the source of the token found
+    #[error("expected {}, got '{found}' instead", one_of(expected))]
+    UnexpectedToken {
+        expected: Vec<TokenType>,
+        found: String,
+        span: Span,
+    },
+    /// `span` is the last token of the statement
+    #[error("expected ';' at the end of the statement")]
+    MissingSemicolon { span: Span },
+    #[error("unexpected end of input, expected {expected}")]
+    UnexpectedEof { expected: String, span: Span },
+    #[error("expected an expression, got '{found}' instead")]
+    ExpectedExpression { found: String, span: Span },
+    #[error("could not parse '{literal}' as integer")]
+    InvalidInteger { literal: String, span: Span },
+    #[error("could not parse '{literal}' as float")]
+    InvalidFloat { literal: String, span: Span },
+    /// `found` is the source of the target, as printed
+    #[error("cannot assign to '{found}'")]
+    InvalidAssignTarget { found: String, span: Span },
+    /// `first` is where the parameter was first declared
+    #[error("duplicate parameter name '{name}'")]
+    DuplicateParameter {
+        name: String,
+        span: Span,
+        first: Span,
+    },
+    /// `open` is the opening brace of the block
+    #[error("unexpected end of input, the block is never closed")]
+    UnclosedBlock { open: Span, span: Span },
+    /// `keyword` is `break` or `continue`
+    #[error("'{keyword}' outside of a loop")]
+    OutsideLoop { keyword: TokenType, span: Span },
+    /// `span` is the value, `enclosing` the keyword of the innermost loop
+    #[error("only 'loop' can break with a value")]
+    BreakWithValue { span: Span, enclosing: Span },
+    #[error("expected a pattern, got '{found}' instead")]
+    ExpectedPattern { found: String, span: Span },
+    /// `first` is where the field was first given
+    #[error("duplicate field '{name}'")]
+    DuplicateField {
+        name: String,
+        span: Span,
+        first: Span,
+    },
+    /// `first` is where the variant was first declared
+    #[error("duplicate variant '{name}'")]
+    DuplicateVariant {
+        name: String,
+        span: Span,
+        first: Span,
+    },
+    /// `variants` lists the variants the enum declares
+    #[error("enum '{enum_name}' has no variant '{variant}'")]
+    UnknownVariant {
+        enum_name: String,
+        variant: String,
+        variants: Vec<String>,
+        span: Span,
+    },
+    #[error("inclusive range without an end")]
+    InclusiveRangeWithoutEnd { span: Span },
+    /// `declaration` is the variant as declared, e.g. `Rect(w, h)`
+    #[error("'{path}' takes {expected} field(s) but {found} were given")]
+    VariantArity {
+        path: String,
+        expected: usize,
+        found: usize,
+        declaration: String,
+        span: Span,
+    },
+    #[error("refutable pattern in let statement")]
+    RefutablePattern { span: Span },
+    #[error("'...' outside of an array pattern")]
+    MisplacedRest { span: Span },
+    /// `first` is the rest already in the array pattern
+    #[error("more than one rest in an array pattern")]
+    MultipleRests { span: Span, first: Span },
+    /// `first` is where the name was first bound
+    #[error("'{name}' is bound more than once in the same pattern")]
+    DuplicateBinding {
+        name: String,
+        span: Span,
+        first: Span,
+    },
+    /// `value` is the literal the pattern can't match
+    #[error("pattern can't match the value, {reason}")]
+    PatternMismatch {
+        reason: String,
+        span: Span,
+        value: Span,
+    },
+    #[error("expected a type, got '{found}' instead")]
+    ExpectedType { found: String, span: Span },
+    #[error("'{keyword}' is only allowed at the top level")]
+    NotTopLevel { keyword: TokenType, span: Span },
+    #[error("expected a declaration after 'export', got '{found}' instead")]
+    ExpectedDeclaration { found: String, span: Span },
+    #[error("const initializer is not a constant expression")]
+    NonConstant { span: Span },
+    /// `span` is the operator that can't be evaluated
+    #[error("can't evaluate the constant, {reason}")]
+    ConstEvaluation { reason: String, span: Span },
+    /// `declaration` is the name in the `const` declaration
+    #[error("cannot assign to constant '{name}'")]
+    AssignToConst {
+        name: String,
+        span: Span,
+        declaration: Span,
+    },
+    /// `first` is the name of the constant declared first
+    #[error("constant '{name}' is declared more than once in the same scope")]
+    DuplicateConst {
+        name: String,
+        span: Span,
+        first: Span,
+    },
+    #[error("the rest parameter must be the last one")]
+    RestNotLast { span: Span },
+    /// `default` is the first parameter with a default value
+    #[error("parameter '{name}' has no default but follows one that has")]
+    RequiredAfterDefault {
+        name: String,
+        span: Span,
+        default: Span,
+    },
+    /// `named` is the first named argument
+    #[error("positional argument after a named one")]
+    PositionalAfterNamed { span: Span, named: Span },
+    /// `first` is the argument given first
+    #[error("argument '{name}' is given more than once")]
+    ArgumentGivenTwice {
+        name: String,
+        span: Span,
+        first: Span,
+    },
+    /// `span` is the callee, `declaration` the name of the function
+    #[error("missing argument(s) {} in the call to '{callee}'", all_of(missing))]
+    MissingArguments {
+        callee: String,
+        missing: Vec<String>,
+        span: Span,
+        declaration: Span,
+    },
+    /// `span` is the first extra argument, `params` lists the parameters
+    #[error("'{callee}' takes {} argument(s) but {found} were given", params.len())]
+    TooManyArguments {
+        callee: String,
+        params: Vec<String>,
+        found: usize,
+        span: Span,
+        declaration: Span,
+    },
+    #[error("'{callee}' has no parameter named '{name}'")]
+    UnknownParameter {
+        callee: String,
+        name: String,
+        params: Vec<String>,
+        span: Span,
+    },
+}
+
+impl ParseError {
+    /// Build the error for finding `found`, whose source is `literal`, where
+    /// one of `expected` should be
+    pub fn unexpected(expected: Vec<TokenType>, found: &Token, literal: &str) -> Self {
+        if found.token_type == TokenType::EOF {
+            Self::UnexpectedEof {
+                expected: one_of(&expected),
+                span: found.span(),
+            }
+        } else {
+            Self::UnexpectedToken {
+                expected,
+                found: literal.to_string(),
+                span: found.span(),
+            }
+        }
+    }
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Lex(error) => error.code(),
+            Self::UnexpectedToken { .. } => "E0100",
+            Self::MissingSemicolon { .. } => "E0101",
+            Self::UnexpectedEof { .. } => "E0102",
+            Self::ExpectedExpression { .. } => "E0103",
+            Self::InvalidInteger { .. } => "E0104",
+            Self::InvalidFloat { .. } => "E0105",
+            Self::InvalidAssignTarget { .. } => "E0106",
+            Self::DuplicateParameter { .. } => "E0107",
+            Self::UnclosedBlock { .. } => "E0108",
+            Self::OutsideLoop { .. } => "E0109",
+            Self::BreakWithValue { .. } => "E0110",
+            Self::ExpectedPattern { .. } => "E0111",
+            Self::DuplicateField { .. } => "E0112",
+            Self::DuplicateVariant { .. } => "E0113",
+            Self::UnknownVariant { .. } => "E0114",
+            Self::VariantArity { .. } => "E0115",
+            Self::InclusiveRangeWithoutEnd { .. } => "E0116",
+            Self::RefutablePattern { .. } => "E0117",
+            Self::MisplacedRest { .. } => "E0118",
+            Self::MultipleRests { .. } => "E0119",
+            Self::DuplicateBinding { .. } => "E0120",
+            Self::PatternMismatch { .. } => "E0121",
+            Self::ExpectedType { .. } => "E0122",
+            Self::NotTopLevel { .. } => "E0123",
+            Self::ExpectedDeclaration { .. } => "E0124",
+            Self::NonConstant { .. } => "E0125",
+            Self::ConstEvaluation { .. } => "E0126",
+            Self::AssignToConst { .. } => "E0127",
+            Self::RestNotLast { .. } => "E0128",
+            Self::RequiredAfterDefault { .. } => "E0129",
+            Self::PositionalAfterNamed { .. } => "E0130",
+            Self::ArgumentGivenTwice { .. } => "E0131",
+            Self::MissingArguments { .. } => "E0132",
+            Self::TooManyArguments { .. } => "E0133",
+            Self::UnknownParameter { .. } => "E0134",
+            Self::DuplicateConst { .. } => "E0135",
+        }
+    }
+    /// Location of the offending token
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Lex(error) => error.span(),
+            Self::UnexpectedToken { span, .. }
+            | Self::MissingSemicolon { span }
+            | Self::UnexpectedEof { span, .. }
+            | Self::ExpectedExpression { span, .. }
+            | Self::InvalidInteger { span, .. }
+            | Self::InvalidFloat { span, .. }
+            | Self::InvalidAssignTarget { span, .. }
+            | Self::DuplicateParameter { span, .. }
+            | Self::UnclosedBlock { span, .. }
+            | Self::OutsideLoop { span, .. }
+            | Self::BreakWithValue { span, .. }
+            | Self::ExpectedPattern { span, .. }
+            | Self::DuplicateField { span, .. }
+            | Self::DuplicateVariant { span, .. }
+            | Self::UnknownVariant { span, .. }
+            | Self::VariantArity { span, .. }
+            | Self::InclusiveRangeWithoutEnd { span }
+            | Self::RefutablePattern { span }
+            | Self::MisplacedRest { span }
+            | Self::MultipleRests { span, .. }
+            | Self::DuplicateBinding { span, .. }
+            | Self::PatternMismatch { span, .. }
+            | Self::ExpectedType { span, .. }
+            | Self::NotTopLevel { span, .. }
+            | Self::ExpectedDeclaration { span, .. }
+            | Self::NonConstant { span }
+            | Self::ConstEvaluation { span, .. }
+            | Self::AssignToConst { span, .. }
+            | Self::RestNotLast { span }
+            | Self::RequiredAfterDefault { span, .. }
+            | Self::PositionalAfterNamed { span, .. }
+            | Self::ArgumentGivenTwice { span, .. }
+            | Self::MissingArguments { span, .. }
+            | Self::TooManyArguments { span, .. }
+            | Self::UnknownParameter { span, .. }
+            | Self::DuplicateConst { span, .. } => *span,
+        }
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Self {
+        let diagnostic = Diagnostic::error(error.to_string()).with_code(error.code());
+        match error {
+            ParseError::Lex(error) => Diagnostic::from(error),
+            ParseError::UnexpectedToken { expected, span, .. } => {
+                diagnostic.with_label(*span, format!("expected {}", one_of(expected)))
+            }
+            ParseError::MissingSemicolon { span } => {
+                let end = Span {
+                    start: span.end,
+                    ..*span
+                };
+                diagnostic.with_label(end, "add ';' here").with_suggestion(
+                    end,
+                    ";",
+                    "add a semicolon",
+                )
+            }
+            ParseError::UnexpectedEof { span, .. } => {
+                diagnostic.with_label(*span, "the input ends here")
+            }
+            ParseError::ExpectedExpression { span, .. } => {
+                diagnostic.with_label(*span, "expected an expression")
+            }
+            ParseError::InvalidInteger { span, .. } => diagnostic
+                .with_label(*span, "integer too large")
+                .with_note(format!("integers range from {} to {}", i64::MIN, i64::MAX)),
+            ParseError::InvalidFloat { span, .. } => {
+                diagnostic.with_label(*span, "invalid float literal")
+            }
+            ParseError::InvalidAssignTarget { span, .. } => diagnostic
+                .with_label(*span, "cannot be assigned to")
+                .with_note("only variables, indexed elements and fields can be assigned"),
+            ParseError::DuplicateParameter { name, span, first } => diagnostic
+                .with_label(*span, "used more than once")
+                .with_secondary(*first, format!("first use of '{}'", name))
+                .with_help("rename one of the parameters"),
+            ParseError::UnclosedBlock { open, span } => diagnostic
+                .with_label(*span, "expected '}'")
+                .with_secondary(*open, "block opened here")
+                .with_suggestion(*span, "}", "close the block"),
+            ParseError::OutsideLoop { span, .. } => diagnostic
+                .with_label(*span, "not inside a loop")
+                .with_note("loops don't extend into the functions defined in them"),
+            ParseError::BreakWithValue { span, enclosing } => diagnostic
+                .with_label(*span, "value given here")
+                .with_secondary(*enclosing, "this loop has no value")
+                .with_help("use 'loop' to break out with a value"),
+            ParseError::ExpectedPattern { span, .. } => diagnostic
+                .with_label(*span, "expected a pattern")
+                .with_note(
+                    "patterns are literals, names, '_', arrays, structs and enum variants of patterns",
+                ),
+            ParseError::DuplicateField { name, span, first } => diagnostic
+                .with_label(*span, "given more than once")
+                .with_secondary(*first, format!("first use of '{}'", name)),
+            ParseError::DuplicateVariant { name, span, first } => diagnostic
+                .with_label(*span, "declared more than once")
+                .with_secondary(*first, format!("first declaration of '{}'", name)),
+            ParseError::UnknownVariant { variants, span, .. } => diagnostic
+                .with_label(*span, "unknown variant")
+                .with_note(format!("the variants are {}", variants.join(", "))),
+            ParseError::VariantArity {
+                expected,
+                declaration,
+                span,
+                ..
+            } => diagnostic
+                .with_label(*span, format!("expected {} field(s)", expected))
+                .with_note(format!("the variant is declared as {}", declaration)),
+            ParseError::InclusiveRangeWithoutEnd { span } => diagnostic
+                .with_label(*span, "expected an end after '..='")
+                .with_suggestion(*span, "..", "use '..' for a range without an end"),
+            ParseError::RefutablePattern { span } => diagnostic
+                .with_label(*span, "may not match")
+                .with_note("a let pattern must match every value, use 'match' to test it"),
+            ParseError::MisplacedRest { span } => diagnostic
+                .with_label(*span, "not in an array pattern")
+                .with_note("'...' binds the elements an array pattern leaves, as in [first, ...rest]"),
+            ParseError::MultipleRests { span, first } => diagnostic
+                .with_label(*span, "second rest")
+                .with_secondary(*first, "first rest"),
+            ParseError::DuplicateBinding { name, span, first } => diagnostic
+                .with_label(*span, "bound again")
+                .with_secondary(*first, format!("first binding of '{}'", name)),
+            ParseError::PatternMismatch { span, value, .. } => diagnostic
+                .with_label(*span, "this pattern")
+                .with_secondary(*value, "can't match this value"),
+            ParseError::ExpectedType { span, .. } => diagnostic
+                .with_label(*span, "expected a type")
+                .with_note("types are names, with their arguments in angle brackets as in array<int>"),
+            ParseError::NotTopLevel { span, .. } => diagnostic
+                .with_label(*span, "inside a block")
+                .with_help("move it to the top level of the file"),
+            ParseError::ExpectedDeclaration { span, .. } => diagnostic
+                .with_label(*span, "expected a declaration")
+                .with_note("'let', 'const', 'struct' and 'enum' declarations can be exported"),
+            ParseError::NonConstant { span } => diagnostic
+                .with_label(*span, "not constant")
+                .with_note("constants are literals, other constants and operators applied to them")
+                .with_help("use 'let' for a value computed when the program runs"),
+            ParseError::ConstEvaluation { span, .. } => {
+                diagnostic.with_label(*span, "evaluated here")
+            }
+            ParseError::AssignToConst {
+                name,
+                span,
+                declaration,
+            } => diagnostic
+                .with_label(*span, "cannot be assigned to")
+                .with_secondary(*declaration, format!("'{}' declared as a constant here", name))
+                .with_help("use 'let mut' for a variable that changes"),
+            ParseError::DuplicateConst { name, span, first } => diagnostic
+                .with_label(*span, "declared again")
+                .with_secondary(*first, format!("first declaration of '{}'", name))
+                .with_help("use a different name, or a 'let' to shadow the constant"),
+            ParseError::RestNotLast { span } => diagnostic
+                .with_label(*span, "followed by other parameters")
+                .with_help("move it to the end of the parameters"),
+            ParseError::RequiredAfterDefault { span, default, .. } => diagnostic
+                .with_label(*span, "needs a default")
+                .with_secondary(*default, "first parameter with a default"),
+            ParseError::PositionalAfterNamed { span, named } => diagnostic
+                .with_label(*span, "positional argument")
+                .with_secondary(*named, "first named argument")
+                .with_help("pass the positional arguments first"),
+            ParseError::ArgumentGivenTwice { name, span, first } => diagnostic
+                .with_label(*span, "given again")
+                .with_secondary(*first, format!("'{}' given here", name)),
+            ParseError::MissingArguments {
+                callee,
+                missing,
+                span,
+                declaration,
+            } => diagnostic
+                .with_label(*span, format!("expected {}", all_of(missing)))
+                .with_secondary(*declaration, format!("'{}' declared here", callee)),
+            ParseError::TooManyArguments {
+                callee,
+                params,
+                span,
+                declaration,
+                ..
+            } => diagnostic
+                .with_label(*span, "extra argument")
+                .with_secondary(*declaration, format!("'{}' declared here", callee))
+                .with_note(parameters(params)),
+            ParseError::UnknownParameter { params, span, .. } => diagnostic
+                .with_label(*span, "unknown parameter")
+                .with_note(parameters(params)),
+        }
+    }
+}
+
+/// A suspicious construct that still parses
+#[derive(Debug, Clone, PartialEq, ThisError)]
+pub enum ParseWarning {
+    /// `first` is where the key was first used
+    #[error("duplicate key {key} in hash literal")]
+    DuplicateKey {
+        key: String,
+        span: Span,
+        first: Span,
+    },
+    /// `covered` is the pattern of the arm matching the values first
+    #[error("unreachable match arm")]
+    UnreachableArm { span: Span, covered: Span },
+    /// `missing` lists the boolean values or enum variants no arm matches
+    #[error("non-exhaustive match, {missing} not covered")]
+    NonExhaustiveMatch { missing: String, span: Span },
+    /// `range` is the range as written, `span` its operator
+    #[error("range {range} is empty, {reason}")]
+    EmptyRange {
+        range: String,
+        reason: &'static str,
+        span: Span,
+    },
+    /// Slice of an array or string literal of `length` elements, `bound` is
+    /// `start` or `end`
+    #[error("slice {bound} {index} is out of bounds for a length of {length}")]
+    SliceOutOfBounds {
+        bound: &'static str,
+        index: i64,
+        length: usize,
+        span: Span,
+    },
+}
+
+impl ParseWarning {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::DuplicateKey { .. } => "W0100",
+            Self::UnreachableArm { .. } => "W0101",
+            Self::NonExhaustiveMatch { .. } => "W0102",
+            Self::EmptyRange { .. } => "W0103",
+            Self::SliceOutOfBounds { .. } => "W0104",
+        }
+    }
+    /// Location of the offending token
+    pub fn span(&self) -> Span {
+        match self {
+            Self::DuplicateKey { span, .. }
+            | Self::UnreachableArm { span, .. }
+            | Self::NonExhaustiveMatch { span, .. }
+            | Self::EmptyRange { span, .. }
+            | Self::SliceOutOfBounds { span, .. } => *span,
+        }
+    }
+}
+
+impl From<&ParseWarning> for Diagnostic {
+    fn from(warning: &ParseWarning) -> Self {
+        let diagnostic = Diagnostic::warning(warning.to_string()).with_code(warning.code());
+        match warning {
+            ParseWarning::DuplicateKey { span, first, .. } => diagnostic
+                .with_label(*span, "key given again")
+                .with_secondary(*first, "first given here")
+                .with_help("remove one of the entries"),
+            ParseWarning::UnreachableArm { span, covered } => diagnostic
+                .with_label(*span, "never matched")
+                .with_secondary(*covered, "the values are matched here first"),
+            ParseWarning::NonExhaustiveMatch { missing, span } => diagnostic
+                .with_label(*span, format!("{} not covered", missing))
+                .with_help("add an arm for the missing values or a '_' arm"),
+            ParseWarning::EmptyRange { span, .. } => diagnostic
+                .with_label(*span, "contains no value")
+                .with_help("ranges count up from their start, and '..' excludes its end"),
+            ParseWarning::SliceOutOfBounds {
+                bound: "start",
+                span,
+                ..
+            } => diagnostic.with_label(*span, "starts past the last element"),
+            ParseWarning::SliceOutOfBounds { span, .. } => diagnostic
+                .with_label(*span, "ends past the last element")
+                .with_note("'..' excludes its end, '..=' includes it"),
+        }
+    }
+}
+
+/// Quote the names and join them, as in `'x', 'y' and 'z'`
+fn all_of(names: &[String]) -> String {
+    let quoted: Vec<String> = names.iter().map(|name| format!("'{}'", name)).collect();
+    match quoted.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} and {}", rest.join(", "), last),
+        None => String::from("nothing"),
+    }
+}
+
+fn parameters(params: &[String]) -> String {
+    if params.is_empty() {
+        String::from("the function has no parameters")
+    } else {
+        format!("the parameters are {}", params.join(", "))
+    }
+}
+
+/// Format a list of token types as `'a'`, `'a' or 'b'`, `'a', 'b' or 'c'`,
+/// the kinds of tokens with a source of their own are described in words
+fn one_of(token_types: &[TokenType]) -> String {
+    let quoted: Vec<String> = token_types.iter().map(describe).collect();
+    match quoted.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => String::from("nothing"),
+    }
+}
+
+fn describe(token_type: &TokenType) -> String {
+    match token_type {
+        TokenType::Ident => String::from("an identifier"),
+        TokenType::Int => String::from("an integer"),
+        TokenType::Float => String::from("a float"),
+        TokenType::String => String::from("a string"),
+        TokenType::EOF => String::from("the end of input"),
+        TokenType::Illegal => String::from("an illegal token"),
+        token_type => format!("'{}'", token_type),
+    }
+}
@@ -1,3 +1,6 @@
+use crate::ast::expand::Expander;
+use crate::ast::Program;
+use crate::diagnostic::{self, Diagnostic};
 use crate::lexer::Lexer;
 use crate::parser;
 use std::io::{self, BufRead, BufReader, Stdin, Write};
@@ -39,15 +42,50 @@ impl FromStr for Commands {
     }
 }
 
+/// What the REPL keeps from one input to the next
+#[derive(Debug, Default)]
+struct Session {
+    /// Every input so far, the spans of the nodes are offsets into it
+    source: String,
+    /// Arena of the nodes of every input, the macros declared point into it
+    program: Program,
+    expander: Expander,
+}
+
+impl Session {
+    /// Parse and expand `input`, returns its diagnostics and the expanded code
+    fn eval(&mut self, input: &str) -> (Vec<Diagnostic>, String) {
+        let start = self.source.len();
+        self.source.push_str(input);
+        let lexer = Lexer::new_at(&self.source, start);
+        let mut parser =
+            parser::Parser::new(lexer, "input").with_program(std::mem::take(&mut self.program));
+        let mut p = parser.parse();
+        self.expander.expand(&mut p);
+        let diagnostics = parser
+            .warnings()
+            .iter()
+            .map(Diagnostic::from)
+            .chain(parser.errors().iter().map(Diagnostic::from))
+            .chain(self.expander.errors().iter().map(Diagnostic::from))
+            .collect();
+        let code = p.to_string();
+        self.program = p;
+        (diagnostics, code)
+    }
+}
+
 #[derive(Debug)]
 pub struct Repl {
     reader: BufReader<Stdin>,
+    session: Session,
 }
 
 impl Default for Repl {
     fn default() -> Self {
         Self {
             reader: BufReader::new(std::io::stdin()),
+            session: Session::default(),
         }
     }
 }
@@ -78,13 +116,43 @@ impl Repl {
                 continue;
             }
 
-            let lexer = Lexer::new(&input);
-            let mut parser = parser::Parser::new(lexer, "input");
-            let p = parser.parse();
-            for error in parser.errors() {
-                println!("parser error: {}", error);
+            let (diagnostics, code) = self.session.eval(&input);
+            let color = diagnostic::use_color();
+            for diagnostic in diagnostics {
+                eprint!(
+                    "{}",
+                    diagnostic.render("input", &self.session.source, color)
+                );
             }
-            println!("{:#?}", p.statements);
+            println!("{}", code);
         }
     }
 }
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn macros_are_kept_between_inputs() {
+        let mut session = Session::default();
+
+        let (diagnostics, code) = session.eval("let twice = macro(x) { quote(unquote(x) * 2) };\n");
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert_eq!(code, "");
+
+        let (diagnostics, code) = session.eval("twice(1 + 2);\n");
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert_eq!(code, "(1 + 2) * 2;");
+
+        // The spans are offsets into every input so far
+        let (diagnostics, code) = session.eval("twice();\n");
+        assert_eq!(code, "twice();");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, Some("E0308"));
+        assert_eq!(diagnostics[0].span().map(|span| span.line), Some(3));
+        let rendered = diagnostics[0].render("input", &session.source, false);
+        assert!(rendered.contains("input:3:1"), "{}", rendered);
+        assert!(rendered.contains("let twice = macro(x)"), "{}", rendered);
+    }
+}
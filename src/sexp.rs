@@ -0,0 +1,80 @@
+use std::fmt::{Display, Write};
+
+/// An S-expression
+///
+/// `{}` prints it on one line, `{:#}` puts every nested list on its own
+/// indented line
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sexp {
+    Atom(String),
+    /// Printed quoted and escaped
+    String(String),
+    List(Vec<Sexp>),
+}
+
+impl Sexp {
+    pub fn atom(value: impl Display) -> Self {
+        Self::Atom(value.to_string())
+    }
+
+    fn write(&self, f: &mut std::fmt::Formatter<'_>, indent: usize) -> std::fmt::Result {
+        match self {
+            Self::Atom(value) => f.write_str(value),
+            Self::String(value) => write!(f, "{:?}", value),
+            Self::List(items) => {
+                f.write_char('(')?;
+                for (i, item) in items.iter().enumerate() {
+                    if f.alternate() && matches!(item, Self::List(_)) {
+                        f.write_char('\n')?;
+                        for _ in 0..=indent {
+                            f.write_str("  ")?;
+                        }
+                    } else if i > 0 {
+                        f.write_char(' ')?;
+                    }
+                    item.write(f, indent + 1)?;
+                }
+                f.write_char(')')
+            }
+        }
+    }
+}
+
+impl From<&str> for Sexp {
+    fn from(value: &str) -> Self {
+        Self::Atom(value.to_string())
+    }
+}
+
+impl From<Vec<Sexp>> for Sexp {
+    fn from(items: Vec<Sexp>) -> Self {
+        Self::List(items)
+    }
+}
+
+impl Display for Sexp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn display_compact_and_pretty() {
+        let sexp = Sexp::List(vec![
+            "let".into(),
+            Sexp::String("a \"b\"".to_string()),
+            Sexp::List(vec!["+".into(), "1".into(), "2".into()]),
+            Sexp::List(vec![]),
+        ]);
+
+        assert_eq!(sexp.to_string(), r#"(let "a \"b\"" (+ 1 2) ())"#);
+        assert_eq!(
+            format!("{:#}", sexp),
+            "(let \"a \\\"b\\\"\"\n  (+ 1 2)\n  ())"
+        );
+    }
+}
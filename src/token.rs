@@ -1,5 +1,29 @@
+use crate::json::Json;
 use std::fmt::Display;
 
+/// Location of a token in the source
+///
+/// `start` and `end` are byte offsets, `line` and `column` where the token
+/// begins, both starting at 1
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl From<Span> for Json {
+    fn from(span: Span) -> Self {
+        Json::object([
+            ("start", span.start.into()),
+            ("end", span.end.into()),
+            ("line", span.line.into()),
+            ("column", span.column.into()),
+        ])
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Token {
     pub token_type: TokenType,
@@ -24,17 +48,41 @@ impl Token {
             column,
         }
     }
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.start,
+            end: self.end,
+            line: self.line,
+            column: self.column,
+        }
+    }
     /// Lookup an identifier and return the corresponding token type
     pub fn lookup_ident(ident: &str, start: usize, end: usize, line: usize, column: usize) -> Self {
         let token_type = match ident {
             "fn" => TokenType::Fn,
+            "macro" => TokenType::Macro,
             "let" => TokenType::Let,
+            "const" => TokenType::Const,
             "mut" => TokenType::Mut,
             "true" => TokenType::True,
             "false" => TokenType::False,
             "if" => TokenType::If,
             "else" => TokenType::Else,
             "return" => TokenType::Return,
+            "while" => TokenType::While,
+            "for" => TokenType::For,
+            "in" => TokenType::In,
+            "loop" => TokenType::Loop,
+            "break" => TokenType::Break,
+            "continue" => TokenType::Continue,
+            "match" => TokenType::Match,
+            "struct" => TokenType::Struct,
+            "enum" => TokenType::Enum,
+            "import" => TokenType::Import,
+            "export" => TokenType::Export,
+            "as" => TokenType::As,
+            "from" => TokenType::From,
+            "_" => TokenType::Underscore,
             _ => TokenType::Ident,
         };
         Self::new(token_type, start, end, line, column)
@@ -48,6 +96,7 @@ pub enum TokenType {
     Ident,
     Int,
     Float,
+    String,
     // Operators
     Assign,
     Plus,
@@ -59,24 +108,80 @@ pub enum TokenType {
     GT,
     EQ,
     NotEQ,
+    FatArrow,
+    Arrow,
+    Pipe,
+    DotDot,
+    DotDotEq,
     // Delimiters
     Comma,
     Semicolon,
+    Colon,
+    ColonColon,
+    Underscore,
+    Dot,
+    DotDotDot,
+    Bar,
 
     LParen,
     RParen,
     LBrace,
     RBrace,
+    LBracket,
+    RBracket,
 
     // Keywords
     Fn,
+    Macro,
     Let,
+    Const,
     Mut,
     True,
     False,
     If,
     Else,
     Return,
+    While,
+    For,
+    In,
+    Loop,
+    Break,
+    Continue,
+    Match,
+    Struct,
+    Enum,
+    Import,
+    Export,
+    As,
+    From,
+}
+
+impl From<&Token> for Json {
+    fn from(token: &Token) -> Self {
+        Json::object([
+            ("type", format!("{:?}", token.token_type).into()),
+            ("span", token.span().into()),
+        ])
+    }
+}
+
+impl TokenType {
+    /// Keywords that can only begin a statement, the parser resumes at
+    /// them after an error
+    pub fn is_statement_keyword(&self) -> bool {
+        matches!(
+            self,
+            Self::Let
+                | Self::Const
+                | Self::Return
+                | Self::Break
+                | Self::Continue
+                | Self::Struct
+                | Self::Enum
+                | Self::Import
+                | Self::Export
+        )
+    }
 }
 
 impl Display for TokenType {
@@ -88,6 +193,7 @@ impl Display for TokenType {
             Self::Ident => "Ident",
             Self::Int => "Int",
             Self::Float => "Float",
+            Self::String => "String",
             // Operators
             Self::Assign => "=",
             Self::Plus => "+",
@@ -99,22 +205,50 @@ impl Display for TokenType {
             Self::GT => ">",
             Self::EQ => "==",
             Self::NotEQ => "!=",
+            Self::FatArrow => "=>",
+            Self::Arrow => "->",
             // Delimiters
             Self::Comma => ",",
             Self::Semicolon => ";",
+            Self::Colon => ":",
+            Self::ColonColon => "::",
+            Self::Underscore => "_",
+            Self::Dot => ".",
+            Self::Bar => "|",
+            Self::Pipe => "|>",
+            Self::DotDot => "..",
+            Self::DotDotEq => "..=",
+            Self::DotDotDot => "...",
             Self::LParen => "(",
             Self::RParen => ")",
             Self::LBrace => "{",
             Self::RBrace => "}",
+            Self::LBracket => "[",
+            Self::RBracket => "]",
             // Keywords
             Self::Fn => "fn",
+            Self::Macro => "macro",
             Self::Let => "let",
+            Self::Const => "const",
             Self::Mut => "mut",
             Self::True => "true",
             Self::False => "false",
             Self::If => "if",
             Self::Else => "else",
             Self::Return => "return",
+            Self::While => "while",
+            Self::For => "for",
+            Self::In => "in",
+            Self::Loop => "loop",
+            Self::Break => "break",
+            Self::Continue => "continue",
+            Self::Match => "match",
+            Self::Struct => "struct",
+            Self::Enum => "enum",
+            Self::Import => "import",
+            Self::Export => "export",
+            Self::As => "as",
+            Self::From => "from",
         };
         write!(f, "{}", s)
     }